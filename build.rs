@@ -1,12 +1,23 @@
-use std::{env, fs::File, io, path::PathBuf, process::Command};
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::PathBuf,
+    process::Command,
+};
 
 // Downloaded from https://www.fontsquirrel.com/fonts/noto-serif
 const DOWNLOAD_LINK: &str = "https://www.fontsquirrel.com/fonts/download/noto-serif";
+// Downloaded from https://github.com/google/fonts/tree/main/ofl/notosansmath
+const MATH_DOWNLOAD_LINK: &str =
+    "https://github.com/google/fonts/raw/main/ofl/notosansmath/NotoSansMath-Regular.ttf";
 
 fn main() {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let font_zip_path = out_dir.join("noto-serif.zip");
     let font_dir_path = out_dir.join("noto-serif");
+    let math_font_dir_path = out_dir.join("noto-sans-math");
+    let math_font_path = math_font_dir_path.join("NotoSansMath-Regular.ttf");
 
     if !font_zip_path.exists() {
         let mut download = ureq::get(DOWNLOAD_LINK).call().unwrap().into_reader();
@@ -22,4 +33,11 @@ fn main() {
             .output()
             .unwrap();
     }
+
+    if !math_font_path.exists() {
+        fs::create_dir_all(&math_font_dir_path).unwrap();
+        let mut download = ureq::get(MATH_DOWNLOAD_LINK).call().unwrap().into_reader();
+        let mut file = File::create(&math_font_path).unwrap();
+        io::copy(&mut download, &mut file).unwrap();
+    }
 }
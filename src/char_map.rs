@@ -1,32 +1,90 @@
-use crate::document::{Block, Document, Inline};
+use ttf_parser::GlyphId;
+
+use crate::{
+    document::{Block, Document, Inline},
+    font::shape::{needs_shaping, Glyph},
+    text_layout::Page,
+};
 
 /// Defines a mapping from document-specific character numbers to Unicode code points.
 #[derive(Debug)]
 pub struct CharMap {
-    pub mappings: Vec<char>,
+    pub mappings: Vec<Mapping>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mapping {
+    Char(char),
+    /// A glyph chosen by the shaper, which only exists in one font and can stand for several
+    /// characters, or none.
+    Glyph {
+        font: String,
+        id: GlyphId,
+        text: String,
+    },
 }
 
 impl CharMap {
     pub fn from_document(document: &Document) -> Self {
         let mut char_map = Self {
-            mappings: vec!['\0'],
+            mappings: vec![Mapping::Char('\0')],
         };
         char_map.extend(&document.blocks);
         char_map
     }
 
+    fn push(&mut self, mapping: Mapping) {
+        if !self.mappings.contains(&mapping) {
+            // Each font is subset to one glyph per code, and glyph IDs are 16 bits.
+            assert!(self.mappings.len() < u16::MAX as usize, "CharMap is full");
+            self.mappings.push(mapping);
+        }
+    }
+
+    /// Whether the codes take two bytes, because there are more of them than fit in one.
+    pub fn is_wide(&self) -> bool {
+        self.mappings.len() > 1 << u8::BITS
+    }
+
     pub fn insert(&mut self, c: char) {
-        if !self.mappings.contains(&c) {
-            // This is a temporary restriction to avoid having to deal with
-            // multi-byte characters.
-            // TODO: Remove this restriction
-            assert!(self.mappings.len() <= u8::MAX as usize, "CharMap is full");
-            self.mappings.push(c);
+        self.push(Mapping::Char(c));
+    }
+
+    pub fn insert_glyph(&mut self, font: &str, glyph: &Glyph) {
+        self.push(Mapping::glyph(font, glyph));
+    }
+
+    pub fn get(&self, c: char) -> Option<u16> {
+        self.position(&Mapping::Char(c))
+    }
+
+    pub fn get_glyph(&self, font: &str, glyph: &Glyph) -> Option<u16> {
+        self.position(&Mapping::glyph(font, glyph))
+    }
+
+    fn position(&self, mapping: &Mapping) -> Option<u16> {
+        self.mappings
+            .iter()
+            .position(|x| x == mapping)
+            .map(|x| x as u16)
+    }
+}
+
+impl Mapping {
+    fn glyph(font: &str, glyph: &Glyph) -> Self {
+        Self::Glyph {
+            font: font.to_owned(),
+            id: glyph.id,
+            text: glyph.text.clone(),
         }
     }
 
-    pub fn get(&self, c: char) -> Option<u8> {
-        self.mappings.iter().position(|&x| x == c).map(|x| x as u8)
+    /// The text that the code stands for when it is copied out of the PDF.
+    pub fn text(&self) -> String {
+        match self {
+            Self::Char(c) => c.to_string(),
+            Self::Glyph { text, .. } => text.clone(),
+        }
     }
 }
 
@@ -37,6 +95,24 @@ impl<'a> Extend<&'a Block<'a>> for CharMap {
                 Block::Text(text_block) => {
                     self.extend(&text_block.inlines);
                 }
+                Block::Table(table) => {
+                    for cell in table.rows.iter().flatten() {
+                        self.extend(&cell.inlines);
+                    }
+                }
+                Block::List(list) => {
+                    for blocks in &list.items {
+                        self.extend(blocks);
+                    }
+                }
+                Block::Heading(heading) => {
+                    self.extend(&heading.block.inlines);
+                }
+                // The entries are added from the laid out pages.
+                Block::TableOfContents(_) | Block::Label(_) => {}
+                Block::Span(blocks) => {
+                    self.extend(blocks);
+                }
             }
         }
     }
@@ -45,7 +121,36 @@ impl<'a> Extend<&'a Block<'a>> for CharMap {
 impl<'a> Extend<&'a Inline<'a>> for CharMap {
     fn extend<T: IntoIterator<Item = &'a Inline<'a>>>(&mut self, iter: T) {
         for inline in iter {
-            self.extend(inline.text.chars());
+            match inline {
+                Inline::Text(inline) => {
+                    // Text that needs shaping is added as glyphs from the laid out pages.
+                    self.extend(inline.text.chars().filter(|&c| !needs_shaping(c)));
+                }
+                Inline::Footnote(footnote) => {
+                    self.extend(footnote.marker.chars());
+                    self.extend(&footnote.inlines);
+                }
+                // The text is added from the laid out pages.
+                Inline::Reference(_) => {}
+            }
+        }
+    }
+}
+
+/// Adds the text generated during layout, such as list markers and references.
+impl<'a> Extend<&'a Page<'a>> for CharMap {
+    fn extend<T: IntoIterator<Item = &'a Page<'a>>>(&mut self, iter: T) {
+        for page in iter {
+            for line in &page.lines {
+                for chunk in &line.chunks {
+                    if chunk.glyphs.is_empty() {
+                        self.extend(chunk.text.chars());
+                    }
+                    for glyph in &chunk.glyphs {
+                        self.insert_glyph(chunk.style.font, glyph);
+                    }
+                }
+            }
         }
     }
 }
@@ -1,18 +1,44 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, iter};
 
 use glam::Vec2;
 
 use crate::font::Font;
 
+/// The width of the stroke that synthetic bold draws around the glyphs, relative to the font size.
+const SYNTHETIC_BOLD_STROKE: f32 = 0.03;
+/// The horizontal skew of synthetic oblique text, which is the tangent of a 12 degree slant.
+const SYNTHETIC_OBLIQUE_SKEW: f32 = 0.21256;
+
 pub struct Document<'a> {
     pub blocks: Vec<Block<'a>>,
     pub fonts: BTreeMap<&'a str, &'a Font<'a>>,
     pub page_size: Vec2,
     pub margin: f32,
+    pub columns: Columns,
+}
+
+/// Describes how the content area of each page is split into columns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Columns {
+    pub count: usize,
+    /// The horizontal space between adjacent columns.
+    pub gap: f32,
+    /// Whether the columns on the last page should end at roughly the same height.
+    pub balance: bool,
 }
 
 pub enum Block<'a> {
     Text(TextBlock<'a>),
+    Table(Table<'a>),
+    List(List<'a>),
+    Heading(Heading<'a>),
+    /// A list of the document's headings with the pages they start on.
+    TableOfContents(TableOfContents<'a>),
+    /// Marks the position of the figure, table or equation that follows, so that it can be
+    /// referenced.
+    Label(Label<'a>),
+    /// Blocks that span every column, such as titles and wide figures.
+    Span(Vec<Block<'a>>),
 }
 
 pub struct TextBlock<'a> {
@@ -20,20 +46,176 @@ pub struct TextBlock<'a> {
     pub align: TextAlign,
 }
 
-pub struct Inline<'a> {
+pub struct Heading<'a> {
+    /// The depth of the section, starting from 1 for the top level.
+    pub level: usize,
+    pub block: TextBlock<'a>,
+    /// The name that references to this section use.
+    pub label: Option<&'a str>,
+}
+
+pub struct TableOfContents<'a> {
+    /// The style of the entries, which replaces the styles of the headings.
+    pub style: Style<'a>,
+    /// How far each heading level is indented from the level above it.
+    pub indent: f32,
+}
+
+pub struct Label<'a> {
+    pub name: &'a str,
+    pub kind: LabelKind,
+}
+
+/// What a label is attached to, which determines how it is numbered and referred to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelKind {
+    Figure,
+    Table,
+    Equation,
+}
+
+pub struct Table<'a> {
+    pub columns: Vec<ColumnWidth>,
+    /// The cells of each row, from left to right.
+    pub rows: Vec<Vec<TextBlock<'a>>>,
+    /// The number of rows at the top of the table that are repeated after a page break.
+    pub header_rows: usize,
+    pub padding: f32,
+    /// The width of the cell borders, or zero for no borders.
+    pub border_width: f32,
+}
+
+/// The width of a table column, including the cell padding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnWidth {
+    Fixed(f32),
+    /// A share of the width left over by the other columns, relative to the other proportional
+    /// columns.
+    Proportional(f32),
+    /// The width of the widest cell, shrunk if the table would be wider than the page.
+    Auto,
+}
+
+pub struct List<'a> {
+    pub marker: ListMarker,
+    /// The style of the markers.
+    pub style: Style<'a>,
+    /// The blocks in each item, which may include nested lists.
+    pub items: Vec<Vec<Block<'a>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListMarker {
+    Bullet,
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+pub enum Inline<'a> {
+    Text(TextInline<'a>),
+    /// A footnote that is marked in the text and placed at the bottom of the page.
+    Footnote(Footnote<'a>),
+    /// Text that refers to a label, which is filled in during layout.
+    Reference(Reference<'a>),
+}
+
+pub struct TextInline<'a> {
     pub text: &'a str,
     pub style: Style<'a>,
 }
 
+pub struct Footnote<'a> {
+    /// The text of the superscript marker, such as "1" or "*".
+    pub marker: &'a str,
+    /// The style of the surrounding text, which the marker is scaled down from.
+    pub style: Style<'a>,
+    pub inlines: Vec<Inline<'a>>,
+}
+
+pub struct Reference<'a> {
+    /// The name of the label being referred to.
+    pub label: &'a str,
+    pub kind: ReferenceKind,
+    pub style: Style<'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    /// Refers to the number of a section, figure, table or equation, such as "Section 3.2",
+    /// "Figure 4" or "(12)".
+    Number,
+    /// Refers to the page that the label is on, such as "page 17".
+    Page,
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Style<'a> {
     pub font: &'a str,
     pub font_size: f32,
+    /// The fonts to try in order for characters that `font` has no glyph for.
+    pub fallbacks: &'a [&'a str],
+    /// Emboldens the glyphs by stroking their outlines, for fonts without a bold face.
+    pub synthetic_bold: bool,
+    /// Slants the glyphs, for fonts without an italic face.
+    pub synthetic_oblique: bool,
 }
 
+#[derive(Default)]
 pub enum TextAlign {
+    /// Aligns to the left margin in left-to-right paragraphs, and the right margin in
+    /// right-to-left paragraphs.
+    #[default]
+    Start,
+    End,
     Left,
     Center,
     Right,
     Justify,
 }
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            gap: 0.0,
+            balance: false,
+        }
+    }
+}
+
+impl<'a> Style<'a> {
+    /// The main font followed by the fallback fonts.
+    pub fn font_chain(&self) -> impl Iterator<Item = &'a str> {
+        iter::once(self.font).chain(self.fallbacks.iter().copied())
+    }
+
+    /// The width of the stroke around synthetic bold glyphs, which widens each glyph by the same
+    /// amount, or zero for other text.
+    pub fn bold_stroke_width(&self) -> f32 {
+        if self.synthetic_bold {
+            SYNTHETIC_BOLD_STROKE * self.font_size
+        } else {
+            0.0
+        }
+    }
+
+    /// How far the glyphs lean to the right for each unit of height.
+    pub fn skew(&self) -> f32 {
+        if self.synthetic_oblique {
+            SYNTHETIC_OBLIQUE_SKEW
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Columns {
+    /// Calculates the width of a single column from the width of the page's content area.
+    pub fn width(&self, content_width: f32) -> f32 {
+        let count = self.count.max(1) as f32;
+        (content_width - (count - 1.0) * self.gap) / count
+    }
+}
@@ -0,0 +1,182 @@
+use std::ops::RangeInclusive;
+
+/// A style of letters and digits in equations, such as bold or double-struck, which Unicode
+/// encodes as separate characters in the Mathematical Alphanumeric Symbols block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    /// Upright letters, which are the plain characters.
+    Roman,
+    Bold,
+    Italic,
+    DoubleStruck,
+    Script,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+impl Alphabet {
+    /// The alphabet that a command such as `\mathbf` sets its argument in.
+    pub fn from_command(command: &str) -> Option<Self> {
+        match command {
+            "mathrm" => Some(Alphabet::Roman),
+            "mathbf" => Some(Alphabet::Bold),
+            "mathit" => Some(Alphabet::Italic),
+            "mathbb" => Some(Alphabet::DoubleStruck),
+            "mathcal" => Some(Alphabet::Script),
+            "mathfrak" => Some(Alphabet::Fraktur),
+            "mathsf" => Some(Alphabet::SansSerif),
+            "mathtt" => Some(Alphabet::Monospace),
+            _ => None,
+        }
+    }
+
+    /// The character in this alphabet, or `c` itself if the alphabet doesn't have it.
+    pub fn apply(self, c: char) -> char {
+        if let Some(c) = self.exception(c) {
+            return c;
+        }
+
+        self.ranges()
+            .into_iter()
+            .find_map(|(plain, start)| {
+                if !plain.contains(&c) {
+                    return None;
+                }
+                char::from_u32(start? + c as u32 - *plain.start() as u32)
+            })
+            .unwrap_or(c)
+    }
+
+    /// The plain character that `c` is in this alphabet, or `c` itself if it isn't in it.
+    pub fn remove(self, c: char) -> char {
+        if let Some(plain) = ('A'..='Z')
+            .chain('a'..='z')
+            .find(|&plain| self.exception(plain) == Some(c))
+        {
+            return plain;
+        }
+
+        self.ranges()
+            .into_iter()
+            .find_map(|(plain, start)| {
+                let offset = (c as u32).checked_sub(start?)?;
+                if offset > *plain.end() as u32 - *plain.start() as u32 {
+                    return None;
+                }
+                char::from_u32(*plain.start() as u32 + offset)
+            })
+            .unwrap_or(c)
+    }
+
+    /// The ranges of capital letters, small letters, digits, capital Greek letters and small Greek
+    /// letters, with where the alphabet's versions of them start, if it has them.
+    fn ranges(self) -> [(RangeInclusive<char>, Option<u32>); 5] {
+        let (upper, lower, digit, greek_upper, greek_lower) = match self {
+            Alphabet::Roman => (None, None, None, None, None),
+            Alphabet::Bold => (
+                Some(0x1D400),
+                Some(0x1D41A),
+                Some(0x1D7CE),
+                Some(0x1D6A8),
+                Some(0x1D6C2),
+            ),
+            Alphabet::Italic => (
+                Some(0x1D434),
+                Some(0x1D44E),
+                None,
+                Some(0x1D6E2),
+                Some(0x1D6FC),
+            ),
+            Alphabet::DoubleStruck => (Some(0x1D538), Some(0x1D552), Some(0x1D7D8), None, None),
+            Alphabet::Script => (Some(0x1D49C), Some(0x1D4B6), None, None, None),
+            Alphabet::Fraktur => (Some(0x1D504), Some(0x1D51E), None, None, None),
+            Alphabet::SansSerif => (Some(0x1D5A0), Some(0x1D5BA), Some(0x1D7E2), None, None),
+            Alphabet::Monospace => (Some(0x1D670), Some(0x1D68A), Some(0x1D7F6), None, None),
+        };
+        [
+            ('A'..='Z', upper),
+            ('a'..='z', lower),
+            ('0'..='9', digit),
+            ('Α'..='Ω', greek_upper),
+            ('α'..='ω', greek_lower),
+        ]
+    }
+
+    /// The letters that were encoded in the Letterlike Symbols block before the rest of their
+    /// alphabet, and which are left out of the Mathematical Alphanumeric Symbols block.
+    fn exception(self, c: char) -> Option<char> {
+        match (self, c) {
+            (Alphabet::Italic, 'h') => Some('ℎ'),
+            (Alphabet::DoubleStruck, 'C') => Some('ℂ'),
+            (Alphabet::DoubleStruck, 'H') => Some('ℍ'),
+            (Alphabet::DoubleStruck, 'N') => Some('ℕ'),
+            (Alphabet::DoubleStruck, 'P') => Some('ℙ'),
+            (Alphabet::DoubleStruck, 'Q') => Some('ℚ'),
+            (Alphabet::DoubleStruck, 'R') => Some('ℝ'),
+            (Alphabet::DoubleStruck, 'Z') => Some('ℤ'),
+            (Alphabet::Script, 'B') => Some('ℬ'),
+            (Alphabet::Script, 'E') => Some('ℰ'),
+            (Alphabet::Script, 'F') => Some('ℱ'),
+            (Alphabet::Script, 'H') => Some('ℋ'),
+            (Alphabet::Script, 'I') => Some('ℐ'),
+            (Alphabet::Script, 'L') => Some('ℒ'),
+            (Alphabet::Script, 'M') => Some('ℳ'),
+            (Alphabet::Script, 'R') => Some('ℛ'),
+            (Alphabet::Script, 'e') => Some('ℯ'),
+            (Alphabet::Script, 'g') => Some('ℊ'),
+            (Alphabet::Script, 'o') => Some('ℴ'),
+            (Alphabet::Fraktur, 'C') => Some('ℭ'),
+            (Alphabet::Fraktur, 'H') => Some('ℌ'),
+            (Alphabet::Fraktur, 'I') => Some('ℑ'),
+            (Alphabet::Fraktur, 'R') => Some('ℜ'),
+            (Alphabet::Fraktur, 'Z') => Some('ℨ'),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Alphabet;
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(Alphabet::Bold.apply('A'), '𝐀');
+        assert_eq!(Alphabet::Bold.apply('z'), '𝐳');
+        assert_eq!(Alphabet::Bold.apply('7'), '𝟕');
+        assert_eq!(Alphabet::Bold.apply('β'), '𝛃');
+        assert_eq!(Alphabet::Italic.apply('x'), '𝑥');
+        assert_eq!(Alphabet::Italic.apply('h'), 'ℎ');
+        assert_eq!(Alphabet::Italic.apply('Ω'), '𝛺');
+        assert_eq!(Alphabet::DoubleStruck.apply('R'), 'ℝ');
+        assert_eq!(Alphabet::DoubleStruck.apply('A'), '𝔸');
+        assert_eq!(Alphabet::DoubleStruck.apply('1'), '𝟙');
+        assert_eq!(Alphabet::Script.apply('L'), 'ℒ');
+        assert_eq!(Alphabet::Script.apply('A'), '𝒜');
+        assert_eq!(Alphabet::Fraktur.apply('g'), '𝔤');
+        assert_eq!(Alphabet::SansSerif.apply('a'), '𝖺');
+        assert_eq!(Alphabet::Monospace.apply('0'), '𝟶');
+
+        // Characters that the alphabet doesn't have are left alone.
+        assert_eq!(Alphabet::Roman.apply('x'), 'x');
+        assert_eq!(Alphabet::Script.apply('1'), '1');
+        assert_eq!(Alphabet::Fraktur.apply('α'), 'α');
+        assert_eq!(Alphabet::Bold.apply('+'), '+');
+    }
+
+    #[test]
+    fn test_remove() {
+        assert_eq!(Alphabet::Italic.remove('𝑥'), 'x');
+        assert_eq!(Alphabet::Italic.remove('ℎ'), 'h');
+        assert_eq!(Alphabet::Italic.remove('𝐴'), 'A');
+        assert_eq!(Alphabet::Italic.remove('𝛼'), 'α');
+        assert_eq!(Alphabet::DoubleStruck.remove('ℝ'), 'R');
+        assert_eq!(Alphabet::Bold.remove('𝟕'), '7');
+
+        // Characters from other alphabets are left alone.
+        assert_eq!(Alphabet::Italic.remove('x'), 'x');
+        assert_eq!(Alphabet::Italic.remove('𝐱'), '𝐱');
+        assert_eq!(Alphabet::Roman.remove('𝑥'), '𝑥');
+    }
+}
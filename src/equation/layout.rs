@@ -0,0 +1,1256 @@
+use glam::{vec2, Vec2};
+use thiserror::Error;
+use ttf_parser::{
+    math::{Constants, GlyphPart, MathValue},
+    GlyphId,
+};
+
+use crate::font::{math::Axis, Font};
+
+use super::{Class, ColumnAlign, Delimited, Delimiter, Fragment, Grid, Limits, Sequence};
+
+/// TeX's `\delimiterfactor`, the part of the content that a delimiter covers at least.
+const DELIMITER_FACTOR: f32 = 0.901;
+/// TeX's `\delimitershortfall`, how much shorter than the content a delimiter can be, in ems.
+const DELIMITER_SHORTFALL: f32 = 0.5;
+/// TeX's `\nulldelimiterspace`, the width of the null delimiter and the space around fractions,
+/// in ems.
+const NULL_DELIMITER_SPACE: f32 = 0.12;
+/// The height and depth that every row of a grid has at least, in ems, from LaTeX's `\strut` with
+/// a baseline skip of 1.2 em.
+const STRUT_HEIGHT: f32 = 0.84;
+const STRUT_DEPTH: f32 = 0.36;
+/// The space between adjacent atoms in math units, by the classes of the left and the right atom,
+/// from chapter 18 of The TeXbook. Negative entries are only used in display and text styles.
+const ATOM_SPACING: [[i8; 8]; 8] = [
+    [0, 3, -4, -5, 0, 0, 0, -3],
+    [3, 3, 0, -5, 0, 0, 0, -3],
+    [-4, -4, 0, 0, -4, 0, 0, -4],
+    [-5, -5, 0, 0, -5, 0, 0, -5],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 3, -4, -5, 0, 0, 0, -3],
+    [-3, -3, 0, -3, -3, -3, -3, -3],
+    [-3, 3, -4, -5, -3, 0, -3, -3],
+];
+/// How often the extenders of an assembly are repeated at most, so that huge sizes end.
+const MAX_EXTENDER_REPEATS: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("font has no MATH table")]
+    MissingMathTable,
+    #[error("missing glyph: '{0}'")]
+    MissingGlyph(char),
+}
+
+/// How large an equation is set, which decides the size of its scripts and fractions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MathStyle {
+    /// Equations on their own line.
+    Display,
+    /// Equations within a paragraph.
+    Text,
+    /// Scripts, and fractions in text equations.
+    Script,
+    /// Scripts of scripts.
+    ScriptScript,
+}
+
+impl MathStyle {
+    /// The style of scripts and limits.
+    fn script(self) -> Self {
+        match self {
+            MathStyle::Display | MathStyle::Text => MathStyle::Script,
+            MathStyle::Script | MathStyle::ScriptScript => MathStyle::ScriptScript,
+        }
+    }
+}
+
+/// A glyph of the math font, positioned on the baseline of the box that contains it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MathGlyph {
+    pub glyph_id: GlyphId,
+    pub position: Vec2,
+    pub font_size: f32,
+}
+
+/// A filled rectangle, such as a fraction bar, with `position` at its bottom left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MathRule {
+    pub position: Vec2,
+    pub size: Vec2,
+}
+
+/// A laid out part of an equation. Positions and sizes are in points, relative to the left end
+/// of the baseline, with y pointing up.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MathBox {
+    pub width: f32,
+    /// How far the box extends above the baseline.
+    pub height: f32,
+    /// How far the box extends below the baseline.
+    pub depth: f32,
+    /// How far the top of the last glyph overhangs the width, which superscripts are moved right
+    /// by.
+    pub italic_correction: f32,
+    pub glyphs: Vec<MathGlyph>,
+    pub rules: Vec<MathRule>,
+}
+
+impl MathBox {
+    /// Adds the contents of `other` with its origin at `offset`, growing the box to cover it.
+    fn place(&mut self, other: MathBox, offset: Vec2) {
+        self.glyphs
+            .extend(other.glyphs.into_iter().map(|glyph| MathGlyph {
+                position: glyph.position + offset,
+                ..glyph
+            }));
+        self.rules
+            .extend(other.rules.into_iter().map(|rule| MathRule {
+                position: rule.position + offset,
+                ..rule
+            }));
+        self.width = self.width.max(offset.x + other.width);
+        self.height = self.height.max(other.height + offset.y);
+        self.depth = self.depth.max(other.depth - offset.y);
+    }
+
+    /// Adds `other` after the end of the box, on the same baseline.
+    fn push(&mut self, other: MathBox) {
+        self.italic_correction = other.italic_correction;
+        self.place(other, vec2(self.width, 0.0));
+    }
+
+    /// Moves the contents of the box down, such as to centre a delimiter on the math axis.
+    fn lower(&mut self, shift: f32) {
+        for glyph in &mut self.glyphs {
+            glyph.position.y -= shift;
+        }
+        for rule in &mut self.rules {
+            rule.position.y -= shift;
+        }
+        self.height -= shift;
+        self.depth += shift;
+    }
+}
+
+/// Lays out an equation in a font with a `MATH` table, such as [`Font::default_math`], at
+/// `font_size` points.
+pub fn layout_equation(
+    font: &Font,
+    font_size: f32,
+    sequence: &Sequence,
+    style: MathStyle,
+) -> Result<MathBox, Error> {
+    let layout = Layout {
+        font,
+        constants: font.math_constants().ok_or(Error::MissingMathTable)?,
+        font_size,
+    };
+    layout.sequence(sequence, style)
+}
+
+struct Layout<'f> {
+    font: &'f Font<'f>,
+    constants: Constants<'f>,
+    font_size: f32,
+}
+
+impl Layout<'_> {
+    /// The font size of a style, in points.
+    fn em(&self, style: MathStyle) -> f32 {
+        let percent = match style {
+            MathStyle::Display | MathStyle::Text => 100,
+            MathStyle::Script => self.constants.script_percent_scale_down(),
+            MathStyle::ScriptScript => self.constants.script_script_percent_scale_down(),
+        };
+        self.font_size * percent as f32 / 100.0
+    }
+
+    /// Converts font units to points in a style.
+    fn scale(&self, style: MathStyle) -> f32 {
+        self.em(style) / self.font.face.units_per_em() as f32
+    }
+
+    fn value(&self, value: MathValue, style: MathStyle) -> f32 {
+        value.value as f32 * self.scale(style)
+    }
+
+    /// Converts math units, eighteenths of an em, to points in a style.
+    fn mu(&self, mu: i8, style: MathStyle) -> f32 {
+        mu as f32 * self.em(style) / 18.0
+    }
+
+    fn sequence(&self, sequence: &Sequence, style: MathStyle) -> Result<MathBox, Error> {
+        let mut result = MathBox::default();
+        let mut previous = None;
+        for (fragment, class) in sequence.fragments.iter().zip(classes(&sequence.fragments)) {
+            if let Fragment::Space(mu) = fragment {
+                // Negative space moves the end of the box back, which `push` doesn't.
+                result.width += self.mu(*mu, style);
+                result.italic_correction = 0.0;
+                continue;
+            }
+            if let (Some(left), Some(right)) = (previous, class) {
+                let mu = ATOM_SPACING[left as usize][right as usize];
+                if mu > 0 || style <= MathStyle::Text {
+                    result.width += self.mu(mu.abs(), style);
+                }
+            }
+            previous = class;
+            result.push(self.fragment(fragment, style)?);
+        }
+        Ok(result)
+    }
+
+    fn fragment(&self, fragment: &Fragment, style: MathStyle) -> Result<MathBox, Error> {
+        match fragment {
+            Fragment::Char(c) => Ok(self.glyph(self.glyph_index(*c)?, style)),
+            Fragment::Group(sequence) => self.sequence(sequence, style),
+            Fragment::Delimited(delimited) => self.delimited(delimited, style),
+            Fragment::Scripts(scripts) => {
+                // Big operators and braces can take their scripts as limits above and below.
+                let limits = match scripts.base {
+                    Fragment::Operator(_, limits) | Fragment::NamedOperator(_, limits) => {
+                        match limits {
+                            Limits::Display => style == MathStyle::Display,
+                            Limits::Always => true,
+                            Limits::Never => false,
+                        }
+                    }
+                    Fragment::Overbrace(_) | Fragment::Underbrace(_) => true,
+                    _ => false,
+                };
+                let base = self.fragment(&scripts.base, style)?;
+                let superscript = scripts.superscript.as_ref();
+                let subscript = scripts.subscript.as_ref();
+                if limits {
+                    self.limits(base, superscript, subscript, style)
+                } else {
+                    self.scripts(base, superscript, subscript, style)
+                }
+            }
+            Fragment::Fraction(fraction) => self.fraction(&fraction.0, &fraction.1, style),
+            Fragment::Radical(radical) => self.radical(radical.0.as_ref(), &radical.1, style),
+            Fragment::Operator(c, _) => self.operator(*c, style),
+            Fragment::Text(text) | Fragment::NamedOperator(text, _) => self.text(text, style),
+            Fragment::Accent(c, sequence) => self.accent(*c, sequence, style),
+            Fragment::Overline(sequence) => self.line(sequence, true, style),
+            Fragment::Underline(sequence) => self.line(sequence, false, style),
+            Fragment::Overbrace(sequence) => self.brace('⏞', sequence, true, style),
+            Fragment::Underbrace(sequence) => self.brace('⏟', sequence, false, style),
+            Fragment::Space(mu) => Ok(MathBox {
+                width: self.mu(*mu, style),
+                ..MathBox::default()
+            }),
+            Fragment::Grid(grid) => self.grid(grid, style),
+        }
+    }
+
+    fn glyph_index(&self, c: char) -> Result<GlyphId, Error> {
+        self.font.face.glyph_index(c).ok_or(Error::MissingGlyph(c))
+    }
+
+    fn glyph(&self, glyph_id: GlyphId, style: MathStyle) -> MathBox {
+        let scale = self.scale(style);
+        let face = &self.font.face;
+        let bbox = face.glyph_bounding_box(glyph_id);
+        MathBox {
+            width: face.glyph_hor_advance(glyph_id).unwrap_or_default() as f32 * scale,
+            height: bbox.map_or(0.0, |bbox| bbox.y_max as f32 * scale),
+            depth: bbox.map_or(0.0, |bbox| -bbox.y_min as f32 * scale),
+            italic_correction: self.font.italic_correction(glyph_id) as f32 * scale,
+            glyphs: vec![MathGlyph {
+                glyph_id,
+                position: Vec2::ZERO,
+                font_size: self.em(style),
+            }],
+            rules: Vec::new(),
+        }
+    }
+
+    /// Attaches scripts to a base, following the rules of the OpenType `MATH` specification.
+    fn scripts(
+        &self,
+        base: MathBox,
+        superscript: Option<&Fragment>,
+        subscript: Option<&Fragment>,
+        style: MathStyle,
+    ) -> Result<MathBox, Error> {
+        let c = &self.constants;
+        let script_style = style.script();
+        let superscript = superscript
+            .map(|script| self.fragment(script, script_style))
+            .transpose()?;
+        let subscript = subscript
+            .map(|script| self.fragment(script, script_style))
+            .transpose()?;
+
+        let mut superscript_shift = superscript.as_ref().map(|script| {
+            self.value(c.superscript_shift_up(), style)
+                .max(base.height - self.value(c.superscript_baseline_drop_max(), style))
+                .max(script.depth + self.value(c.superscript_bottom_min(), style))
+        });
+        let mut subscript_shift = subscript.as_ref().map(|script| {
+            self.value(c.subscript_shift_down(), style)
+                .max(base.depth + self.value(c.subscript_baseline_drop_min(), style))
+                .max(script.height - self.value(c.subscript_top_max(), style))
+        });
+
+        if let (Some(superscript), Some(subscript), Some(up), Some(down)) = (
+            &superscript,
+            &subscript,
+            &mut superscript_shift,
+            &mut subscript_shift,
+        ) {
+            let gap = (*up - superscript.depth) - (subscript.height - *down);
+            let gap_min = self.value(c.sub_superscript_gap_min(), style);
+            if gap < gap_min {
+                *down += gap_min - gap;
+                // Raise the superscript instead, as far as it may be raised.
+                let raise = self.value(c.superscript_bottom_max_with_subscript(), style)
+                    - (*up - superscript.depth);
+                if raise > 0.0 {
+                    *up += raise;
+                    *down -= raise;
+                }
+            }
+        }
+
+        let mut result = MathBox::default();
+        let base_width = base.width;
+        let italic_correction = base.italic_correction;
+        result.place(base, Vec2::ZERO);
+        let space_after = self.value(c.space_after_script(), style);
+        if let (Some(script), Some(shift)) = (superscript, superscript_shift) {
+            let width = base_width + italic_correction + script.width + space_after;
+            result.place(script, vec2(base_width + italic_correction, shift));
+            result.width = result.width.max(width);
+        }
+        if let (Some(script), Some(shift)) = (subscript, subscript_shift) {
+            let width = base_width + script.width + space_after;
+            result.place(script, vec2(base_width, -shift));
+            result.width = result.width.max(width);
+        }
+        Ok(result)
+    }
+
+    fn fraction(
+        &self,
+        numerator: &Sequence,
+        denominator: &Sequence,
+        style: MathStyle,
+    ) -> Result<MathBox, Error> {
+        let c = &self.constants;
+        let (inner_style, shift_up, shift_down, numerator_gap, denominator_gap) = match style {
+            MathStyle::Display => (
+                MathStyle::Text,
+                c.fraction_numerator_display_style_shift_up(),
+                c.fraction_denominator_display_style_shift_down(),
+                c.fraction_num_display_style_gap_min(),
+                c.fraction_denom_display_style_gap_min(),
+            ),
+            _ => (
+                if style == MathStyle::Text {
+                    MathStyle::Script
+                } else {
+                    MathStyle::ScriptScript
+                },
+                c.fraction_numerator_shift_up(),
+                c.fraction_denominator_shift_down(),
+                c.fraction_numerator_gap_min(),
+                c.fraction_denominator_gap_min(),
+            ),
+        };
+        let numerator = self.sequence(numerator, inner_style)?;
+        let denominator = self.sequence(denominator, inner_style)?;
+
+        let axis = self.value(c.axis_height(), style);
+        let thickness = self.value(c.fraction_rule_thickness(), style);
+        let shift_up = self
+            .value(shift_up, style)
+            .max(axis + thickness / 2.0 + self.value(numerator_gap, style) + numerator.depth);
+        let shift_down = self.value(shift_down, style).max(
+            denominator.height + self.value(denominator_gap, style) - (axis - thickness / 2.0),
+        );
+
+        let padding = NULL_DELIMITER_SPACE * self.em(style);
+        let width = numerator.width.max(denominator.width);
+        let mut result = MathBox::default();
+        let numerator_x = padding + (width - numerator.width) / 2.0;
+        result.place(numerator, vec2(numerator_x, shift_up));
+        let denominator_x = padding + (width - denominator.width) / 2.0;
+        result.place(denominator, vec2(denominator_x, -shift_down));
+        result.rules.push(MathRule {
+            position: vec2(padding, axis - thickness / 2.0),
+            size: vec2(width, thickness),
+        });
+        result.width = width + 2.0 * padding;
+        Ok(result)
+    }
+
+    fn delimited(&self, delimited: &Delimited, style: MathStyle) -> Result<MathBox, Error> {
+        let parts = delimited
+            .parts
+            .iter()
+            .map(|part| self.sequence(part, style))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Delimiters are centred on the math axis, so they have to reach as far from it as the
+        // content does on either side.
+        let axis = self.value(self.constants.axis_height(), style);
+        let extent = parts
+            .iter()
+            .map(|part| (part.height - axis).max(part.depth + axis))
+            .fold(0.0, f32::max);
+        let size = (2.0 * extent * DELIMITER_FACTOR)
+            .max(2.0 * extent - DELIMITER_SHORTFALL * self.em(style));
+
+        let opening = |delimiter: Delimiter| delimiter.chars().map(|chars| chars.0);
+        let mut result = MathBox::default();
+        result.push(self.delimiter(opening(delimited.left), size, style)?);
+        for (i, part) in parts.into_iter().enumerate() {
+            if i > 0 {
+                let middle = delimited.middle[i - 1];
+                result.push(self.delimiter(opening(middle), size, style)?);
+            }
+            result.push(part);
+        }
+        let closing = delimited.right.chars().map(|chars| chars.1);
+        result.push(self.delimiter(closing, size, style)?);
+        result.italic_correction = 0.0;
+        Ok(result)
+    }
+
+    /// Lays out a delimiter that is at least `size` points tall, centred on the math axis. `None`
+    /// is the null delimiter, which is empty space.
+    fn delimiter(&self, c: Option<char>, size: f32, style: MathStyle) -> Result<MathBox, Error> {
+        let Some(c) = c else {
+            return Ok(MathBox {
+                width: NULL_DELIMITER_SPACE * self.em(style),
+                ..Default::default()
+            });
+        };
+        let mut result = self.stretched(self.glyph_index(c)?, size, Axis::Vertical, style);
+        let axis = self.value(self.constants.axis_height(), style);
+        result.lower((result.height - result.depth) / 2.0 - axis);
+        Ok(result)
+    }
+
+    /// Lays out a glyph that is at least `size` points long in the direction. The smallest variant
+    /// that is big enough is used, or else the glyph's assembly, or else the largest variant.
+    fn stretched(&self, glyph_id: GlyphId, size: f32, axis: Axis, style: MathStyle) -> MathBox {
+        let scale = self.scale(style);
+        let target = size / scale;
+
+        let variants = self.font.glyph_variants(glyph_id, axis);
+        let variant = variants
+            .iter()
+            .find(|&&(_, advance)| advance as f32 >= target);
+        let assembly = self
+            .font
+            .glyph_assembly(glyph_id, axis)
+            .and_then(|assembly| {
+                let parts = assembly.parts.into_iter().collect::<Vec<_>>();
+                let min_overlap = self.font.min_connector_overlap() as f32;
+                let (parts, length) = assemble(&parts, target, min_overlap)?;
+                Some((parts, length, assembly.italics_correction))
+            });
+        match (variant, assembly) {
+            (Some(&(variant, _)), _) => self.glyph(variant, style),
+            (None, Some((parts, length, italic_correction))) => {
+                let parts = parts
+                    .into_iter()
+                    .map(|(part, offset)| (self.glyph(part, style), offset * scale))
+                    .collect::<Vec<_>>();
+                let mut result = match axis {
+                    Axis::Vertical => MathBox {
+                        height: length * scale,
+                        ..Default::default()
+                    },
+                    // Horizontal parts can float above or below the baseline, like braces do.
+                    Axis::Horizontal => MathBox {
+                        width: length * scale,
+                        height: parts
+                            .iter()
+                            .map(|part| part.0.height)
+                            .fold(f32::MIN, f32::max),
+                        depth: parts
+                            .iter()
+                            .map(|part| part.0.depth)
+                            .fold(f32::MIN, f32::max),
+                        ..Default::default()
+                    },
+                };
+                for (part, offset) in parts {
+                    let position = match axis {
+                        // Vertical parts start where the bottom of their glyph is.
+                        Axis::Vertical => vec2(0.0, offset + part.depth),
+                        Axis::Horizontal => vec2(offset, 0.0),
+                    };
+                    result.place(part, position);
+                }
+                result.italic_correction = self.value(italic_correction, style);
+                result
+            }
+            (None, None) => self.glyph(variants.last().map_or(glyph_id, |v| v.0), style),
+        }
+    }
+
+    /// Lays out upright text, such as from `\text` or the name of an operator, glyph by glyph.
+    fn text(&self, text: &str, style: MathStyle) -> Result<MathBox, Error> {
+        let mut result = MathBox::default();
+        for c in text.chars() {
+            result.push(self.glyph(self.glyph_index(c)?, style));
+        }
+        Ok(result)
+    }
+
+    /// Lays out a big operator centred on the math axis, using a larger variant in display style.
+    fn operator(&self, c: char, style: MathStyle) -> Result<MathBox, Error> {
+        let mut glyph_id = self.glyph_index(c)?;
+        if style == MathStyle::Display {
+            let min_height = self.constants.display_operator_min_height() as f32;
+            let variants = self.font.glyph_variants(glyph_id, Axis::Vertical);
+            if let Some(&(variant, _)) = variants
+                .iter()
+                .find(|&&(_, advance)| advance as f32 >= min_height)
+                .or(variants.last())
+            {
+                glyph_id = variant;
+            }
+        }
+
+        let mut result = self.glyph(glyph_id, style);
+        let axis = self.value(self.constants.axis_height(), style);
+        result.lower((result.height - result.depth) / 2.0 - axis);
+        Ok(result)
+    }
+
+    /// Sets the limits of a big operator above and below it, centred and moved apart by its
+    /// italic correction so that they follow its slant.
+    fn limits(
+        &self,
+        operator: MathBox,
+        upper: Option<&Fragment>,
+        lower: Option<&Fragment>,
+        style: MathStyle,
+    ) -> Result<MathBox, Error> {
+        let c = &self.constants;
+        let upper = upper
+            .map(|limit| self.fragment(limit, style.script()))
+            .transpose()?;
+        let lower = lower
+            .map(|limit| self.fragment(limit, style.script()))
+            .transpose()?;
+
+        let width = [&upper, &lower]
+            .into_iter()
+            .flatten()
+            .map(|limit| limit.width)
+            .fold(operator.width, f32::max);
+        let italic_correction = operator.italic_correction;
+        let (operator_height, operator_depth) = (operator.height, operator.depth);
+        let operator_x = (width - operator.width) / 2.0;
+        let mut result = MathBox::default();
+        result.place(operator, vec2(operator_x, 0.0));
+        if let Some(upper) = upper {
+            let shift = operator_height
+                + self
+                    .value(c.upper_limit_baseline_rise_min(), style)
+                    .max(self.value(c.upper_limit_gap_min(), style) + upper.depth);
+            let x = (width - upper.width + italic_correction) / 2.0;
+            result.place(upper, vec2(x, shift));
+        }
+        if let Some(lower) = lower {
+            let shift = operator_depth
+                + self
+                    .value(c.lower_limit_baseline_drop_min(), style)
+                    .max(self.value(c.lower_limit_gap_min(), style) + lower.height);
+            let x = (width - lower.width - italic_correction) / 2.0;
+            result.place(lower, vec2(x, -shift));
+        }
+        result.width = width;
+        Ok(result)
+    }
+
+    /// Lays out a root, with a radical sign that is stretched to cover the radicand and a rule
+    /// over it, and the degree raised into the crook of the sign.
+    fn radical(
+        &self,
+        degree: Option<&Sequence>,
+        radicand: &Sequence,
+        style: MathStyle,
+    ) -> Result<MathBox, Error> {
+        let c = &self.constants;
+        let radicand = self.sequence(radicand, style)?;
+        let gap = match style {
+            MathStyle::Display => c.radical_display_style_vertical_gap(),
+            _ => c.radical_vertical_gap(),
+        };
+        let mut gap = self.value(gap, style);
+        let thickness = self.value(c.radical_rule_thickness(), style);
+
+        let size = radicand.height + radicand.depth + gap + thickness;
+        let mut sign = self.stretched(self.glyph_index('√')?, size, Axis::Vertical, style);
+        // A sign that is taller than needed leaves the radicand centred under it.
+        let sign_size = sign.height + sign.depth;
+        if sign_size > size {
+            gap += (sign_size - size) / 2.0;
+        }
+        let rule_bottom = radicand.height + gap;
+        sign.lower(sign.height - rule_bottom - thickness);
+
+        let mut result = MathBox::default();
+        let mut x = 0.0;
+        if let Some(degree) = degree {
+            let degree = self.sequence(degree, MathStyle::ScriptScript)?;
+            let before = self.value(c.radical_kern_before_degree(), style);
+            let after = self.value(c.radical_kern_after_degree(), style);
+            let raise = c.radical_degree_bottom_raise_percent() as f32 / 100.0
+                * (sign.height + sign.depth)
+                - sign.depth;
+            x = (before + degree.width + after).max(0.0);
+            result.place(degree, vec2(before, raise));
+        }
+
+        let sign_width = sign.width;
+        result.place(sign, vec2(x, 0.0));
+        result.rules.push(MathRule {
+            position: vec2(x + sign_width, rule_bottom),
+            size: vec2(radicand.width, thickness),
+        });
+        result.place(radicand, vec2(x + sign_width, 0.0));
+        result.height = result
+            .height
+            .max(rule_bottom + thickness + self.value(c.radical_extra_ascender(), style));
+        Ok(result)
+    }
+
+    /// Sets an accent over its content. The accent is centred on the content's accent
+    /// attachment point, and raised if the content is taller than the accent was designed for.
+    fn accent(&self, c: char, sequence: &Sequence, style: MathStyle) -> Result<MathBox, Error> {
+        let content = self.sequence(sequence, style)?;
+        let accent_id = self.glyph_index(c)?;
+        let accent = self.glyph(accent_id, style);
+
+        let units_per_em = self.font.face.units_per_em() as f32;
+        let attachment = match content.glyphs.as_slice() {
+            [glyph] if content.rules.is_empty() => {
+                let attachment = self.font.top_accent_attachment(glyph.glyph_id) as f32;
+                glyph.position.x + attachment * glyph.font_size / units_per_em
+            }
+            _ => content.width / 2.0,
+        };
+        let accent_attachment =
+            self.font.top_accent_attachment(accent_id) as f32 * self.scale(style);
+        let base_height = self.value(self.constants.accent_base_height(), style);
+        let shift = (content.height - base_height).max(0.0);
+
+        let (width, italic_correction) = (content.width, content.italic_correction);
+        let mut result = MathBox::default();
+        result.place(content, Vec2::ZERO);
+        result.place(accent, vec2(attachment - accent_attachment, shift));
+        result.width = width;
+        result.italic_correction = italic_correction;
+        Ok(result)
+    }
+
+    /// Draws a rule over or under its content.
+    fn line(&self, sequence: &Sequence, over: bool, style: MathStyle) -> Result<MathBox, Error> {
+        let c = &self.constants;
+        let content = self.sequence(sequence, style)?;
+        let (width, height, depth) = (content.width, content.height, content.depth);
+        let mut result = MathBox::default();
+        result.place(content, Vec2::ZERO);
+        if over {
+            let gap = self.value(c.overbar_vertical_gap(), style);
+            let thickness = self.value(c.overbar_rule_thickness(), style);
+            result.rules.push(MathRule {
+                position: vec2(0.0, height + gap),
+                size: vec2(width, thickness),
+            });
+            result.height =
+                height + gap + thickness + self.value(c.overbar_extra_ascender(), style);
+        } else {
+            let gap = self.value(c.underbar_vertical_gap(), style);
+            let thickness = self.value(c.underbar_rule_thickness(), style);
+            result.rules.push(MathRule {
+                position: vec2(0.0, -(depth + gap + thickness)),
+                size: vec2(width, thickness),
+            });
+            result.depth =
+                depth + gap + thickness + self.value(c.underbar_extra_descender(), style);
+        }
+        Ok(result)
+    }
+
+    /// Sets a horizontal brace, stretched to the width of its content, over or under it.
+    fn brace(
+        &self,
+        c: char,
+        sequence: &Sequence,
+        over: bool,
+        style: MathStyle,
+    ) -> Result<MathBox, Error> {
+        let content = self.sequence(sequence, style)?;
+        let brace_id = self.glyph_index(c)?;
+        let brace = self.stretched(brace_id, content.width, Axis::Horizontal, style);
+
+        let width = content.width.max(brace.width);
+        let shift = if over {
+            let gap = self.value(self.constants.stretch_stack_gap_below_min(), style);
+            content.height + gap + brace.depth
+        } else {
+            let gap = self.value(self.constants.stretch_stack_gap_above_min(), style);
+            -(content.depth + gap + brace.height)
+        };
+        let content_x = (width - content.width) / 2.0;
+        let brace_x = (width - brace.width) / 2.0;
+        let mut result = MathBox::default();
+        result.place(content, vec2(content_x, 0.0));
+        result.place(brace, vec2(brace_x, shift));
+        Ok(result)
+    }
+
+    /// Lays out the cells of a grid in rows from the top, centred on the math axis.
+    fn grid(&self, grid: &Grid, style: MathStyle) -> Result<MathBox, Error> {
+        let cell_style = if grid.display {
+            MathStyle::Display
+        } else {
+            MathStyle::Text
+        };
+        let rows = grid
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| self.sequence(cell, cell_style))
+                    .collect::<Result<Vec<_>, Error>>()
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Each column starts after the widest cell of the previous one and the column's space.
+        let mut widths = vec![0.0f32; grid.columns.len()];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = width.max(cell.width);
+            }
+        }
+        let mut starts = Vec::with_capacity(widths.len());
+        let mut x = 0.0;
+        for (i, (column, width)) in grid.columns.iter().zip(&widths).enumerate() {
+            if i > 0 {
+                x += self.mu(column.space, cell_style);
+            }
+            starts.push(x);
+            x += width;
+        }
+
+        let em = self.em(cell_style);
+        let row_space = self.mu(grid.row_space, cell_style);
+        let mut result = MathBox::default();
+        let mut y = 0.0;
+        for (i, row) in rows.into_iter().enumerate() {
+            if i > 0 {
+                y -= row_space;
+            }
+            let height = row
+                .iter()
+                .fold(STRUT_HEIGHT * em, |height, cell| height.max(cell.height));
+            let depth = row
+                .iter()
+                .fold(STRUT_DEPTH * em, |depth, cell| depth.max(cell.depth));
+            y -= height;
+            for (j, cell) in row.into_iter().enumerate() {
+                let offset = match grid.columns[j].align {
+                    ColumnAlign::Left => 0.0,
+                    ColumnAlign::Center => (widths[j] - cell.width) / 2.0,
+                    ColumnAlign::Right => widths[j] - cell.width,
+                };
+                result.place(cell, vec2(starts[j] + offset, y));
+            }
+            y -= depth;
+        }
+        result.width = x;
+        result.height = 0.0;
+        result.depth = -y;
+
+        let axis = self.value(self.constants.axis_height(), style);
+        result.lower(-(result.depth / 2.0 + axis));
+        Ok(result)
+    }
+}
+
+/// The classes of the atoms in a sequence, with binary operators that have no operand on one side
+/// turned into ordinary symbols, as in `-x` or `(+)`.
+fn classes(fragments: &[Fragment]) -> Vec<Option<Class>> {
+    let mut classes: Vec<_> = fragments.iter().map(Fragment::class).collect();
+    let mut previous: Option<usize> = None;
+    for i in 0..classes.len() {
+        let Some(class) = classes[i] else {
+            continue;
+        };
+        let left = previous.and_then(|previous| classes[previous]);
+        match (class, left) {
+            (
+                Class::Bin,
+                None | Some(Class::Bin | Class::Op | Class::Rel | Class::Open | Class::Punct),
+            ) => classes[i] = Some(Class::Ord),
+            (Class::Rel | Class::Close | Class::Punct, Some(Class::Bin)) => {
+                classes[previous.unwrap()] = Some(Class::Ord);
+            }
+            _ => {}
+        }
+        previous = Some(i);
+    }
+    if let Some(previous) = previous.filter(|&previous| classes[previous] == Some(Class::Bin)) {
+        classes[previous] = Some(Class::Ord);
+    }
+    classes
+}
+
+/// Finds how often to repeat the extenders of an assembly and how much to overlap its parts so
+/// that it is at least `size` long. Returns the glyphs with their offsets from the start, and the
+/// length of the assembly, all in font units.
+///
+/// The extenders are repeated as few times as possible, and the parts then overlap by the same
+/// amount, as much as their connectors allow, to come as close to `size` as possible. Returns
+/// `None` if the assembly has no parts.
+fn assemble(
+    parts: &[GlyphPart],
+    size: f32,
+    min_overlap: f32,
+) -> Option<(Vec<(GlyphId, f32)>, f32)> {
+    let has_extenders = parts.iter().any(|part| part.part_flags.extender());
+    for repeats in 0..=MAX_EXTENDER_REPEATS {
+        let mut expanded = Vec::new();
+        for part in parts {
+            let count = if part.part_flags.extender() {
+                repeats
+            } else {
+                1
+            };
+            for _ in 0..count {
+                expanded.push(part);
+            }
+        }
+
+        let total = expanded
+            .iter()
+            .map(|part| part.full_advance as f32)
+            .sum::<f32>();
+        let connections = expanded.len().saturating_sub(1) as f32;
+        let big_enough = total - min_overlap * connections >= size;
+        if expanded.is_empty() {
+            continue;
+        }
+        if big_enough || !has_extenders || repeats == MAX_EXTENDER_REPEATS {
+            let max_overlap = expanded
+                .windows(2)
+                .map(|pair| {
+                    pair[0]
+                        .end_connector_length
+                        .min(pair[1].start_connector_length) as f32
+                })
+                .fold(f32::INFINITY, f32::min)
+                .max(min_overlap);
+            let overlap = if connections > 0.0 {
+                ((total - size) / connections).clamp(min_overlap, max_overlap)
+            } else {
+                0.0
+            };
+
+            let mut offset = 0.0;
+            let glyphs = expanded
+                .iter()
+                .map(|part| {
+                    let glyph = (part.glyph_id, offset);
+                    offset += part.full_advance as f32 - overlap;
+                    glyph
+                })
+                .collect();
+            return Some((glyphs, total - overlap * connections));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{
+        math::{GlyphPart, PartFlags},
+        GlyphId,
+    };
+
+    use super::{
+        assemble, layout_equation, MathBox, MathGlyph, MathStyle, DELIMITER_FACTOR,
+        MAX_EXTENDER_REPEATS,
+    };
+    use crate::{
+        equation::parser::parse_latex,
+        font::{math::Axis, Font},
+    };
+
+    fn layout(font: &Font, source: &str) -> MathBox {
+        let sequence = parse_latex(source).unwrap();
+        layout_equation(font, 10.0, &sequence, MathStyle::Display).unwrap()
+    }
+
+    #[test]
+    fn test_assemble() {
+        let part = |glyph_id, extender| GlyphPart {
+            glyph_id: GlyphId(glyph_id),
+            start_connector_length: 200,
+            end_connector_length: 200,
+            full_advance: 1000,
+            part_flags: PartFlags(extender as u16),
+        };
+        let parts = [part(1, false), part(2, true), part(3, false)];
+
+        // The ends alone are long enough, and overlap as much as their connectors allow.
+        let (glyphs, length) = assemble(&parts, 1000.0, 100.0).unwrap();
+        assert_eq!(glyphs, [(GlyphId(1), 0.0), (GlyphId(3), 800.0)]);
+        assert_eq!(length, 1800.0);
+
+        // The extender is repeated once, and the parts overlap less for larger sizes.
+        let (glyphs, length) = assemble(&parts, 2500.0, 100.0).unwrap();
+        assert_eq!(
+            glyphs,
+            [(GlyphId(1), 0.0), (GlyphId(2), 800.0), (GlyphId(3), 1600.0)]
+        );
+        assert_eq!(length, 2600.0);
+
+        let (glyphs, length) = assemble(&parts, 2750.0, 100.0).unwrap();
+        assert_eq!(glyphs[2], (GlyphId(3), 1750.0));
+        assert_eq!(length, 2750.0);
+        let (glyphs, _) = assemble(&parts, 5000.0, 100.0).unwrap();
+        assert_eq!(glyphs.len(), 6);
+
+        // Assemblies without parts, or with only extenders, end.
+        assert_eq!(assemble(&[], 1000.0, 100.0), None);
+        let (glyphs, _) = assemble(&[part(2, true)], 1e9, 100.0).unwrap();
+        assert_eq!(glyphs.len(), MAX_EXTENDER_REPEATS);
+    }
+
+    #[test]
+    fn test_stretch_delimiters() {
+        let font = Font::default_math();
+        let paren = font.face.glyph_index('(').unwrap();
+        let scale = 10.0 / font.face.units_per_em() as f32;
+
+        // Delimiters around a single letter keep their normal size.
+        let small = layout(&font, r"\left( x \right)");
+        assert_eq!(small.glyphs[0].glyph_id, paren);
+
+        // Delimiters around a fraction switch to a larger variant that covers most of it.
+        let content = layout(&font, r"\frac{a}{b}");
+        let delimited = layout(&font, r"\left( \frac{a}{b} \right)");
+        let delimiter = delimited.glyphs[0].glyph_id;
+        assert_ne!(delimiter, paren);
+        let bbox = font.face.glyph_bounding_box(delimiter).unwrap();
+        assert!(bbox.height() as f32 * scale >= 0.9 * (content.height + content.depth));
+
+        // Delimiters around tall content are assembled from several parts.
+        let tall = r"\frac{\frac{\frac{a}{b}}{\frac{c}{d}}}{\frac{\frac{e}{f}}{\frac{g}{h}}}";
+        let content = layout(&font, tall);
+        let delimited = layout(&font, &format!(r"\left[ {tall} \right]"));
+        let bracket = font.face.glyph_index('[').unwrap();
+        let variants = font.glyph_variants(bracket, Axis::Vertical);
+        let parts = delimited
+            .glyphs
+            .iter()
+            .take_while(|glyph| glyph.position.x == 0.0)
+            .collect::<Vec<_>>();
+        assert!(parts.len() > 2);
+        assert!(parts
+            .iter()
+            .all(|part| !variants.iter().any(|variant| variant.0 == part.glyph_id)));
+        assert!(parts
+            .windows(2)
+            .all(|pair| pair[0].position.y < pair[1].position.y));
+        assert!(delimited.height >= content.height);
+        assert!(delimited.depth >= content.depth);
+    }
+
+    #[test]
+    fn test_null_and_middle_delimiters() {
+        let font = Font::default_math();
+
+        // The null delimiter is empty space.
+        let null = layout(&font, r"\left. x \right|");
+        assert_eq!(null.glyphs.len(), 2);
+        assert_eq!(null.glyphs[0].glyph_id, font.face.glyph_index('𝑥').unwrap());
+        assert!((null.glyphs[0].position.x - 1.2).abs() < 1e-6);
+
+        // Middle delimiters grow with the outer ones.
+        let middle = layout(&font, r"\left( \frac{a}{b} \middle| c \right)");
+        let bar = font.face.glyph_index('|').unwrap();
+        let outer = font
+            .face
+            .glyph_bounding_box(middle.glyphs[0].glyph_id)
+            .unwrap();
+        let inner = font
+            .face
+            .glyph_bounding_box(middle.glyphs[3].glyph_id)
+            .unwrap();
+        assert_ne!(middle.glyphs[3].glyph_id, bar);
+        assert!(inner.height() > font.face.glyph_bounding_box(bar).unwrap().height());
+        assert!(inner.height() as f32 >= 0.9 * outer.height() as f32);
+    }
+
+    #[test]
+    fn test_radical() {
+        let font = Font::default_math();
+        let scale = 10.0 / font.face.units_per_em() as f32;
+        let sign = font.face.glyph_index('√').unwrap();
+
+        // The rule starts at the end of the sign and covers the radicand.
+        let root = layout(&font, r"\sqrt{x}");
+        let (x, rule) = (root.glyphs[1], root.rules[0]);
+        let bbox = font.face.glyph_bounding_box(x.glyph_id).unwrap();
+        let advance = font.face.glyph_hor_advance(x.glyph_id).unwrap();
+        assert_eq!(root.glyphs[0].glyph_id, sign);
+        assert_eq!(rule.position.x, x.position.x);
+        assert_eq!(rule.size.x, advance as f32 * scale);
+        assert!(rule.position.y > bbox.y_max as f32 * scale);
+        assert!(root.height > rule.position.y + rule.size.y);
+
+        // The degree is smaller and raised before the sign.
+        let cube = layout(&font, r"\sqrt[3]{x}");
+        assert!(cube.glyphs[0].font_size < 10.0);
+        assert!(cube.glyphs[0].position.y > 0.0);
+        assert!(cube.glyphs[0].position.x < cube.glyphs[1].position.x + 1.0);
+
+        // The sign grows to cover a fraction.
+        let fraction = layout(&font, r"\sqrt{\frac{a}{b}}");
+        assert_ne!(fraction.glyphs[0].glyph_id, sign);
+        assert!(fraction.depth >= layout(&font, r"\frac{a}{b}").depth);
+    }
+
+    #[test]
+    fn test_big_operators() {
+        let font = Font::default_math();
+        let sequence = parse_latex(r"\sum_{i=0}^n").unwrap();
+        let display = layout_equation(&font, 10.0, &sequence, MathStyle::Display).unwrap();
+        let text = layout_equation(&font, 10.0, &sequence, MathStyle::Text).unwrap();
+        let height = |math_box: &MathBox| {
+            let bbox = font.face.glyph_bounding_box(math_box.glyphs[0].glyph_id);
+            bbox.unwrap().height()
+        };
+
+        // Sums are bigger in display style, with their limits above and below.
+        assert!(height(&display) > height(&text));
+        let (sum, upper, lower) = (display.glyphs[0], display.glyphs[1], display.glyphs[2]);
+        assert!(upper.position.y > sum.position.y);
+        assert!(lower.position.y < sum.position.y);
+        assert!(upper.position.x > sum.position.x);
+        assert!(upper.position.x < sum.position.x + display.width / 2.0);
+
+        // In text style, the limits are scripts after the sum.
+        let advance = font
+            .face
+            .glyph_hor_advance(text.glyphs[0].glyph_id)
+            .unwrap();
+        let scale = 10.0 / font.face.units_per_em() as f32;
+        assert!(text.glyphs[1].position.x >= advance as f32 * scale);
+        assert!(text.width > display.width);
+
+        // Integrals take scripts, even in display style, unless they are given `\limits`.
+        let integral = layout(&font, r"\int_0^1");
+        let limits = layout(&font, r"\int\limits_0^1");
+        assert!(integral.width > limits.width);
+        assert!(limits.height > integral.height);
+    }
+
+    #[test]
+    fn test_scripts() {
+        let font = Font::default_math();
+
+        // A subscript and a superscript are stacked after the same base.
+        let both = layout(&font, r"x_i^2");
+        let (x, superscript, subscript) = (both.glyphs[0], both.glyphs[1], both.glyphs[2]);
+        let advance = font.face.glyph_hor_advance(x.glyph_id).unwrap() as f32;
+        let scale = 10.0 / font.face.units_per_em() as f32;
+        assert!(superscript.position.x >= advance * scale);
+        assert_eq!(subscript.position.x, advance * scale);
+        assert!(superscript.position.y > 0.0);
+        assert!(subscript.position.y < 0.0);
+        assert!(both.width < layout(&font, r"x_i{}^2").width);
+
+        // Primes are set as a superscript.
+        let prime = layout(&font, r"f'");
+        assert!(prime.glyphs[1].position.y > 0.0);
+        assert!(prime.glyphs[1].font_size < 10.0);
+    }
+
+    #[test]
+    fn test_accents() {
+        let font = Font::default_math();
+        let scale = 10.0 / font.face.units_per_em() as f32;
+
+        // The accent's attachment point is put over the base's.
+        let hat = layout(&font, r"\hat{x}");
+        let (x, accent) = (hat.glyphs[0], hat.glyphs[1]);
+        let accent_attachment = font.top_accent_attachment(accent.glyph_id) as f32 * scale;
+        let base_attachment = font.top_accent_attachment(x.glyph_id) as f32 * scale;
+        assert!((accent.position.x + accent_attachment - base_attachment).abs() < 1e-5);
+        assert_eq!(accent.position.y, 0.0);
+        assert_eq!(hat.width, layout(&font, "x").width);
+
+        // Accents over tall letters are raised.
+        let tall = layout(&font, r"\hat{A}");
+        assert!(tall.glyphs[1].position.y > 0.0);
+
+        let overline = layout(&font, r"\overline{xy}");
+        assert_eq!(overline.rules.len(), 1);
+        assert_eq!(overline.rules[0].size.x, overline.width);
+        assert!(overline.rules[0].position.y > layout(&font, "xy").height);
+        let underline = layout(&font, r"\underline{y}");
+        assert!(
+            underline.rules[0].position.y + underline.rules[0].size.y < -layout(&font, "y").depth
+        );
+    }
+
+    #[test]
+    fn test_braces() {
+        let font = Font::default_math();
+        let content = layout(&font, "a+b+c+d+e+f");
+
+        // The brace stretches over the content, and the superscript goes above it.
+        let over = layout(&font, r"\overbrace{a+b+c+d+e+f}^n");
+        let n = *over.glyphs.last().unwrap();
+        let brace = &over.glyphs[content.glyphs.len()..over.glyphs.len() - 1];
+        let top = brace
+            .iter()
+            .map(|glyph| {
+                let bbox = font.face.glyph_bounding_box(glyph.glyph_id).unwrap();
+                glyph.position.y + bbox.y_max as f32 * 10.0 / font.face.units_per_em() as f32
+            })
+            .fold(f32::MIN, f32::max);
+        assert!(brace.iter().all(|glyph| glyph.position.y > 0.0));
+        assert!(n.position.y > top);
+        assert!(over.width >= content.width);
+
+        // Under a brace, the subscript goes below it.
+        let under = layout(&font, r"\underbrace{a+b+c+d+e+f}_n");
+        let n = *under.glyphs.last().unwrap();
+        let brace = under.glyphs[content.glyphs.len()];
+        assert!(brace.position.y < 0.0);
+        assert!(n.position.y < brace.position.y);
+        assert!(under.depth > content.depth);
+    }
+
+    #[test]
+    fn test_text_and_named_operators() {
+        let font = Font::default_math();
+
+        // Text keeps its spaces.
+        let text = layout(&font, r"\text{a b}");
+        assert_eq!(text.glyphs.len(), 3);
+        assert!(text.width > layout(&font, "ab").width);
+
+        // Operators like `\lim` take limits in display style, and others take scripts.
+        let lim = layout(&font, r"\lim_n");
+        let n = lim.glyphs[3];
+        assert!(n.position.y < 0.0);
+        assert!(n.position.x < lim.width / 2.0);
+        let sin = layout(&font, r"\sin^2");
+        assert_eq!(sin.glyphs[3].position.x, layout(&font, r"\sin").width);
+
+        let bold = layout(&font, r"\mathbf{x}");
+        assert_eq!(bold.glyphs[0].glyph_id, font.face.glyph_index('𝐱').unwrap());
+
+        // Bare letters are italic, unlike text and upright alphabets.
+        let x = layout(&font, "x").glyphs[0].glyph_id;
+        assert_eq!(x, font.face.glyph_index('𝑥').unwrap());
+        assert_ne!(x, layout(&font, r"\text{x}").glyphs[0].glyph_id);
+        assert_ne!(x, layout(&font, r"\mathrm{x}").glyphs[0].glyph_id);
+        assert_ne!(x, layout(&font, r"\operatorname{x}").glyphs[0].glyph_id);
+    }
+
+    #[test]
+    fn test_atom_spacing() {
+        let font = Font::default_math();
+        let width = |source: &str| layout(&font, source).width;
+        let glyphs = |source: &str| source.chars().map(|c| width(&c.to_string())).sum::<f32>();
+        let assert_space = |source, mu: f32| {
+            let space = width(source) - glyphs(source);
+            assert!((space - mu * 10.0 / 18.0).abs() < 1e-4, "{source}: {space}");
+        };
+
+        // Medium space around binary operators and thick space around relations.
+        assert_space("a+b", 8.0);
+        assert_space("a=b", 10.0);
+        assert_space("a,b", 3.0);
+        assert_space("(a)", 0.0);
+
+        // A binary operator without a left operand is an ordinary symbol.
+        assert_space("+a", 0.0);
+        assert_space("a=+b", 10.0);
+        assert_space("(+)", 0.0);
+        assert_space("a+", 0.0);
+
+        // Named operators and fractions are spaced like TeX's op and inner atoms.
+        assert!(width(r"\sin x") > width(r"\sin") + width("x"));
+        assert!(width(r"a\frac{1}{2}") > width("a") + width(r"\frac{1}{2}"));
+
+        // Scripts only keep the thin spaces.
+        let script = |source| {
+            let sequence = parse_latex(source).unwrap();
+            layout_equation(&font, 10.0, &sequence, MathStyle::Script)
+                .unwrap()
+                .width
+        };
+        assert_eq!(script("a+b"), script("a") + script("+") + script("b"));
+
+        // Explicit spaces, which can be negative.
+        assert!((width(r"a\quad b") - width("ab") - 10.0).abs() < 1e-4);
+        assert!((width(r"a\!b") - width("ab") + 10.0 / 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_grids() {
+        let font = Font::default_math();
+        let font_size = 10.0;
+        let axis = font.math_constants().unwrap().axis_height().value as f32 * font_size
+            / font.face.units_per_em() as f32;
+        let right = |glyph: &MathGlyph| {
+            let advance = font.face.glyph_hor_advance(glyph.glyph_id).unwrap();
+            glyph.position.x + advance as f32 * font_size / font.face.units_per_em() as f32
+        };
+
+        // Grids are centred on the math axis, and their rows are at least a baseline skip apart.
+        let matrix = layout(&font, r"\begin{matrix} 1 & 22 \\ 333 & 4 \end{matrix}");
+        assert!((matrix.height - axis - (matrix.depth + axis)).abs() < 1e-4);
+        let (one, three) = (matrix.glyphs[0], matrix.glyphs[3]);
+        assert!(one.position.y - three.position.y >= 1.2 * font_size - 1e-4);
+
+        // Cells are centred in their columns, which are as wide as their widest cell and a quad
+        // apart.
+        let centre = (one.position.x + right(&one)) / 2.0;
+        assert!((centre - (three.position.x + right(&matrix.glyphs[5])) / 2.0).abs() < 1e-4);
+        let four = matrix.glyphs[6];
+        assert!(four.position.x >= right(&matrix.glyphs[5]) + font_size - 1e-4);
+        assert!(right(&four) < matrix.width);
+
+        let array = layout(&font, r"\begin{array}{rl} 1 & 22 \\ 333 & 4 \end{array}");
+        assert!((right(&array.glyphs[0]) - right(&array.glyphs[5])).abs() < 1e-4);
+        assert_eq!(array.glyphs[1].position.x, array.glyphs[6].position.x);
+
+        // Relations line up in `aligned`, and keep their space from the empty group before them.
+        let aligned = layout(&font, r"\begin{aligned} x &= 1 \\ yy &= 2 \end{aligned}");
+        let (x, first, second) = (aligned.glyphs[0], aligned.glyphs[1], aligned.glyphs[5]);
+        assert_eq!(first.position.x, second.position.x);
+        assert!(first.position.x > right(&x) + 0.2 * font_size);
+
+        // Matrices with delimiters and cases are grids inside delimiters that cover them.
+        let pmatrix = layout(&font, r"\begin{pmatrix} 1 \\ 2 \\ 3 \end{pmatrix}");
+        assert!(pmatrix.height + pmatrix.depth > 3.0 * 1.2 * font_size * DELIMITER_FACTOR);
+        let cases = layout(
+            &font,
+            r"\begin{cases} 1 & x > 0 \\ 0 & x \leq 0 \end{cases}",
+        );
+        assert!(cases.height > matrix.height);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod alphabet;
+pub mod layout;
 pub mod parser;
 pub mod scanner;
 
@@ -10,15 +12,172 @@ pub struct Sequence {
 pub enum Fragment {
     Char(char),
     Group(Box<Sequence>),
-    Delimited(Box<(Delimiter, Sequence)>),
-    Superscript(Box<Fragment>),
-    Subscript(Box<Fragment>),
+    Delimited(Box<Delimited>),
+    Scripts(Box<Scripts>),
     Fraction(Box<(Sequence, Sequence)>),
+    /// A square root, or another root with its degree first.
+    Radical(Box<(Option<Sequence>, Sequence)>),
+    /// A big operator, such as a sum or an integral, whose scripts are its limits.
+    Operator(char, Limits),
+    /// Upright text with its spaces kept, from `\text`.
+    Text(String),
+    /// An operator written as an upright word, such as `\sin` or `\lim`.
+    NamedOperator(String, Limits),
+    /// An accent over its content, such as a hat or a dot, as a combining character.
+    Accent(char, Box<Sequence>),
+    Overline(Box<Sequence>),
+    Underline(Box<Sequence>),
+    /// A brace over its content, with its superscript above it like a limit.
+    Overbrace(Box<Sequence>),
+    /// A brace under its content, with its subscript below it like a limit.
+    Underbrace(Box<Sequence>),
+    /// Horizontal space in math units, eighteenths of an em, from commands like `\,` or `\quad`.
+    /// It can be negative, like `\!`.
+    Space(i8),
+    /// Cells aligned in rows and columns, from environments like `matrix` or `aligned`.
+    Grid(Box<Grid>),
 }
 
+impl Fragment {
+    /// The class of the atom that the fragment makes, or `None` for space, which doesn't take
+    /// part in the spacing between atoms.
+    pub fn class(&self) -> Option<Class> {
+        match self {
+            Fragment::Char(c) => Some(Class::of_char(*c)),
+            Fragment::Scripts(scripts) => scripts.base.class(),
+            Fragment::Delimited(_) | Fragment::Fraction(_) => Some(Class::Inner),
+            Fragment::Operator(..) | Fragment::NamedOperator(..) => Some(Class::Op),
+            Fragment::Space(_) => None,
+            _ => Some(Class::Ord),
+        }
+    }
+}
+
+/// The class of a math atom, which decides how much space TeX puts between it and its
+/// neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// Ordinary symbols, such as letters and digits.
+    Ord,
+    /// Big operators, such as sums, and named operators, such as `\sin`.
+    Op,
+    /// Binary operators, such as `+`, which become ordinary where they have no left operand.
+    Bin,
+    /// Relations, such as `=`.
+    Rel,
+    Open,
+    Close,
+    Punct,
+    /// Delimited content and fractions.
+    Inner,
+}
+
+impl Class {
+    /// The class of a character on its own.
+    pub fn of_char(c: char) -> Self {
+        match c {
+            '+' | '-' | '−' | '*' | '∗' | '×' | '÷' | '±' | '∓' | '⋅' | '·' | '∘' | '∙' | '∪'
+            | '∩' | '∧' | '∨' | '⊎' | '⊓' | '⊔' | '⊕' | '⊖' | '⊗' | '⊘' | '⊙' | '∖' | '⋆' => {
+                Class::Bin
+            }
+            '=' | '<' | '>' | ':' | '≤' | '≥' | '≠' | '≈' | '≡' | '∼' | '≃' | '≅' | '∝' | '≪'
+            | '≫' | '≺' | '≻' | '∈' | '∉' | '∋' | '⊂' | '⊃' | '⊆' | '⊇' | '←' | '→' | '↔' | '⇐'
+            | '⇒' | '⇔' | '↦' | '∣' | '∥' | '⊥' | '⊢' | '⊨' => Class::Rel,
+            '(' | '[' | '{' | '⟨' | '⌊' | '⌈' => Class::Open,
+            ')' | ']' | '}' | '⟩' | '⌋' | '⌉' | '!' | '?' => Class::Close,
+            ',' | ';' => Class::Punct,
+            _ => Class::Ord,
+        }
+    }
+}
+
+/// A base with a superscript, a subscript or both. Primes after the base, as in `f'`, are part of
+/// the superscript.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Scripts {
+    /// The fragment that the scripts are attached to, which is an empty group for scripts at the
+    /// start of a sequence.
+    base: Fragment,
+    superscript: Option<Fragment>,
+    subscript: Option<Fragment>,
+}
+
+/// Rows of cells, whose columns are as wide as their widest cell.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Grid {
+    rows: Vec<Vec<Sequence>>,
+    /// The columns, which are at least as many as the cells of the longest row.
+    columns: Vec<Column>,
+    /// The extra space between rows in math units, like LaTeX's `\jot` in `aligned`.
+    row_space: i8,
+    /// Whether the cells are set in display style, like in `aligned`, rather than in text style.
+    display: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    align: ColumnAlign,
+    /// The space before the column in math units, which is ignored for the first column.
+    space: i8,
+}
+
+/// How the cells of a column are aligned when they are narrower than the column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// Where the scripts of a big operator are placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limits {
+    /// Above and below the operator in display style, and after it otherwise, like sums.
+    Display,
+    /// Always above and below the operator, set with `\limits`.
+    Always,
+    /// Always after the operator, like integrals or with `\nolimits`.
+    Never,
+}
+
+/// Content between `\left` and `\right` delimiters, which grow to cover it.
 #[derive(Debug, PartialEq, Eq)]
+pub struct Delimited {
+    left: Delimiter,
+    /// The content, split into parts by `\middle` delimiters.
+    parts: Vec<Sequence>,
+    /// The delimiters between the parts.
+    middle: Vec<Delimiter>,
+    right: Delimiter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Delimiter {
     Paren,
     Bracket,
     Brace,
+    Angle,
+    Floor,
+    Ceil,
+    Bar,
+    DoubleBar,
+    /// An invisible delimiter, written `.`, for content that is only delimited on one side.
+    Null,
+}
+
+impl Delimiter {
+    /// The opening and closing characters of the delimiter, or `None` for the null delimiter.
+    pub fn chars(self) -> Option<(char, char)> {
+        match self {
+            Delimiter::Paren => Some(('(', ')')),
+            Delimiter::Bracket => Some(('[', ']')),
+            Delimiter::Brace => Some(('{', '}')),
+            Delimiter::Angle => Some(('⟨', '⟩')),
+            Delimiter::Floor => Some(('⌊', '⌋')),
+            Delimiter::Ceil => Some(('⌈', '⌉')),
+            Delimiter::Bar => Some(('|', '|')),
+            Delimiter::DoubleBar => Some(('‖', '‖')),
+            Delimiter::Null => None,
+        }
+    }
 }
@@ -1,12 +1,11 @@
-use std::iter::Peekable;
+use std::mem;
 
 use thiserror::Error;
 
-use crate::equation::Delimiter;
-
 use super::{
+    alphabet::Alphabet,
     scanner::{LatexScanner, Token},
-    Fragment, Sequence,
+    Column, ColumnAlign, Delimited, Delimiter, Fragment, Grid, Limits, Scripts, Sequence,
 };
 
 #[derive(Debug, Error)]
@@ -19,14 +18,36 @@ pub enum Error {
     ExpectedFragment,
     #[error("expected token: '{0}'")]
     ExpectedToken(String),
+    #[error("expected script")]
+    ExpectedScript,
+    #[error("double superscript")]
+    DoubleSuperscript,
+    #[error("double subscript")]
+    DoubleSubscript,
     #[error("invalid delimiter: '{0}'")]
     InvalidDelimiter(String),
     #[error("unknown keyword: '\\{0}'")]
     UnknownKeyword(String),
+    #[error("unknown environment: '{0}'")]
+    UnknownEnvironment(String),
+    #[error("environment '{0}' ended by '{1}'")]
+    MismatchedEnvironment(String, String),
+    #[error("invalid column alignment: '{0}'")]
+    InvalidColumnAlign(char),
+    #[error("more than {0} columns")]
+    TooManyColumns(usize),
+}
+
+/// Where a delimiter is written, which decides whether opening or closing delimiters are allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Middle,
+    Right,
 }
 
 pub fn parse_latex(source: &str) -> Result<Sequence, Error> {
-    let mut scanner = LatexScanner::new(source).peekable();
+    let mut scanner = LatexScanner::new(source);
 
     let sequence = parse_sequence(&mut scanner)?;
     if scanner.peek().is_some() {
@@ -36,7 +57,7 @@ pub fn parse_latex(source: &str) -> Result<Sequence, Error> {
     Ok(sequence)
 }
 
-fn parse_sequence(scanner: &mut Peekable<LatexScanner>) -> Result<Sequence, Error> {
+fn parse_sequence(scanner: &mut LatexScanner) -> Result<Sequence, Error> {
     let mut fragments = Vec::new();
 
     while let Some(fragment) = parse_fragment(scanner).map_or_else(
@@ -52,41 +73,161 @@ fn parse_sequence(scanner: &mut Peekable<LatexScanner>) -> Result<Sequence, Erro
     Ok(Sequence { fragments })
 }
 
-fn parse_fragment(scanner: &mut Peekable<LatexScanner>) -> Result<Fragment, Error> {
+fn parse_fragment(scanner: &mut LatexScanner) -> Result<Fragment, Error> {
+    let base = match scanner.peek() {
+        Some(Token::Char('^' | '_' | '\'')) => None,
+        _ => Some(parse_atom(scanner)?),
+    };
+
+    let mut superscript = None;
+    let mut subscript = None;
+    let mut primes = Vec::new();
+    loop {
+        match scanner.peek() {
+            Some(Token::Char('^')) => {
+                scanner.next();
+                if superscript.is_some() {
+                    return Err(Error::DoubleSuperscript);
+                }
+                superscript = Some(parse_script(scanner)?);
+            }
+            Some(Token::Char('_')) => {
+                scanner.next();
+                if subscript.is_some() {
+                    return Err(Error::DoubleSubscript);
+                }
+                subscript = Some(parse_script(scanner)?);
+            }
+            // Primes are only allowed before the superscript, which they become the start of.
+            Some(Token::Char('\'')) if superscript.is_none() => {
+                scanner.next();
+                primes.push(Fragment::Char('′'));
+            }
+            Some(Token::Char('\'')) => return Err(Error::DoubleSuperscript),
+            _ => break,
+        }
+    }
+
+    let superscript = match (primes.len(), superscript) {
+        (0, superscript) => superscript,
+        (1, None) => primes.pop(),
+        (_, superscript) => {
+            primes.extend(superscript);
+            Some(Fragment::Group(Box::new(Sequence { fragments: primes })))
+        }
+    };
+    let base = base.unwrap_or_else(|| Fragment::Group(Box::new(Sequence { fragments: vec![] })));
+    if superscript.is_none() && subscript.is_none() {
+        Ok(base)
+    } else {
+        Ok(Fragment::Scripts(Box::new(Scripts {
+            base,
+            superscript,
+            subscript,
+        })))
+    }
+}
+
+/// Parses the script after `^` or `_`, which is a single atom without scripts of its own.
+fn parse_script(scanner: &mut LatexScanner) -> Result<Fragment, Error> {
+    parse_atom(scanner).map_err(|err| match err {
+        Error::ExpectedFragment => Error::ExpectedScript,
+        err => err,
+    })
+}
+
+/// Parses a fragment without its scripts.
+fn parse_atom(scanner: &mut LatexScanner) -> Result<Fragment, Error> {
     match *scanner.peek().ok_or(Error::ExpectedFragment)? {
         Token::Keyword(keyword) => match keyword {
             "left" => {
                 scanner.next();
-                let delimiter = match scanner.next().ok_or(Error::UnexpectedEof)? {
-                    Token::Char('(') => Delimiter::Paren,
-                    Token::Char('[') => Delimiter::Bracket,
-                    Token::Char('{') => Delimiter::Brace,
-                    token => return Err(Error::InvalidDelimiter(token.to_string())),
-                };
-                let right_delimiter = match delimiter {
-                    Delimiter::Paren => ')',
-                    Delimiter::Bracket => ']',
-                    Delimiter::Brace => '}',
-                };
-
-                let sequence = parse_sequence(scanner)?;
+                let left = parse_delimiter(scanner, Side::Left)?;
+                let mut parts = vec![parse_sequence(scanner)?];
+                let mut middle = Vec::new();
+                while scanner.next_if_eq(&Token::Keyword("middle")).is_some() {
+                    middle.push(parse_delimiter(scanner, Side::Middle)?);
+                    parts.push(parse_sequence(scanner)?);
+                }
 
                 expect_token(scanner, Token::Keyword("right"))?;
-                expect_token(scanner, Token::Char(right_delimiter))?;
+                let right = parse_delimiter(scanner, Side::Right)?;
 
-                Ok(Fragment::Delimited(Box::new((delimiter, sequence))))
+                Ok(Fragment::Delimited(Box::new(Delimited {
+                    left,
+                    parts,
+                    middle,
+                    right,
+                })))
+            }
+            "middle" | "right" | "end" | "\\" => Err(Error::ExpectedFragment),
+            "begin" => {
+                scanner.next();
+                parse_environment(scanner)
             }
-            "right" => Err(Error::ExpectedFragment),
             "frac" => {
                 scanner.next();
                 let numerator = parse_group(scanner)?;
                 let denominator = parse_group(scanner)?;
                 Ok(Fragment::Fraction(Box::new((numerator, denominator))))
             }
+            "sqrt" => {
+                scanner.next();
+                let degree = parse_optional_argument(scanner)?;
+                let radicand = parse_group(scanner)?;
+                Ok(Fragment::Radical(Box::new((degree, radicand))))
+            }
+            "overline" | "underline" | "overbrace" | "underbrace" => {
+                scanner.next();
+                let sequence = Box::new(parse_group(scanner)?);
+                Ok(match keyword {
+                    "overline" => Fragment::Overline(sequence),
+                    "underline" => Fragment::Underline(sequence),
+                    "overbrace" => Fragment::Overbrace(sequence),
+                    _ => Fragment::Underbrace(sequence),
+                })
+            }
+            "text" => {
+                scanner.next();
+                Ok(Fragment::Text(parse_text(scanner, true)?))
+            }
+            "operatorname" => {
+                scanner.next();
+                // The starred form takes limits like `\lim` does.
+                let limits = match scanner.next_if_eq(&Token::Char('*')) {
+                    Some(_) => Limits::Display,
+                    None => Limits::Never,
+                };
+                let name = parse_text(scanner, false)?;
+                let limits = parse_limits(scanner, limits);
+                Ok(Fragment::NamedOperator(name, limits))
+            }
             _ => {
-                if let Some(c) = parse_keyword_symbol(keyword) {
+                if let Some(alphabet) = Alphabet::from_command(keyword) {
+                    scanner.next();
+                    let mut sequence = parse_group(scanner)?;
+                    for fragment in &mut sequence.fragments {
+                        apply_alphabet(fragment, alphabet);
+                    }
+                    Ok(Fragment::Group(Box::new(sequence)))
+                } else if let Some(limits) = parse_named_operator(keyword) {
+                    scanner.next();
+                    let limits = parse_limits(scanner, limits);
+                    Ok(Fragment::NamedOperator(keyword.to_owned(), limits))
+                } else if let Some(accent) = parse_accent(keyword) {
+                    scanner.next();
+                    let sequence = parse_group(scanner)?;
+                    Ok(Fragment::Accent(accent, Box::new(sequence)))
+                } else if let Some((c, limits)) = parse_operator(keyword) {
+                    scanner.next();
+                    let limits = parse_limits(scanner, limits);
+                    Ok(Fragment::Operator(c, limits))
+                } else if let Some(space) = parse_space(keyword) {
                     scanner.next();
-                    Ok(Fragment::Char(c))
+                    Ok(Fragment::Space(space))
+                } else if let Some(c) = parse_keyword_symbol(keyword) {
+                    scanner.next();
+                    Ok(Fragment::Char(default_alphabet(c)))
                 } else {
                     Err(Error::UnknownKeyword(keyword.to_owned()))
                 }
@@ -98,25 +239,29 @@ fn parse_fragment(scanner: &mut Peekable<LatexScanner>) -> Result<Fragment, Erro
                 Ok(Fragment::Group(Box::new(sequence)))
             }
             '}' => Err(Error::ExpectedFragment),
-            '^' => {
-                scanner.next();
-                let fragment = parse_fragment(scanner)?;
-                Ok(Fragment::Superscript(Box::new(fragment)))
-            }
-            '_' => {
+            '^' | '_' | '&' => Err(Error::ExpectedFragment),
+            '\'' => {
                 scanner.next();
-                let fragment = parse_fragment(scanner)?;
-                Ok(Fragment::Subscript(Box::new(fragment)))
+                Ok(Fragment::Char('′'))
             }
             _ => {
                 scanner.next();
-                Ok(Fragment::Char(c))
+                Ok(Fragment::Char(default_alphabet(c)))
             }
         },
     }
 }
 
-fn parse_group(scanner: &mut Peekable<LatexScanner>) -> Result<Sequence, Error> {
+/// Sets Latin letters and small Greek letters in italic, as TeX does for letters outside of
+/// `\mathrm` and the like. Capital Greek letters stay upright.
+fn default_alphabet(c: char) -> char {
+    match c {
+        'A'..='Z' | 'a'..='z' | 'α'..='ω' => Alphabet::Italic.apply(c),
+        _ => c,
+    }
+}
+
+fn parse_group(scanner: &mut LatexScanner) -> Result<Sequence, Error> {
     expect_token(scanner, Token::Char('{'))?;
     let sequence = parse_sequence(scanner)?;
     expect_token(scanner, Token::Char('}'))?;
@@ -124,6 +269,315 @@ fn parse_group(scanner: &mut Peekable<LatexScanner>) -> Result<Sequence, Error>
     Ok(sequence)
 }
 
+/// Parses `\limits` or `\nolimits` after an operator, which override where its limits go.
+fn parse_limits(scanner: &mut LatexScanner, limits: Limits) -> Limits {
+    match scanner.next_if(|token| matches!(token, Token::Keyword("limits" | "nolimits"))) {
+        Some(Token::Keyword("limits")) => Limits::Always,
+        Some(_) => Limits::Never,
+        None => limits,
+    }
+}
+
+/// Parses a group as plain text, for `\text` and `\operatorname`. Braces inside it only group.
+/// Runs of whitespace become a single space if `keep_spaces` is set, and are dropped otherwise.
+fn parse_text(scanner: &mut LatexScanner, keep_spaces: bool) -> Result<String, Error> {
+    expect_token(scanner, Token::Char('{'))?;
+    scanner.set_skip_whitespace(!keep_spaces);
+    let text = parse_text_contents(scanner);
+    scanner.set_skip_whitespace(true);
+    text
+}
+
+fn parse_text_contents(scanner: &mut LatexScanner) -> Result<String, Error> {
+    let mut text = String::new();
+    let mut depth = 0;
+    loop {
+        match scanner.next().ok_or(Error::UnexpectedEof)? {
+            Token::Char('{') => depth += 1,
+            Token::Char('}') if depth == 0 => return Ok(text),
+            Token::Char('}') => depth -= 1,
+            Token::Char(c) if c.is_whitespace() => {
+                if !text.ends_with(' ') {
+                    text.push(' ');
+                }
+            }
+            Token::Char(c) => text.push(c),
+            Token::Keyword(" ") => text.push(' '),
+            Token::Keyword(symbol @ ("{" | "}" | "$" | "%" | "&" | "_" | "#")) => {
+                text.push_str(symbol)
+            }
+            Token::Keyword(keyword) => return Err(Error::UnknownKeyword(keyword.to_owned())),
+        }
+    }
+}
+
+/// Sets the letters and digits of a fragment in an alphabet, such as for `\mathbf`, in place of
+/// the default italic. Text and operator names are left alone.
+fn apply_alphabet(fragment: &mut Fragment, alphabet: Alphabet) {
+    let mut apply = |sequence: &mut Sequence| {
+        for fragment in &mut sequence.fragments {
+            apply_alphabet(fragment, alphabet);
+        }
+    };
+    match fragment {
+        Fragment::Char(c) => *c = alphabet.apply(Alphabet::Italic.remove(*c)),
+        Fragment::Group(sequence)
+        | Fragment::Accent(_, sequence)
+        | Fragment::Overline(sequence)
+        | Fragment::Underline(sequence)
+        | Fragment::Overbrace(sequence)
+        | Fragment::Underbrace(sequence) => apply(sequence),
+        Fragment::Delimited(delimited) => delimited.parts.iter_mut().for_each(apply),
+        Fragment::Grid(grid) => grid.rows.iter_mut().flatten().for_each(apply),
+        Fragment::Scripts(scripts) => {
+            apply_alphabet(&mut scripts.base, alphabet);
+            for script in [&mut scripts.superscript, &mut scripts.subscript]
+                .into_iter()
+                .flatten()
+            {
+                apply_alphabet(script, alphabet);
+            }
+        }
+        Fragment::Fraction(fraction) => {
+            apply(&mut fraction.0);
+            apply(&mut fraction.1);
+        }
+        Fragment::Radical(radical) => {
+            radical.0.iter_mut().for_each(&mut apply);
+            apply(&mut radical.1);
+        }
+        Fragment::Operator(..)
+        | Fragment::Text(_)
+        | Fragment::NamedOperator(..)
+        | Fragment::Space(_) => {}
+    }
+}
+
+/// Parses an environment after `\begin`, up to and including its `\end`.
+fn parse_environment(scanner: &mut LatexScanner) -> Result<Fragment, Error> {
+    let name = parse_text(scanner, false)?;
+    let array_columns = match name.as_str() {
+        "array" => parse_columns(scanner)?,
+        "matrix" | "pmatrix" | "bmatrix" | "cases" | "aligned" => Vec::new(),
+        _ => return Err(Error::UnknownEnvironment(name)),
+    };
+    let mut rows = parse_rows(scanner)?;
+    let end = parse_text(scanner, false)?;
+    if end != name {
+        return Err(Error::MismatchedEnvironment(name, end));
+    }
+
+    let count = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let column = |align, space| Column { align, space };
+    let (columns, row_space, display) = match name.as_str() {
+        "array" => (array_columns, 0, false),
+        "cases" => (
+            vec![column(ColumnAlign::Left, 0), column(ColumnAlign::Left, 18)],
+            4,
+            false,
+        ),
+        "aligned" => {
+            // Columns come in pairs, aligned at the relation that starts the second column.
+            let columns = (0..count)
+                .map(|i| match i % 2 {
+                    0 => column(ColumnAlign::Right, 36),
+                    _ => column(ColumnAlign::Left, 0),
+                })
+                .collect();
+            // Like amsmath, the second column starts with an empty group so that the relation
+            // at its start is spaced as if something came before it.
+            for row in &mut rows {
+                for cell in row.iter_mut().skip(1).step_by(2) {
+                    let empty = Fragment::Group(Box::new(Sequence { fragments: vec![] }));
+                    cell.fragments.insert(0, empty);
+                }
+            }
+            (columns, 5, true)
+        }
+        _ => (vec![column(ColumnAlign::Center, 18); count], 0, false),
+    };
+    if count > columns.len() {
+        return Err(Error::TooManyColumns(columns.len()));
+    }
+
+    let grid = Fragment::Grid(Box::new(Grid {
+        rows,
+        columns,
+        row_space,
+        display,
+    }));
+    let (left, right) = match name.as_str() {
+        "pmatrix" => (Delimiter::Paren, Delimiter::Paren),
+        "bmatrix" => (Delimiter::Bracket, Delimiter::Bracket),
+        "cases" => (Delimiter::Brace, Delimiter::Null),
+        _ => return Ok(grid),
+    };
+    Ok(Fragment::Delimited(Box::new(Delimited {
+        left,
+        parts: vec![Sequence {
+            fragments: vec![grid],
+        }],
+        middle: Vec::new(),
+        right,
+    })))
+}
+
+/// Parses the column alignments of an `array`, such as `{lcr}`.
+fn parse_columns(scanner: &mut LatexScanner) -> Result<Vec<Column>, Error> {
+    parse_text(scanner, false)?
+        .chars()
+        .map(|c| {
+            let align = match c {
+                'l' => ColumnAlign::Left,
+                'c' => ColumnAlign::Center,
+                'r' => ColumnAlign::Right,
+                _ => return Err(Error::InvalidColumnAlign(c)),
+            };
+            Ok(Column { align, space: 18 })
+        })
+        .collect()
+}
+
+/// Parses the cells of an environment, with `&` between cells and `\\` between rows, up to and
+/// including `\end`.
+fn parse_rows(scanner: &mut LatexScanner) -> Result<Vec<Vec<Sequence>>, Error> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    loop {
+        row.push(parse_sequence(scanner)?);
+        match scanner.next() {
+            Some(Token::Char('&')) => {}
+            Some(Token::Keyword("\\")) => rows.push(mem::take(&mut row)),
+            Some(Token::Keyword("end")) => break,
+            Some(token) => return Err(Error::ExpectedToken(token.to_string())),
+            None => return Err(Error::UnexpectedEof),
+        }
+    }
+    // A `\\` at the end of the last row doesn't start another one.
+    let empty = matches!(row.as_slice(), [cell] if cell.fragments.is_empty());
+    if !empty || rows.is_empty() {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Parses an argument in square brackets, such as the degree of a root, if there is one.
+fn parse_optional_argument(scanner: &mut LatexScanner) -> Result<Option<Sequence>, Error> {
+    if scanner.next_if_eq(&Token::Char('[')).is_none() {
+        return Ok(None);
+    }
+
+    let mut fragments = Vec::new();
+    loop {
+        match scanner.peek().ok_or(Error::UnexpectedEof)? {
+            Token::Char(']') => {
+                scanner.next();
+                return Ok(Some(Sequence { fragments }));
+            }
+            // Anything that ends a sequence, like a `}`, can't end the argument.
+            _ => fragments.push(parse_fragment(scanner).map_err(|err| match err {
+                Error::ExpectedFragment => Error::ExpectedToken("]".to_owned()),
+                err => err,
+            })?),
+        }
+    }
+}
+
+/// Parses the delimiter after `\left`, `\middle` or `\right`. Opening delimiters are only allowed
+/// on the left and closing ones on the right, while bars and the null delimiter `.` are allowed
+/// anywhere.
+fn parse_delimiter(scanner: &mut LatexScanner, side: Side) -> Result<Delimiter, Error> {
+    let token = scanner.next().ok_or(Error::UnexpectedEof)?;
+    let (delimiter, allowed) = match token {
+        Token::Char('(') => (Delimiter::Paren, Side::Left),
+        Token::Char(')') => (Delimiter::Paren, Side::Right),
+        Token::Char('[') | Token::Keyword("lbrack") => (Delimiter::Bracket, Side::Left),
+        Token::Char(']') | Token::Keyword("rbrack") => (Delimiter::Bracket, Side::Right),
+        Token::Keyword("{" | "lbrace") => (Delimiter::Brace, Side::Left),
+        Token::Keyword("}" | "rbrace") => (Delimiter::Brace, Side::Right),
+        Token::Char('<') | Token::Keyword("langle") => (Delimiter::Angle, Side::Left),
+        Token::Char('>') | Token::Keyword("rangle") => (Delimiter::Angle, Side::Right),
+        Token::Keyword("lfloor") => (Delimiter::Floor, Side::Left),
+        Token::Keyword("rfloor") => (Delimiter::Floor, Side::Right),
+        Token::Keyword("lceil") => (Delimiter::Ceil, Side::Left),
+        Token::Keyword("rceil") => (Delimiter::Ceil, Side::Right),
+        Token::Char('|') | Token::Keyword("vert" | "lvert" | "rvert") => (Delimiter::Bar, side),
+        Token::Keyword("|" | "Vert" | "lVert" | "rVert") => (Delimiter::DoubleBar, side),
+        Token::Char('.') => (Delimiter::Null, side),
+        _ => return Err(Error::InvalidDelimiter(token.to_string())),
+    };
+
+    if allowed == side {
+        Ok(delimiter)
+    } else {
+        Err(Error::InvalidDelimiter(token.to_string()))
+    }
+}
+
+/// Where the limits of a named operator go, for the operators that LaTeX defines.
+fn parse_named_operator(keyword: &str) -> Option<Limits> {
+    match keyword {
+        "arccos" | "arcsin" | "arctan" | "arg" | "cos" | "cosh" | "cot" | "coth" | "csc"
+        | "deg" | "dim" | "exp" | "hom" | "ker" | "lg" | "ln" | "log" | "sec" | "sin" | "sinh"
+        | "tan" | "tanh" => Some(Limits::Never),
+        "det" | "gcd" | "inf" | "lim" | "liminf" | "limsup" | "max" | "min" | "Pr" | "sup" => {
+            Some(Limits::Display)
+        }
+        _ => None,
+    }
+}
+
+/// The combining character of an accent.
+fn parse_accent(keyword: &str) -> Option<char> {
+    match keyword {
+        "grave" => Some('\u{300}'),
+        "acute" => Some('\u{301}'),
+        "hat" => Some('\u{302}'),
+        "tilde" => Some('\u{303}'),
+        "bar" => Some('\u{304}'),
+        "breve" => Some('\u{306}'),
+        "dot" => Some('\u{307}'),
+        "ddot" => Some('\u{308}'),
+        "check" => Some('\u{30C}'),
+        "vec" => Some('\u{20D7}'),
+        _ => None,
+    }
+}
+
+/// Parses the keyword of a spacing command to its width in math units.
+fn parse_space(keyword: &str) -> Option<i8> {
+    match keyword {
+        "," => Some(3),
+        ":" | ">" => Some(4),
+        ";" => Some(5),
+        "!" => Some(-3),
+        "quad" => Some(18),
+        "qquad" => Some(36),
+        _ => None,
+    }
+}
+
+/// The character of a big operator, and where its limits go unless `\limits` or `\nolimits`
+/// follows it.
+fn parse_operator(keyword: &str) -> Option<(char, Limits)> {
+    match keyword {
+        "sum" => Some(('∑', Limits::Display)),
+        "prod" => Some(('∏', Limits::Display)),
+        "coprod" => Some(('∐', Limits::Display)),
+        "bigcup" => Some(('⋃', Limits::Display)),
+        "bigcap" => Some(('⋂', Limits::Display)),
+        "bigvee" => Some(('⋁', Limits::Display)),
+        "bigwedge" => Some(('⋀', Limits::Display)),
+        "bigoplus" => Some(('⨁', Limits::Display)),
+        "bigotimes" => Some(('⨂', Limits::Display)),
+        "int" => Some(('∫', Limits::Never)),
+        "iint" => Some(('∬', Limits::Never)),
+        "iiint" => Some(('∭', Limits::Never)),
+        "oint" => Some(('∮', Limits::Never)),
+        _ => None,
+    }
+}
+
 fn parse_keyword_symbol(keyword: &str) -> Option<char> {
     match keyword {
         "alpha" => Some('α'),
@@ -208,7 +662,7 @@ fn parse_keyword_symbol(keyword: &str) -> Option<char> {
     }
 }
 
-pub fn expect_token(scanner: &mut Peekable<LatexScanner>, expected: Token) -> Result<(), Error> {
+pub fn expect_token(scanner: &mut LatexScanner, expected: Token) -> Result<(), Error> {
     match scanner.next() {
         Some(token) if token == expected => Ok(()),
         Some(token) => Err(Error::ExpectedToken(token.to_string())),
@@ -227,11 +681,14 @@ mod tests {
             sequence,
             Sequence {
                 fragments: vec![
-                    Fragment::Char('E'),
+                    Fragment::Char('𝐸'),
                     Fragment::Char('='),
-                    Fragment::Char('m'),
-                    Fragment::Char('c'),
-                    Fragment::Superscript(Box::new(Fragment::Char('2'))),
+                    Fragment::Char('𝑚'),
+                    Fragment::Scripts(Box::new(Scripts {
+                        base: Fragment::Char('𝑐'),
+                        superscript: Some(Fragment::Char('2')),
+                        subscript: None,
+                    })),
                 ],
             },
         );
@@ -246,27 +703,33 @@ mod tests {
             sequence,
             Sequence {
                 fragments: vec![
-                    Fragment::Char('α'),
-                    Fragment::Subscript(Box::new(Fragment::Char('β'))),
+                    Fragment::Scripts(Box::new(Scripts {
+                        base: Fragment::Char('𝛼'),
+                        superscript: None,
+                        subscript: Some(Fragment::Char('𝛽')),
+                    })),
                     Fragment::Char('('),
-                    Fragment::Char('γ'),
+                    Fragment::Char('𝛾'),
                     Fragment::Char(','),
-                    Fragment::Char('θ'),
+                    Fragment::Char('𝜃'),
                     Fragment::Char(')'),
                     Fragment::Char('='),
                     Fragment::Fraction(Box::new((
                         Sequence {
-                            fragments: vec![Fragment::Char('β')],
+                            fragments: vec![Fragment::Char('𝛽')],
                         },
                         Sequence {
-                            fragments: vec![Fragment::Char('γ')],
+                            fragments: vec![Fragment::Char('𝛾')],
                         },
                     ))),
                     Fragment::Char('⋅'),
-                    Fragment::Char('e'),
-                    Fragment::Superscript(Box::new(Fragment::Group(Box::new(Sequence {
-                        fragments: vec![Fragment::Char('i'), Fragment::Char('θ')],
-                    })))),
+                    Fragment::Scripts(Box::new(Scripts {
+                        base: Fragment::Char('𝑒'),
+                        superscript: Some(Fragment::Group(Box::new(Sequence {
+                            fragments: vec![Fragment::Char('𝑖'), Fragment::Char('𝜃')],
+                        }))),
+                        subscript: None,
+                    })),
                 ],
             },
         );
@@ -281,43 +744,429 @@ mod tests {
             sequence,
             Sequence {
                 fragments: vec![
-                    Fragment::Char('α'),
-                    Fragment::Subscript(Box::new(Fragment::Char('β'))),
+                    Fragment::Scripts(Box::new(Scripts {
+                        base: Fragment::Char('𝛼'),
+                        superscript: None,
+                        subscript: Some(Fragment::Char('𝛽')),
+                    })),
                     Fragment::Char('('),
-                    Fragment::Char('γ'),
+                    Fragment::Char('𝛾'),
                     Fragment::Char(','),
-                    Fragment::Char('θ'),
+                    Fragment::Char('𝜃'),
                     Fragment::Char(')'),
                     Fragment::Char('='),
-                    Fragment::Delimited(Box::new((
-                        Delimiter::Bracket,
-                        Sequence {
+                    Fragment::Delimited(Box::new(Delimited {
+                        left: Delimiter::Bracket,
+                        parts: vec![Sequence {
                             fragments: vec![Fragment::Fraction(Box::new((
                                 Sequence {
-                                    fragments: vec![Fragment::Char('β')],
+                                    fragments: vec![Fragment::Char('𝛽')],
                                 },
                                 Sequence {
-                                    fragments: vec![Fragment::Char('γ')],
+                                    fragments: vec![Fragment::Char('𝛾')],
                                 },
                             )))],
-                        }
-                    ))),
+                        }],
+                        middle: vec![],
+                        right: Delimiter::Bracket,
+                    })),
                     Fragment::Char('⋅'),
-                    Fragment::Delimited(Box::new((
-                        Delimiter::Paren,
-                        Sequence {
+                    Fragment::Delimited(Box::new(Delimited {
+                        left: Delimiter::Paren,
+                        parts: vec![Sequence {
+                            fragments: vec![Fragment::Scripts(Box::new(Scripts {
+                                base: Fragment::Char('𝑒'),
+                                superscript: Some(Fragment::Group(Box::new(Sequence {
+                                    fragments: vec![Fragment::Char('𝑖'), Fragment::Char('𝜃')],
+                                }))),
+                                subscript: None,
+                            }))],
+                        }],
+                        middle: vec![],
+                        right: Delimiter::Paren,
+                    })),
+                ],
+            },
+        );
+    }
+
+    #[test]
+    fn test_delimiters() {
+        let sequence = parse_latex(r"\left\langle a \middle\| b \middle| c \right.").unwrap();
+        let char = |c| Sequence {
+            fragments: vec![Fragment::Char(c)],
+        };
+        assert_eq!(
+            sequence,
+            Sequence {
+                fragments: vec![Fragment::Delimited(Box::new(Delimited {
+                    left: Delimiter::Angle,
+                    parts: vec![char('𝑎'), char('𝑏'), char('𝑐')],
+                    middle: vec![Delimiter::DoubleBar, Delimiter::Bar],
+                    right: Delimiter::Null,
+                }))],
+            },
+        );
+
+        let sequence = parse_latex(r"\left\lfloor x \right\rceil").unwrap();
+        let Fragment::Delimited(delimited) = &sequence.fragments[0] else {
+            panic!("expected delimited fragment");
+        };
+        assert_eq!(
+            (delimited.left, delimited.right),
+            (Delimiter::Floor, Delimiter::Ceil)
+        );
+
+        assert!(matches!(
+            parse_latex(r"\left) x \right("),
+            Err(Error::InvalidDelimiter(_))
+        ));
+        assert!(matches!(
+            parse_latex(r"\left( x \middle( y \right)"),
+            Err(Error::InvalidDelimiter(_))
+        ));
+        assert!(matches!(
+            parse_latex(r"\left( x"),
+            Err(Error::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn test_radicals_and_operators() {
+        let sequence = parse_latex(r"\sqrt{x} \sqrt[n+1]{y}").unwrap();
+        let char = |c| Sequence {
+            fragments: vec![Fragment::Char(c)],
+        };
+        assert_eq!(
+            sequence,
+            Sequence {
+                fragments: vec![
+                    Fragment::Radical(Box::new((None, char('𝑥')))),
+                    Fragment::Radical(Box::new((
+                        Some(Sequence {
                             fragments: vec![
-                                Fragment::Char('e'),
-                                Fragment::Superscript(Box::new(Fragment::Group(Box::new(
-                                    Sequence {
-                                        fragments: vec![Fragment::Char('i'), Fragment::Char('θ')],
-                                    },
-                                )))),
+                                Fragment::Char('𝑛'),
+                                Fragment::Char('+'),
+                                Fragment::Char('1'),
                             ],
-                        },
+                        }),
+                        char('𝑦'),
                     ))),
                 ],
             },
         );
+
+        let sequence = parse_latex(r"\sum_{i=0}^n \int\limits_a^b \oint \bigcup\nolimits").unwrap();
+        let Fragment::Scripts(scripts) = &sequence.fragments[0] else {
+            panic!("expected scripts");
+        };
+        assert_eq!(scripts.base, Fragment::Operator('∑', Limits::Display));
+        assert_eq!(
+            sequence.fragments[1..],
+            [
+                Fragment::Scripts(Box::new(Scripts {
+                    base: Fragment::Operator('∫', Limits::Always),
+                    superscript: Some(Fragment::Char('𝑏')),
+                    subscript: Some(Fragment::Char('𝑎')),
+                })),
+                Fragment::Operator('∮', Limits::Never),
+                Fragment::Operator('⋃', Limits::Never),
+            ]
+        );
+
+        assert!(matches!(
+            parse_latex(r"\sqrt[3{x}"),
+            Err(Error::UnexpectedEof)
+        ));
+        assert!(matches!(
+            parse_latex(r"{\sqrt[n}x"),
+            Err(Error::ExpectedToken(token)) if token == "]"
+        ));
+    }
+
+    #[test]
+    fn test_scripts_and_primes() {
+        let scripts = |base, superscript, subscript| {
+            Fragment::Scripts(Box::new(Scripts {
+                base,
+                superscript,
+                subscript,
+            }))
+        };
+        let group = |fragments| Fragment::Group(Box::new(Sequence { fragments }));
+
+        let sequence = parse_latex(r"x_i^2 y^2_j f' g''^2 {}^3").unwrap();
+        assert_eq!(
+            sequence.fragments,
+            [
+                scripts(
+                    Fragment::Char('𝑥'),
+                    Some(Fragment::Char('2')),
+                    Some(Fragment::Char('𝑖')),
+                ),
+                scripts(
+                    Fragment::Char('𝑦'),
+                    Some(Fragment::Char('2')),
+                    Some(Fragment::Char('𝑗')),
+                ),
+                scripts(Fragment::Char('𝑓'), Some(Fragment::Char('′')), None),
+                scripts(
+                    Fragment::Char('𝑔'),
+                    Some(group(vec![
+                        Fragment::Char('′'),
+                        Fragment::Char('′'),
+                        Fragment::Char('2'),
+                    ])),
+                    None,
+                ),
+                scripts(group(vec![]), Some(Fragment::Char('3')), None),
+            ]
+        );
+
+        assert!(matches!(
+            parse_latex(r"x^2^3"),
+            Err(Error::DoubleSuperscript)
+        ));
+        assert!(matches!(
+            parse_latex(r"x^2'"),
+            Err(Error::DoubleSuperscript)
+        ));
+        assert!(matches!(parse_latex(r"x_1_2"), Err(Error::DoubleSubscript)));
+        assert!(matches!(parse_latex(r"x^"), Err(Error::ExpectedScript)));
+    }
+
+    #[test]
+    fn test_accents() {
+        let sequence = parse_latex(r"\hat{x} \vec{v} \overline{z} \underbrace{a+b}_n").unwrap();
+        let char = |c| {
+            Box::new(Sequence {
+                fragments: vec![Fragment::Char(c)],
+            })
+        };
+        assert_eq!(
+            sequence.fragments,
+            [
+                Fragment::Accent('\u{302}', char('𝑥')),
+                Fragment::Accent('\u{20D7}', char('𝑣')),
+                Fragment::Overline(char('𝑧')),
+                Fragment::Scripts(Box::new(Scripts {
+                    base: Fragment::Underbrace(Box::new(Sequence {
+                        fragments: vec![
+                            Fragment::Char('𝑎'),
+                            Fragment::Char('+'),
+                            Fragment::Char('𝑏'),
+                        ],
+                    })),
+                    superscript: None,
+                    subscript: Some(Fragment::Char('𝑛')),
+                })),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_alphabets() {
+        let sequence = parse_latex(r"\mathbf{v}_1 \mathbb{R}^n \mathcal{L}(\mathrm{d}x)").unwrap();
+        let group = |c| {
+            Fragment::Group(Box::new(Sequence {
+                fragments: vec![Fragment::Char(c)],
+            }))
+        };
+        assert_eq!(
+            sequence.fragments[..2],
+            [
+                Fragment::Scripts(Box::new(Scripts {
+                    base: group('𝐯'),
+                    superscript: None,
+                    subscript: Some(Fragment::Char('1')),
+                })),
+                Fragment::Scripts(Box::new(Scripts {
+                    base: group('ℝ'),
+                    superscript: Some(Fragment::Char('𝑛')),
+                    subscript: None,
+                })),
+            ]
+        );
+        assert_eq!(sequence.fragments[2], group('ℒ'));
+        assert_eq!(sequence.fragments[4], group('d'));
+
+        // Alphabets reach into nested fragments, but not into text.
+        let sequence = parse_latex(r"\mathbf{\frac{a}{2^b} \text{c}}").unwrap();
+        assert_eq!(
+            sequence.fragments[0],
+            Fragment::Group(Box::new(Sequence {
+                fragments: vec![
+                    Fragment::Fraction(Box::new((
+                        Sequence {
+                            fragments: vec![Fragment::Char('𝐚')],
+                        },
+                        Sequence {
+                            fragments: vec![Fragment::Scripts(Box::new(Scripts {
+                                base: Fragment::Char('𝟐'),
+                                superscript: Some(Fragment::Char('𝐛')),
+                                subscript: None,
+                            }))],
+                        },
+                    ))),
+                    Fragment::Text("c".to_owned()),
+                ],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_text_and_named_operators() {
+        let sequence = parse_latex(r"x \text{ if  {x}\}  is even} \sin x").unwrap();
+        assert_eq!(
+            sequence.fragments,
+            [
+                Fragment::Char('𝑥'),
+                Fragment::Text(" if x} is even".to_owned()),
+                Fragment::NamedOperator("sin".to_owned(), Limits::Never),
+                Fragment::Char('𝑥'),
+            ]
+        );
+
+        let sequence =
+            parse_latex(r"\lim_{n} \max\nolimits \operatorname{sgn} \operatorname*{arg max}")
+                .unwrap();
+        let Fragment::Scripts(scripts) = &sequence.fragments[0] else {
+            panic!("expected scripts");
+        };
+        assert_eq!(
+            scripts.base,
+            Fragment::NamedOperator("lim".to_owned(), Limits::Display)
+        );
+        assert_eq!(
+            sequence.fragments[1..],
+            [
+                Fragment::NamedOperator("max".to_owned(), Limits::Never),
+                Fragment::NamedOperator("sgn".to_owned(), Limits::Never),
+                Fragment::NamedOperator("argmax".to_owned(), Limits::Display),
+            ]
+        );
+
+        assert!(matches!(parse_latex(r"\text{x"), Err(Error::UnexpectedEof)));
+        assert!(matches!(
+            parse_latex(r"\text{\alpha}"),
+            Err(Error::UnknownKeyword(_))
+        ));
+    }
+
+    #[test]
+    fn test_spaces() {
+        let sequence = parse_latex(r"a\,b\:c\;d\!e\quad f\qquad").unwrap();
+        assert_eq!(
+            sequence.fragments,
+            vec![
+                Fragment::Char('𝑎'),
+                Fragment::Space(3),
+                Fragment::Char('𝑏'),
+                Fragment::Space(4),
+                Fragment::Char('𝑐'),
+                Fragment::Space(5),
+                Fragment::Char('𝑑'),
+                Fragment::Space(-3),
+                Fragment::Char('𝑒'),
+                Fragment::Space(18),
+                Fragment::Char('𝑓'),
+                Fragment::Space(36),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_environments() {
+        let chars = |source: &str| Sequence {
+            fragments: source.chars().map(Fragment::Char).collect(),
+        };
+        let column = |align| Column { align, space: 18 };
+
+        let sequence = parse_latex(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}").unwrap();
+        assert_eq!(
+            sequence.fragments,
+            vec![Fragment::Delimited(Box::new(Delimited {
+                left: Delimiter::Paren,
+                parts: vec![Sequence {
+                    fragments: vec![Fragment::Grid(Box::new(Grid {
+                        rows: vec![vec![chars("𝑎"), chars("𝑏")], vec![chars("𝑐"), chars("𝑑")],],
+                        columns: vec![column(ColumnAlign::Center); 2],
+                        row_space: 0,
+                        display: false,
+                    }))],
+                }],
+                middle: vec![],
+                right: Delimiter::Paren,
+            }))]
+        );
+
+        // The trailing `\\` doesn't add a row, and the right column of each pair starts with an
+        // empty group.
+        let sequence = parse_latex(r"\begin{aligned} x &= 1 \\ &= y \\ \end{aligned}").unwrap();
+        let Fragment::Grid(grid) = &sequence.fragments[0] else {
+            panic!("expected grid: {sequence:?}");
+        };
+        let empty = || Fragment::Group(Box::new(chars("")));
+        assert_eq!(
+            grid.rows,
+            vec![
+                vec![
+                    chars("𝑥"),
+                    Sequence {
+                        fragments: vec![empty(), Fragment::Char('='), Fragment::Char('1')],
+                    },
+                ],
+                vec![
+                    chars(""),
+                    Sequence {
+                        fragments: vec![empty(), Fragment::Char('='), Fragment::Char('𝑦')],
+                    },
+                ],
+            ]
+        );
+        assert_eq!(grid.columns[0].align, ColumnAlign::Right);
+        assert_eq!(grid.columns[1].align, ColumnAlign::Left);
+        assert!(grid.display);
+
+        let sequence = parse_latex(r"\begin{array}{lr} 1 & 2 \\ 3 \end{array}").unwrap();
+        let Fragment::Grid(grid) = &sequence.fragments[0] else {
+            panic!("expected grid: {sequence:?}");
+        };
+        assert_eq!(
+            grid.columns,
+            vec![column(ColumnAlign::Left), column(ColumnAlign::Right)]
+        );
+        assert_eq!(grid.rows[1], vec![chars("3")]);
+
+        let sequence = parse_latex(r"\begin{cases} 1 & x > 0 \\ 0 \end{cases}").unwrap();
+        let Fragment::Delimited(delimited) = &sequence.fragments[0] else {
+            panic!("expected delimited: {sequence:?}");
+        };
+        assert_eq!(
+            (delimited.left, delimited.right),
+            (Delimiter::Brace, Delimiter::Null)
+        );
+
+        assert!(matches!(
+            parse_latex(r"\begin{foo} a \end{foo}"),
+            Err(Error::UnknownEnvironment(_))
+        ));
+        assert!(matches!(
+            parse_latex(r"\begin{matrix} a \end{bmatrix}"),
+            Err(Error::MismatchedEnvironment(..))
+        ));
+        assert!(matches!(
+            parse_latex(r"\begin{array}{lx} a \end{array}"),
+            Err(Error::InvalidColumnAlign('x'))
+        ));
+        assert!(matches!(
+            parse_latex(r"\begin{cases} a & b & c \end{cases}"),
+            Err(Error::TooManyColumns(2))
+        ));
+        assert!(matches!(
+            parse_latex(r"\begin{matrix} a & b"),
+            Err(Error::UnexpectedEof)
+        ));
+        assert!(matches!(parse_latex(r"a & b"), Err(Error::ExpectedEof)));
     }
 }
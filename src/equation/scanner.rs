@@ -3,9 +3,12 @@ use std::fmt;
 #[derive(Debug)]
 pub struct LatexScanner<'a> {
     source: &'a str,
+    /// Whether whitespace between tokens is skipped, which is turned off inside `\text`.
+    skip_whitespace: bool,
+    peeked: Option<Option<Token<'a>>>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Token<'a> {
     Keyword(&'a str),
     Char(char),
@@ -13,15 +16,40 @@ pub enum Token<'a> {
 
 impl<'a> LatexScanner<'a> {
     pub fn new(source: &'a str) -> Self {
-        LatexScanner { source }
+        LatexScanner {
+            source,
+            skip_whitespace: true,
+            peeked: None,
+        }
     }
-}
 
-impl<'a> Iterator for LatexScanner<'a> {
-    type Item = Token<'a>;
+    /// Turns skipping whitespace on or off, from the first token that hasn't been peeked at.
+    pub fn set_skip_whitespace(&mut self, skip_whitespace: bool) {
+        self.skip_whitespace = skip_whitespace;
+    }
 
-    fn next(&mut self) -> Option<Token<'a>> {
-        loop {
+    pub fn peek(&mut self) -> Option<&Token<'a>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.scan());
+        }
+        self.peeked.as_ref().unwrap().as_ref()
+    }
+
+    /// Consumes the next token if it satisfies the predicate.
+    pub fn next_if(&mut self, predicate: impl FnOnce(&Token<'a>) -> bool) -> Option<Token<'a>> {
+        match self.peek() {
+            Some(token) if predicate(token) => self.next(),
+            _ => None,
+        }
+    }
+
+    /// Consumes the next token if it is equal to `expected`.
+    pub fn next_if_eq(&mut self, expected: &Token) -> Option<Token<'a>> {
+        self.next_if(|token| token == expected)
+    }
+
+    fn scan(&mut self) -> Option<Token<'a>> {
+        while self.skip_whitespace {
             let next = self.source.chars().next()?;
             if next.is_whitespace() {
                 self.source = &self.source[next.len_utf8()..];
@@ -33,10 +61,14 @@ impl<'a> Iterator for LatexScanner<'a> {
         let next = self.source.chars().next()?;
         if next == '\\' {
             self.source = &self.source[next.len_utf8()..];
-            let len = self
-                .source
-                .find(|c: char| !c.is_alphabetic())
-                .unwrap_or(self.source.len());
+            let len = match self.source.chars().next() {
+                // Control symbols, such as `\|` or `\{`, are a single non-alphabetic character.
+                Some(c) if !c.is_alphabetic() => c.len_utf8(),
+                _ => self
+                    .source
+                    .find(|c: char| !c.is_alphabetic())
+                    .unwrap_or(self.source.len()),
+            };
             let keyword = &self.source[..len];
             self.source = &self.source[len..];
             Some(Token::Keyword(keyword))
@@ -47,6 +79,17 @@ impl<'a> Iterator for LatexScanner<'a> {
     }
 }
 
+impl<'a> Iterator for LatexScanner<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        match self.peeked.take() {
+            Some(token) => token,
+            None => self.scan(),
+        }
+    }
+}
+
 impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -73,4 +116,39 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_control_symbol() {
+        let scanner = LatexScanner::new(r"\left\{ a \|b\right\}");
+        let tokens: Vec<_> = scanner.collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword("left"),
+                Token::Keyword("{"),
+                Token::Char('a'),
+                Token::Keyword("|"),
+                Token::Char('b'),
+                Token::Keyword("right"),
+                Token::Keyword("}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_whitespace() {
+        let mut scanner = LatexScanner::new(r"a \text{b c}");
+        assert_eq!(scanner.peek(), Some(&Token::Char('a')));
+        assert_eq!(scanner.next_if_eq(&Token::Char('b')), None);
+        assert_eq!(scanner.next(), Some(Token::Char('a')));
+        assert_eq!(scanner.next(), Some(Token::Keyword("text")));
+        assert_eq!(scanner.next(), Some(Token::Char('{')));
+
+        scanner.set_skip_whitespace(false);
+        let tokens: Vec<_> = scanner.by_ref().take(3).collect();
+        assert_eq!(
+            tokens,
+            vec![Token::Char('b'), Token::Char(' '), Token::Char('c')]
+        );
+    }
 }
@@ -0,0 +1,1106 @@
+use std::collections::BTreeSet;
+
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use super::{generate::VecExt, util::Reader};
+
+// Top DICT and Private DICT operators, with two-byte operators prefixed by 12
+const CHARSET: u16 = 15;
+const ENCODING: u16 = 16;
+const CHAR_STRINGS: u16 = 17;
+const PRIVATE: u16 = 18;
+const SUBRS: u16 = 19;
+const VS_INDEX: u16 = 22;
+const BLEND: u16 = 23;
+const VSTORE: u16 = 24;
+const ROS: u16 = 0x0c1e;
+const CID_COUNT: u16 = 0x0c22;
+const FD_ARRAY: u16 = 0x0c24;
+const FD_SELECT: u16 = 0x0c25;
+
+/// A variation store with one item variation data and no regions, preceded by its length, for
+/// CFF2 tables written in tests.
+#[cfg(test)]
+const EMPTY_VARIATION_STORE: &[u8] = &[
+    0, 22, // length
+    0, 1, 0, 0, 0, 12, 0, 1, 0, 0, 0, 16, // header
+    0, 0, 0, 0, // region list
+    0, 0, 0, 0, 0, 0, // item variation data
+];
+
+/// How deeply subroutines can be nested, from the Type 2 charstring limits.
+const MAX_SUBR_DEPTH: usize = 10;
+
+/// The parts of a CFF or CFF2 table that are rebuilt when it is subset.
+struct Cff<'a> {
+    cff2: bool,
+    names: Vec<&'a [u8]>,
+    top: Vec<Entry>,
+    strings: Vec<&'a [u8]>,
+    global_subrs: Vec<&'a [u8]>,
+    char_strings: Vec<&'a [u8]>,
+    /// The string IDs of the glyph names, if the glyphs are named rather than keyed by CID.
+    charset: Option<Vec<u16>>,
+    /// The Font DICTs of a CID-keyed or CFF2 font, which each have their own Private DICT.
+    font_dicts: Vec<Vec<Entry>>,
+    privates: Vec<Private<'a>>,
+    /// The Font DICT of each glyph.
+    fd_select: Vec<usize>,
+    /// The CFF2 variation store, including its length.
+    variation_store: Option<&'a [u8]>,
+    /// How many regions a blend in each item variation data of the store has a delta for.
+    region_counts: Vec<usize>,
+}
+
+/// A Private DICT and its local subroutines.
+struct Private<'a> {
+    entries: Vec<Entry>,
+    subrs: Vec<&'a [u8]>,
+    vs_index: usize,
+}
+
+/// A DICT entry, with its operands left encoded.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    operator: u16,
+    operands: Vec<u8>,
+}
+
+/// Where the parts of the table that are referred to by offset start.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Offsets {
+    charset: usize,
+    fd_select: usize,
+    char_strings: usize,
+    fd_array: usize,
+    variation_store: usize,
+    /// The size and offset of each Private DICT.
+    privates: Vec<(usize, usize)>,
+}
+
+/// Builds a CFF table with the glyphs in `glyph_map`, renumbered in order.
+///
+/// Subroutines are called by number, so they keep their places, but the ones that no glyph in the
+/// subset calls are emptied. Returns `None` if the table is malformed.
+pub(super) fn subset_cff(data: &[u8], glyph_map: &[GlyphId]) -> Option<Vec<u8>> {
+    let mut cff = Cff::parse(data)?;
+    cff.subset(glyph_map)?;
+    Some(cff.build())
+}
+
+/// Builds a CID-keyed CFF table from a CFF2 table, with the glyphs in `glyph_map` outlined at the
+/// face's variation coordinates, or at the default ones if it isn't instanced.
+///
+/// PDF only describes CFF fonts, and CFF2 charstrings can't be used in one, so the outlines are
+/// written again without hints or subroutines, and the blends in the Private DICTs are left out.
+/// Glyph `i` of the table has CID `i`.
+pub(super) fn convert_cff2(
+    data: &[u8],
+    glyph_map: &[GlyphId],
+    face: &Face,
+    ps_name: &str,
+) -> Option<Vec<u8>> {
+    let char_strings = glyph_map
+        .iter()
+        .map(|&glyph_id| {
+            let mut char_string = CharString::default();
+            face.outline_glyph(glyph_id, &mut char_string);
+            char_string.contents.push(14); // endchar
+            char_string.contents
+        })
+        .collect::<Vec<_>>();
+
+    let mut cff = Cff::parse2(data)?;
+    cff.subset(glyph_map)?;
+
+    // The ROS must come first, and names the Adobe-Identity-0 character collection by the string
+    // IDs of the first two strings after the standard ones.
+    let mut top = Vec::new();
+    set_entry(&mut top, ROS, &[391, 392, 0]);
+    top.extend(cff.top.into_iter().filter(|entry| entry.operator != VSTORE));
+
+    let cff = Cff {
+        cff2: false,
+        names: vec![ps_name.as_bytes()],
+        top,
+        strings: vec![b"Adobe", b"Identity"],
+        global_subrs: Vec::new(),
+        char_strings: char_strings.iter().map(Vec::as_slice).collect(),
+        charset: None,
+        privates: cff
+            .privates
+            .into_iter()
+            .map(|mut private| {
+                private.subrs.clear();
+                // The operator after a blend takes its operands from the blend, so it's left with
+                // none.
+                private.entries.retain(|entry| {
+                    entry.operator != BLEND
+                        && entry.operator != VS_INDEX
+                        && !entry.operands.is_empty()
+                });
+                private
+            })
+            .collect(),
+        variation_store: None,
+        region_counts: Vec::new(),
+        ..cff
+    };
+    Some(cff.build())
+}
+
+impl<'a> Cff<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut reader = Reader::new(data, 2);
+        reader.offset = reader.u8()? as usize; // header size
+        let names = read_index(&mut reader, false)?;
+        let top_dicts = read_index(&mut reader, false)?;
+        let strings = read_index(&mut reader, false)?;
+        let global_subrs = read_index(&mut reader, false)?;
+
+        let top = read_dict(top_dicts.first()?)?;
+        let char_strings = read_index_at(data, offset(&top, CHAR_STRINGS)?, false)?;
+        let glyph_count = char_strings.len();
+
+        let (charset, font_dicts, fd_select) = if top.iter().any(|entry| entry.operator == ROS) {
+            let font_dicts = read_index_at(data, offset(&top, FD_ARRAY)?, false)?
+                .into_iter()
+                .map(read_dict)
+                .collect::<Option<Vec<_>>>()?;
+            let fd_select = read_fd_select(data, offset(&top, FD_SELECT)?, glyph_count)?;
+            (None, font_dicts, fd_select)
+        } else {
+            let charset_offset = offset(&top, CHARSET).unwrap_or_default();
+            let charset = read_charset(data, charset_offset, glyph_count)?;
+            (Some(charset), Vec::new(), vec![0; glyph_count])
+        };
+
+        let privates = if font_dicts.is_empty() {
+            vec![Private::parse(data, &top, false)?]
+        } else {
+            font_dicts
+                .iter()
+                .map(|font_dict| Private::parse(data, font_dict, false))
+                .collect::<Option<Vec<_>>>()?
+        };
+
+        Some(Self {
+            cff2: false,
+            names,
+            top,
+            strings,
+            global_subrs,
+            char_strings,
+            charset,
+            font_dicts,
+            privates,
+            fd_select,
+            variation_store: None,
+            region_counts: Vec::new(),
+        })
+    }
+
+    fn parse2(data: &'a [u8]) -> Option<Self> {
+        let mut reader = Reader::new(data, 2);
+        let header_size = reader.u8()? as usize;
+        let top_dict_len = reader.u16()? as usize;
+        let top = read_dict(data.get(header_size..header_size + top_dict_len)?)?;
+        let mut reader = Reader::new(data, header_size + top_dict_len);
+        let global_subrs = read_index(&mut reader, true)?;
+
+        let char_strings = read_index_at(data, offset(&top, CHAR_STRINGS)?, true)?;
+        let glyph_count = char_strings.len();
+        let font_dicts = read_index_at(data, offset(&top, FD_ARRAY)?, true)?
+            .into_iter()
+            .map(read_dict)
+            .collect::<Option<Vec<_>>>()?;
+        let fd_select = match offset(&top, FD_SELECT) {
+            Some(fd_select_offset) => read_fd_select(data, fd_select_offset, glyph_count)?,
+            None => vec![0; glyph_count],
+        };
+        let privates = font_dicts
+            .iter()
+            .map(|font_dict| Private::parse(data, font_dict, true))
+            .collect::<Option<Vec<_>>>()?;
+
+        let (variation_store, region_counts) = match offset(&top, VSTORE) {
+            Some(store_offset) => {
+                let len = Reader::new(data, store_offset).u16()? as usize;
+                let store = data.get(store_offset..store_offset + 2 + len)?;
+                (Some(store), read_region_counts(&store[2..])?)
+            }
+            None => (None, Vec::new()),
+        };
+
+        Some(Self {
+            cff2: true,
+            names: Vec::new(),
+            top,
+            strings: Vec::new(),
+            global_subrs,
+            char_strings,
+            charset: None,
+            font_dicts,
+            privates,
+            fd_select,
+            variation_store,
+            region_counts,
+        })
+    }
+
+    /// Keeps the glyphs of the subset and the subroutines that they call.
+    fn subset(&mut self, glyph_map: &[GlyphId]) -> Option<()> {
+        let mut used_global = BTreeSet::new();
+        let mut used_local = vec![BTreeSet::new(); self.privates.len()];
+
+        let mut char_strings = Vec::new();
+        let mut fd_select = Vec::new();
+        for &glyph_id in glyph_map {
+            let char_string = *self.char_strings.get(glyph_id.0 as usize)?;
+            let fd = *self.fd_select.get(glyph_id.0 as usize)?;
+            let private = self.privates.get(fd)?;
+
+            let mut walker = Walker {
+                global_subrs: &self.global_subrs,
+                local_subrs: &private.subrs,
+                used_global: &mut used_global,
+                used_local: &mut used_local[fd],
+                region_counts: &self.region_counts,
+                region_count: self
+                    .region_counts
+                    .get(private.vs_index)
+                    .copied()
+                    .unwrap_or_default(),
+                cff2: self.cff2,
+                stack: Vec::new(),
+                stems: 0,
+            };
+            walker.walk(char_string, 0)?;
+
+            char_strings.push(char_string);
+            fd_select.push(fd);
+        }
+
+        if let Some(charset) = &mut self.charset {
+            *charset = glyph_map
+                .iter()
+                .map(|glyph_id| charset.get(glyph_id.0 as usize).copied())
+                .collect::<Option<Vec<_>>>()?;
+        }
+        self.char_strings = char_strings;
+        self.fd_select = fd_select;
+        self.global_subrs = subset_subrs(&self.global_subrs, &used_global);
+        for (private, used) in self.privates.iter_mut().zip(&used_local) {
+            private.subrs = subset_subrs(&private.subrs, used);
+        }
+
+        Some(())
+    }
+
+    fn build(&self) -> Vec<u8> {
+        // The offsets are written at a fixed size, so writing the table once with placeholders
+        // finds where everything goes.
+        let (_, offsets) = self.write(&Offsets::default());
+        let (table, _) = self.write(&offsets);
+        table
+    }
+
+    /// Writes the table with the given offsets, returning where the parts actually went.
+    fn write(&self, offsets: &Offsets) -> (Vec<u8>, Offsets) {
+        let private_offsets = |i: usize| {
+            let (size, offset) = offsets.privates.get(i).copied().unwrap_or_default();
+            [size as i32, offset as i32]
+        };
+
+        let mut top = self.top.clone();
+        set_entry(&mut top, CHAR_STRINGS, &[offsets.char_strings as i32]);
+        if self.cff2 {
+            set_entry(&mut top, FD_ARRAY, &[offsets.fd_array as i32]);
+            if self.variation_store.is_some() {
+                set_entry(&mut top, VSTORE, &[offsets.variation_store as i32]);
+            }
+        } else {
+            set_entry(&mut top, CHARSET, &[offsets.charset as i32]);
+            // Custom encodings refer to the old glyph IDs, and glyphs are chosen by CID anyway.
+            set_entry(&mut top, ENCODING, &[]);
+            if self.font_dicts.is_empty() {
+                set_entry(&mut top, PRIVATE, &private_offsets(0));
+            } else {
+                set_entry(&mut top, FD_ARRAY, &[offsets.fd_array as i32]);
+                set_entry(&mut top, CID_COUNT, &[self.char_strings.len() as i32]);
+            }
+        }
+        if self.has_fd_select() {
+            set_entry(&mut top, FD_SELECT, &[offsets.fd_select as i32]);
+        } else {
+            set_entry(&mut top, FD_SELECT, &[]);
+        }
+        let top = write_dict(&top);
+
+        let mut contents = Vec::new();
+        if self.cff2 {
+            contents.extend([2, 0, 5]); // version and header size
+            contents.push_u16(top.len() as u16);
+            contents.extend(&top);
+        } else {
+            contents.extend([1, 0, 4, 4]); // version, header size and offset size
+            write_index(&mut contents, &self.names, false);
+            write_index(&mut contents, &[top], false);
+            write_index(&mut contents, &self.strings, false);
+        }
+        write_index(&mut contents, &self.global_subrs, self.cff2);
+
+        let mut new_offsets = Offsets::default();
+        if let Some(variation_store) = self.variation_store {
+            new_offsets.variation_store = contents.len();
+            contents.extend(variation_store);
+        }
+        if !self.cff2 {
+            new_offsets.charset = contents.len();
+            write_charset(
+                &mut contents,
+                self.charset.as_deref(),
+                self.char_strings.len(),
+            );
+        }
+        if self.has_fd_select() {
+            new_offsets.fd_select = contents.len();
+            contents.push(0); // format
+            contents.extend(self.fd_select.iter().map(|&fd| fd as u8));
+        }
+
+        new_offsets.char_strings = contents.len();
+        write_index(&mut contents, &self.char_strings, self.cff2);
+
+        if !self.font_dicts.is_empty() {
+            let font_dicts = self
+                .font_dicts
+                .iter()
+                .enumerate()
+                .map(|(i, font_dict)| {
+                    let mut font_dict = font_dict.clone();
+                    set_entry(&mut font_dict, PRIVATE, &private_offsets(i));
+                    write_dict(&font_dict)
+                })
+                .collect::<Vec<_>>();
+            new_offsets.fd_array = contents.len();
+            write_index(&mut contents, &font_dicts, self.cff2);
+        }
+
+        for private in &self.privates {
+            let (private, size) = private.write(self.cff2);
+            new_offsets.privates.push((size, contents.len()));
+            contents.extend(private);
+        }
+
+        (contents, new_offsets)
+    }
+
+    fn has_fd_select(&self) -> bool {
+        if self.cff2 {
+            self.font_dicts.len() > 1
+        } else {
+            !self.font_dicts.is_empty()
+        }
+    }
+}
+
+impl<'a> Private<'a> {
+    /// Reads the Private DICT that a Top DICT or Font DICT points to.
+    fn parse(data: &'a [u8], font_dict: &[Entry], cff2: bool) -> Option<Self> {
+        let [size, offset] = integers(font_dict, PRIVATE)?[..] else {
+            return None;
+        };
+        let (size, offset) = (usize::try_from(size).ok()?, usize::try_from(offset).ok()?);
+        let entries = read_dict(data.get(offset..offset + size)?)?;
+
+        // The local subroutines are found relative to the Private DICT.
+        let subrs = match integers(&entries, SUBRS).as_deref() {
+            Some(&[subrs_offset]) => {
+                read_index_at(data, offset + usize::try_from(subrs_offset).ok()?, cff2)?
+            }
+            _ => Vec::new(),
+        };
+        let vs_index = match integers(&entries, VS_INDEX).as_deref() {
+            Some(&[vs_index]) => usize::try_from(vs_index).ok()?,
+            _ => 0,
+        };
+
+        Some(Self {
+            entries,
+            subrs,
+            vs_index,
+        })
+    }
+
+    /// Writes the DICT followed by the local subroutines, returning the size of the DICT.
+    fn write(&self, cff2: bool) -> (Vec<u8>, usize) {
+        let mut entries = self.entries.clone();
+        if self.subrs.is_empty() {
+            set_entry(&mut entries, SUBRS, &[]);
+        } else {
+            set_entry(&mut entries, SUBRS, &[0]);
+            let size = write_dict(&entries).len();
+            set_entry(&mut entries, SUBRS, &[size as i32]);
+        }
+
+        let mut contents = write_dict(&entries);
+        let size = contents.len();
+        if !self.subrs.is_empty() {
+            write_index(&mut contents, &self.subrs, cff2);
+        }
+
+        (contents, size)
+    }
+}
+
+/// Follows a charstring through its subroutine calls, recording which subroutines it uses.
+struct Walker<'a, 'b> {
+    global_subrs: &'b [&'a [u8]],
+    local_subrs: &'b [&'a [u8]],
+    used_global: &'b mut BTreeSet<usize>,
+    used_local: &'b mut BTreeSet<usize>,
+    region_counts: &'b [usize],
+    region_count: usize,
+    cff2: bool,
+    stack: Vec<f32>,
+    /// The number of stem hints so far, which sets the length of hint masks.
+    stems: usize,
+}
+
+impl Walker<'_, '_> {
+    /// Returns whether the glyph ended inside the charstring.
+    fn walk(&mut self, char_string: &[u8], depth: usize) -> Option<bool> {
+        if depth > MAX_SUBR_DEPTH {
+            return None;
+        }
+
+        let mut reader = Reader::new(char_string, 0);
+        while let Some(b0) = reader.u8() {
+            match b0 {
+                28 => {
+                    let value = reader.u16()? as i16;
+                    self.stack.push(value as f32);
+                }
+                32..=246 => self.stack.push(b0 as f32 - 139.0),
+                247..=250 => {
+                    let b1 = reader.u8()?;
+                    self.stack
+                        .push((b0 as f32 - 247.0) * 256.0 + b1 as f32 + 108.0);
+                }
+                251..=254 => {
+                    let b1 = reader.u8()?;
+                    self.stack
+                        .push(-(b0 as f32 - 251.0) * 256.0 - b1 as f32 - 108.0);
+                }
+                255 => {
+                    let value = reader.u32()? as i32;
+                    self.stack.push(value as f32 / 65536.0);
+                }
+                // hstem, vstem, hstemhm and vstemhm
+                1 | 3 | 18 | 23 => {
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                }
+                // hintmask and cntrmask, which can follow vstem hints without an operator
+                19 | 20 => {
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    reader.bytes(self.stems.div_ceil(8))?;
+                }
+                // callsubr and callgsubr
+                10 | 29 => {
+                    let subrs = if b0 == 10 {
+                        self.local_subrs
+                    } else {
+                        self.global_subrs
+                    };
+                    let index = self.stack.pop()? as i32 + subr_bias(subrs.len());
+                    let index = usize::try_from(index).ok()?;
+                    let subr = *subrs.get(index)?;
+                    if b0 == 10 {
+                        self.used_local.insert(index);
+                    } else {
+                        self.used_global.insert(index);
+                    }
+
+                    if self.walk(subr, depth + 1)? {
+                        return Some(true);
+                    }
+                }
+                // return
+                11 if !self.cff2 => return Some(false),
+                // endchar
+                14 if !self.cff2 => return Some(true),
+                // vsindex
+                15 if self.cff2 => {
+                    let vs_index = self.stack.pop()? as usize;
+                    self.region_count = *self.region_counts.get(vs_index)?;
+                    self.stack.clear();
+                }
+                // blend, which leaves one value for each group of deltas
+                16 if self.cff2 => {
+                    let count = self.stack.pop()? as usize;
+                    let len = self.stack.len().checked_sub(count * self.region_count)?;
+                    self.stack.truncate(len);
+                }
+                12 => {
+                    reader.u8()?;
+                    self.stack.clear();
+                }
+                _ => self.stack.clear(),
+            }
+        }
+
+        Some(false)
+    }
+}
+
+/// A Type 2 charstring written from an outline, with coordinates rounded to whole units.
+#[derive(Debug, Default)]
+struct CharString {
+    contents: Vec<u8>,
+    x: i32,
+    y: i32,
+}
+
+impl CharString {
+    /// Pushes the points relative to the one before, followed by the operator.
+    fn push(&mut self, points: &[(f32, f32)], operator: u8) {
+        for &(x, y) in points {
+            let (x, y) = (x.round() as i32, y.round() as i32);
+            self.number(x - self.x);
+            self.number(y - self.y);
+            (self.x, self.y) = (x, y);
+        }
+        self.contents.push(operator);
+    }
+
+    fn number(&mut self, value: i32) {
+        match value {
+            -107..=107 => self.contents.push((value + 139) as u8),
+            108..=1131 => self.contents.extend([
+                (247 + (value - 108) / 256) as u8,
+                ((value - 108) % 256) as u8,
+            ]),
+            -1131..=-108 => self.contents.extend([
+                (251 + (-value - 108) / 256) as u8,
+                ((-value - 108) % 256) as u8,
+            ]),
+            _ => {
+                self.contents.push(28);
+                self.contents.push_u16(value as i16 as u16);
+            }
+        }
+    }
+}
+
+impl OutlineBuilder for CharString {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.push(&[(x, y)], 21); // rmoveto
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(&[(x, y)], 5); // rlineto
+    }
+
+    /// Writes a quadratic curve as the cubic curve that traces it.
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x0, y0) = (self.x as f32, self.y as f32);
+        let control1 = (x0 + 2.0 / 3.0 * (x1 - x0), y0 + 2.0 / 3.0 * (y1 - y0));
+        let control2 = (x + 2.0 / 3.0 * (x1 - x), y + 2.0 / 3.0 * (y1 - y));
+        self.push(&[control1, control2, (x, y)], 8); // rrcurveto
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.push(&[(x1, y1), (x2, y2), (x, y)], 8); // rrcurveto
+    }
+
+    // Contours are closed by the next moveto, or the end of the charstring.
+    fn close(&mut self) {}
+}
+
+/// The number added to subroutine numbers in charstrings, which depends on how many there are.
+fn subr_bias(count: usize) -> i32 {
+    if count < 1240 {
+        107
+    } else if count < 33900 {
+        1131
+    } else {
+        32768
+    }
+}
+
+/// Keeps the subroutines that are used, and empties the rest.
+fn subset_subrs<'a>(subrs: &[&'a [u8]], used: &BTreeSet<usize>) -> Vec<&'a [u8]> {
+    subrs
+        .iter()
+        .enumerate()
+        .map(|(i, &subr)| if used.contains(&i) { subr } else { &[] })
+        .collect()
+}
+
+/// Reads the Font DICT index of each glyph.
+fn read_fd_select(data: &[u8], offset: usize, glyph_count: usize) -> Option<Vec<usize>> {
+    let mut reader = Reader::new(data, offset);
+    let format = reader.u8()?;
+    if format == 0 {
+        let fds = reader.bytes(glyph_count)?;
+        return Some(fds.iter().map(|&fd| fd as usize).collect());
+    }
+
+    let wide = match format {
+        3 => false,
+        4 => true,
+        _ => return None,
+    };
+    let read_glyph = |reader: &mut Reader| {
+        if wide {
+            reader.u32().map(|glyph| glyph as usize)
+        } else {
+            reader.u16().map(|glyph| glyph as usize)
+        }
+    };
+
+    let range_count = read_glyph(&mut reader)?;
+    let mut fd_select = vec![0; glyph_count];
+    let mut first = read_glyph(&mut reader)?;
+    for _ in 0..range_count {
+        let fd = if wide {
+            reader.u16()? as usize
+        } else {
+            reader.u8()? as usize
+        };
+        let next = read_glyph(&mut reader)?;
+        for glyph_fd in fd_select.get_mut(first..next.min(glyph_count))? {
+            *glyph_fd = fd;
+        }
+        first = next;
+    }
+
+    Some(fd_select)
+}
+
+/// Reads the string IDs of the glyph names, which are listed from glyph 1.
+fn read_charset(data: &[u8], offset: usize, glyph_count: usize) -> Option<Vec<u16>> {
+    // Glyph 0 is always .notdef.
+    let mut sids = vec![0];
+
+    // The predefined charsets are only used by fonts whose glyphs are in the same order.
+    if offset <= 2 {
+        sids.extend(1..glyph_count as u16);
+        return Some(sids);
+    }
+
+    let mut reader = Reader::new(data, offset);
+    match reader.u8()? {
+        0 => {
+            for _ in 1..glyph_count {
+                sids.push(reader.u16()?);
+            }
+        }
+        format @ (1 | 2) => {
+            while sids.len() < glyph_count {
+                let first = reader.u16()?;
+                let left = if format == 1 {
+                    reader.u8()? as u16
+                } else {
+                    reader.u16()?
+                };
+                sids.extend((0..=left).map(|i| first.wrapping_add(i)));
+            }
+            sids.truncate(glyph_count);
+        }
+        _ => return None,
+    }
+
+    Some(sids)
+}
+
+/// Writes a charset, which maps the glyphs to the CIDs with the same numbers if they have no names.
+fn write_charset(contents: &mut Vec<u8>, sids: Option<&[u16]>, glyph_count: usize) {
+    match sids {
+        Some(sids) => {
+            contents.push(0); // format
+            for &sid in sids.iter().skip(1) {
+                contents.push_u16(sid);
+            }
+        }
+        None => {
+            contents.push(2); // format
+            if glyph_count > 1 {
+                contents.push_u16(1); // first
+                contents.push_u16(glyph_count as u16 - 2); // glyphs left
+            }
+        }
+    }
+}
+
+/// Reads how many regions each item variation data in a variation store has.
+fn read_region_counts(store: &[u8]) -> Option<Vec<usize>> {
+    let mut reader = Reader::new(store, 4); // after the format and region list offset
+    reader.u32()?;
+    let count = reader.u16()?;
+    (0..count)
+        .map(|_| {
+            let offset = reader.u32()? as usize;
+            // after the item count and word delta count
+            Reader::new(store, offset + 4).u16().map(usize::from)
+        })
+        .collect()
+}
+
+fn read_index_at(data: &[u8], offset: usize, cff2: bool) -> Option<Vec<&[u8]>> {
+    read_index(&mut Reader::new(data, offset), cff2)
+}
+
+/// Reads the items of an INDEX, which is a count followed by offsets into the data after it.
+fn read_index<'a>(reader: &mut Reader<'a>, cff2: bool) -> Option<Vec<&'a [u8]>> {
+    let count = if cff2 {
+        reader.u32()? as usize
+    } else {
+        reader.u16()? as usize
+    };
+    if count == 0 {
+        return Some(Vec::new());
+    }
+
+    let offset_size = reader.u8()?;
+    let offsets = (0..=count)
+        .map(|_| reader.offset(offset_size))
+        .collect::<Option<Vec<_>>>()?;
+
+    // The offsets count from the byte before the data.
+    let start = reader.offset - 1;
+    let items = offsets
+        .iter()
+        .zip(&offsets[1..])
+        .map(|(&item_start, &item_end)| reader.data.get(start + item_start..start + item_end))
+        .collect::<Option<Vec<_>>>()?;
+    reader.offset = start + offsets[count];
+
+    Some(items)
+}
+
+fn write_index<T: AsRef<[u8]>>(contents: &mut Vec<u8>, items: &[T], cff2: bool) {
+    if cff2 {
+        contents.push_u32(items.len() as u32);
+    } else {
+        contents.push_u16(items.len() as u16);
+    }
+    if items.is_empty() {
+        return;
+    }
+
+    let data_len = items.iter().map(|item| item.as_ref().len()).sum::<usize>();
+    let offset_size = match data_len + 1 {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x10000..=0xffffff => 3,
+        _ => 4,
+    };
+    contents.push(offset_size as u8);
+
+    let mut offset = 1;
+    contents.extend_from_slice(&(offset as u32).to_be_bytes()[4 - offset_size..]);
+    for item in items {
+        offset += item.as_ref().len();
+        contents.extend_from_slice(&(offset as u32).to_be_bytes()[4 - offset_size..]);
+    }
+    for item in items {
+        contents.extend_from_slice(item.as_ref());
+    }
+}
+
+fn read_dict(data: &[u8]) -> Option<Vec<Entry>> {
+    let mut reader = Reader::new(data, 0);
+    let mut entries = Vec::new();
+    let mut operands_start = 0;
+    while let Some(b0) = reader.u8() {
+        match b0 {
+            0..=27 => {
+                let operands = data[operands_start..reader.offset - 1].to_vec();
+                let operator = if b0 == 12 {
+                    0x0c00 | reader.u8()? as u16
+                } else {
+                    b0 as u16
+                };
+                entries.push(Entry { operator, operands });
+                operands_start = reader.offset;
+            }
+            28 => {
+                reader.bytes(2)?;
+            }
+            29 => {
+                reader.bytes(4)?;
+            }
+            // A real number, which ends with a nibble of 0xf.
+            30 => loop {
+                let b = reader.u8()?;
+                if b & 0x0f == 0x0f || b >> 4 == 0x0f {
+                    break;
+                }
+            },
+            32..=246 => {}
+            247..=254 => {
+                reader.u8()?;
+            }
+            _ => return None,
+        }
+    }
+
+    Some(entries)
+}
+
+fn write_dict(entries: &[Entry]) -> Vec<u8> {
+    let mut contents = Vec::new();
+    for entry in entries {
+        contents.extend(&entry.operands);
+        if entry.operator > 0xff {
+            contents.extend([12, entry.operator as u8]);
+        } else {
+            contents.push(entry.operator as u8);
+        }
+    }
+    contents
+}
+
+/// Decodes the operands of an entry, if they are all integers.
+fn integers(entries: &[Entry], operator: u16) -> Option<Vec<i32>> {
+    let entry = entries.iter().find(|entry| entry.operator == operator)?;
+
+    let mut reader = Reader::new(&entry.operands, 0);
+    let mut values = Vec::new();
+    while let Some(b0) = reader.u8() {
+        let value = match b0 {
+            28 => reader.u16()? as i16 as i32,
+            29 => reader.u32()? as i32,
+            32..=246 => b0 as i32 - 139,
+            247..=250 => (b0 as i32 - 247) * 256 + reader.u8()? as i32 + 108,
+            251..=254 => -(b0 as i32 - 251) * 256 - reader.u8()? as i32 - 108,
+            _ => return None,
+        };
+        values.push(value);
+    }
+
+    Some(values)
+}
+
+fn offset(entries: &[Entry], operator: u16) -> Option<usize> {
+    match integers(entries, operator)?[..] {
+        [offset] => usize::try_from(offset).ok(),
+        _ => None,
+    }
+}
+
+/// Replaces the operands of an entry, or adds it if it's missing. Entries with no operands are
+/// removed.
+///
+/// The values are always written in five bytes, so that the size of the DICT doesn't depend on
+/// them.
+fn set_entry(entries: &mut Vec<Entry>, operator: u16, values: &[i32]) {
+    if values.is_empty() {
+        entries.retain(|entry| entry.operator != operator);
+        return;
+    }
+
+    let mut operands = Vec::new();
+    for &value in values {
+        operands.push(29);
+        operands.extend(value.to_be_bytes());
+    }
+    match entries.iter_mut().find(|entry| entry.operator == operator) {
+        Some(entry) => entry.operands = operands,
+        None => entries.push(Entry { operator, operands }),
+    }
+}
+
+/// Rebuilds a TrueType font with CFF or CFF2 outlines, since the fonts at hand for testing are all
+/// TrueType.
+#[cfg(test)]
+pub(crate) fn with_cff_outlines(font: &super::Font, cff2: bool) -> Vec<u8> {
+    use std::collections::BTreeMap;
+
+    use ttf_parser::Tag;
+
+    let char_strings = (0..font.face.number_of_glyphs())
+        .map(|glyph_id| {
+            let mut char_string = CharString::default();
+            font.face.outline_glyph(GlyphId(glyph_id), &mut char_string);
+            if !cff2 {
+                char_string.contents.push(14); // endchar
+            }
+            char_string.contents
+        })
+        .collect::<Vec<_>>();
+    let cff = Cff {
+        cff2,
+        names: if cff2 {
+            vec![]
+        } else {
+            vec![font.ps_name.as_bytes()]
+        },
+        top: Vec::new(),
+        strings: Vec::new(),
+        global_subrs: Vec::new(),
+        char_strings: char_strings.iter().map(Vec::as_slice).collect(),
+        charset: None,
+        font_dicts: if cff2 { vec![Vec::new()] } else { vec![] },
+        privates: vec![Private {
+            entries: Vec::new(),
+            subrs: Vec::new(),
+            vs_index: 0,
+        }],
+        fd_select: vec![0; char_strings.len()],
+        variation_store: cff2.then_some(EMPTY_VARIATION_STORE),
+        region_counts: if cff2 { vec![0] } else { vec![] },
+    };
+
+    let raw_face = font.face.raw_face();
+    let mut tables = raw_face
+        .table_records
+        .into_iter()
+        .filter(|record| ![b"glyf", b"loca"].contains(&&record.tag.to_bytes()))
+        .map(|record| (record.tag, raw_face.table(record.tag).unwrap().to_owned()))
+        .collect::<BTreeMap<_, _>>();
+    let tag = if cff2 { b"CFF2" } else { b"CFF " };
+    tables.insert(Tag::from_bytes(tag), cff.build());
+    super::generate::write_sfnt(u32::from_be_bytes(*b"OTTO"), tables)
+}
+
+impl Reader<'_> {
+    /// Reads an offset of one to four bytes.
+    fn offset(&mut self, size: u8) -> Option<usize> {
+        if !(1..=4).contains(&size) {
+            return None;
+        }
+        let bytes = self.bytes(size as usize)?;
+        Some(
+            bytes
+                .iter()
+                .fold(0, |offset, &byte| offset << 8 | byte as usize),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{cff, cff2, GlyphId, OutlineBuilder};
+
+    use super::{subset_cff, Cff, CharString, Private, EMPTY_VARIATION_STORE};
+    use crate::font::util::Path;
+
+    /// A font with an empty glyph, a glyph that calls a local subroutine and a glyph that calls a
+    /// global subroutine.
+    fn test_cff(cff2: bool) -> Cff<'static> {
+        // 0 0 rmoveto 100 0 rlineto, then callsubr or callgsubr with subroutine 0
+        let (glyph1, glyph2): (&[u8], &[u8]) = if cff2 {
+            (
+                &[139, 139, 21, 239, 139, 5, 32, 10],
+                &[139, 139, 21, 32, 29],
+            )
+        } else {
+            (
+                &[139, 139, 21, 239, 139, 5, 32, 10, 14],
+                &[139, 139, 21, 32, 29, 14],
+            )
+        };
+        // 0 100 rlineto and -100 0 rlineto, which return in CFF
+        let (local_subr, global_subr): (&[u8], &[u8]) = if cff2 {
+            (&[139, 239, 5], &[39, 139, 5])
+        } else {
+            (&[139, 239, 5, 11], &[39, 139, 5, 11])
+        };
+
+        Cff {
+            cff2,
+            names: if cff2 { vec![] } else { vec![b"Test"] },
+            top: Vec::new(),
+            strings: Vec::new(),
+            global_subrs: vec![global_subr],
+            char_strings: vec![if cff2 { &[] } else { &[14] }, glyph1, glyph2],
+            // .notdef, space and exclam
+            charset: if cff2 { None } else { Some(vec![0, 1, 2]) },
+            font_dicts: if cff2 { vec![Vec::new()] } else { vec![] },
+            privates: vec![Private {
+                entries: Vec::new(),
+                subrs: vec![local_subr, local_subr],
+                vs_index: 0,
+            }],
+            fd_select: vec![0; 3],
+            variation_store: cff2.then_some(EMPTY_VARIATION_STORE),
+            region_counts: if cff2 { vec![0] } else { vec![] },
+        }
+    }
+
+    #[test]
+    fn test_subset_cff() {
+        let data = test_cff(false).build();
+        let table = cff::Table::parse(&data).unwrap();
+        let outline = |table: &cff::Table, glyph_id| {
+            let mut path = Path::default();
+            table.outline(GlyphId(glyph_id), &mut path).unwrap();
+            path.0
+        };
+
+        let subset = subset_cff(&data, &[GlyphId(0), GlyphId(2)]).unwrap();
+        let subset_table = cff::Table::parse(&subset).unwrap();
+        assert_eq!(subset_table.number_of_glyphs(), 2);
+        assert_eq!(outline(&subset_table, 1), outline(&table, 2));
+        assert_eq!(subset_table.glyph_name(GlyphId(1)), Some("exclam"));
+
+        // No glyph in the subset calls the local subroutines, so they are emptied.
+        let cff = Cff::parse(&subset).unwrap();
+        assert_eq!(cff.privates[0].subrs, vec![&[] as &[u8]; 2]);
+        assert_eq!(cff.global_subrs, test_cff(false).global_subrs);
+    }
+
+    #[test]
+    fn test_subset_cff2() {
+        let data = test_cff(true).build();
+        let table = cff2::Table::parse(&data).unwrap();
+        let outline = |table: &cff2::Table, glyph_id| {
+            let mut path = Path::default();
+            table.outline(&[], GlyphId(glyph_id), &mut path).unwrap();
+            path.0
+        };
+
+        let mut cff = Cff::parse2(&data).unwrap();
+        cff.subset(&[GlyphId(0), GlyphId(1)]).unwrap();
+        let subset = cff.build();
+        let subset_table = cff2::Table::parse(&subset).unwrap();
+        assert_eq!(outline(&subset_table, 1), outline(&table, 1));
+
+        let cff = Cff::parse2(&subset).unwrap();
+        assert_eq!(cff.char_strings.len(), 2);
+        assert_eq!(cff.global_subrs, vec![&[] as &[u8]]);
+        assert_eq!(
+            cff.privates[0].subrs[0],
+            test_cff(true).privates[0].subrs[0]
+        );
+        assert!(cff.privates[0].subrs[1].is_empty());
+    }
+
+    #[test]
+    fn test_char_string() {
+        let mut char_string = CharString::default();
+        char_string.move_to(10.0, 20.0);
+        char_string.line_to(500.0, -20.4);
+        char_string.quad_to(800.0, 100.0, 500.0, 1000.0);
+        char_string.curve_to(400.0, 900.0, 300.0, 900.0, 10.0, 20.0);
+        char_string.close();
+
+        let mut cff = test_cff(true);
+        cff.char_strings[1] = &char_string.contents;
+        let data = cff.build();
+        let table = cff2::Table::parse(&data).unwrap();
+        let mut path = Path::default();
+        table.outline(&[], GlyphId(1), &mut path).unwrap();
+
+        // The quadratic curve is written as a cubic curve, and the coordinates are rounded.
+        assert_eq!(
+            path.0,
+            "M 10 20 L 500 -20 C 700 60 700 400 500 1000 C 400 900 300 900 10 20 "
+        );
+    }
+}
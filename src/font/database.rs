@@ -0,0 +1,360 @@
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ttf_parser::{name::Name, name_id, Face, Language, Style, Width};
+
+use super::{woff, Error, Font};
+
+/// The file extensions that are loaded when scanning a directory.
+const FONT_EXTENSIONS: [&str; 6] = ["ttf", "otf", "ttc", "otc", "woff", "woff2"];
+
+/// A collection of font faces, loaded from files or memory, that can be looked up by family,
+/// weight, width and style.
+#[derive(Default)]
+pub struct FontDatabase {
+    sources: Vec<Vec<u8>>,
+    faces: Vec<FaceInfo>,
+}
+
+/// Identifies a face in a [`FontDatabase`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FaceId(usize);
+
+/// The properties of a face, read from its `name` and `OS/2` tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaceInfo {
+    /// The file that the face was loaded from, if any.
+    pub path: Option<PathBuf>,
+    /// The index of the face in its collection, or 0 if the file isn't a collection.
+    pub index: u32,
+    /// The family names of the face, starting with the typographic family name.
+    pub families: Vec<String>,
+    pub subfamily: String,
+    pub ps_name: String,
+    /// The weight, from 100 for thin to 900 for black, with 400 being regular and 700 bold.
+    pub weight: u16,
+    pub width: Width,
+    pub style: Style,
+    source: usize,
+}
+
+/// A description of the face to look up in a [`FontDatabase`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontQuery<'a> {
+    /// The families to try in order, matched case-insensitively.
+    pub families: &'a [&'a str],
+    pub weight: u16,
+    pub width: Width,
+    pub style: Style,
+}
+
+impl FontDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every font file in a directory and its subdirectories. Files that can't be read or
+    /// parsed are skipped, since font directories often contain other files.
+    pub fn load_dir(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut paths = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Sort so that the order of the faces, which breaks ties between equally good matches,
+        // doesn't depend on the file system.
+        paths.sort();
+
+        for path in paths {
+            if path.is_dir() {
+                self.load_dir(&path)?;
+            } else if path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    FONT_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                })
+            {
+                let _ = self.load_file(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads every face of a font file, which can also be a collection or a WOFF or WOFF2 file.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        self.load(data, Some(path.to_owned()))
+    }
+
+    /// Loads every face of a font from memory.
+    pub fn load_data(&mut self, data: Vec<u8>) -> Result<(), Error> {
+        self.load(data, None)
+    }
+
+    fn load(&mut self, data: Vec<u8>, path: Option<PathBuf>) -> Result<(), Error> {
+        let decoded = match woff::decode(&data)? {
+            Cow::Owned(decoded) => Some(decoded),
+            Cow::Borrowed(_) => None,
+        };
+        let data = decoded.unwrap_or(data);
+
+        let source = self.sources.len();
+        let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+        let faces = (0..count)
+            .map(|index| {
+                let font = Font::with_index(&data, index)?;
+                let families = names(&font.face, name_id::TYPOGRAPHIC_FAMILY)
+                    .into_iter()
+                    .chain(names(&font.face, name_id::FAMILY))
+                    .fold(Vec::new(), |mut families, name| {
+                        if !families.contains(&name) {
+                            families.push(name);
+                        }
+                        families
+                    });
+                let subfamily = names(&font.face, name_id::TYPOGRAPHIC_SUBFAMILY)
+                    .into_iter()
+                    .chain(names(&font.face, name_id::SUBFAMILY))
+                    .next()
+                    .unwrap_or_default();
+
+                Ok(FaceInfo {
+                    path: path.clone(),
+                    index,
+                    families,
+                    subfamily,
+                    ps_name: font.ps_name,
+                    weight: font.face.weight().to_number(),
+                    width: font.face.width(),
+                    style: font.face.style(),
+                    source,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        self.sources.push(data);
+        self.faces.extend(faces);
+        Ok(())
+    }
+
+    pub fn faces(&self) -> impl Iterator<Item = (FaceId, &FaceInfo)> {
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(i, face)| (FaceId(i), face))
+    }
+
+    pub fn face(&self, id: FaceId) -> &FaceInfo {
+        &self.faces[id.0]
+    }
+
+    /// Finds the face that best matches the query, following CSS font matching.
+    ///
+    /// The families are tried in order, and the first one that has any faces is used. If the
+    /// family has no exact match, the closest width is preferred over the closest style, and the
+    /// closest style over the closest weight: narrower widths are tried first for condensed and
+    /// normal queries, italic falls back to oblique and then normal, and bold falls back to
+    /// heavier and then lighter weights. Returns `None` if none of the families have any faces.
+    pub fn query(&self, query: &FontQuery) -> Option<FaceId> {
+        query.families.iter().find_map(|family| {
+            self.faces()
+                .filter(|(_, face)| {
+                    face.families
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(family))
+                })
+                .min_by_key(|(_, face)| {
+                    (
+                        width_distance(query.width, face.width),
+                        style_distance(query.style, face.style),
+                        weight_distance(query.weight, face.weight),
+                    )
+                })
+                .map(|(id, _)| id)
+        })
+    }
+
+    /// Parses a face so that it can be used in a document.
+    pub fn font(&self, id: FaceId) -> Result<Font<'_>, Error> {
+        let face = self.face(id);
+        Font::with_index(&self.sources[face.source], face.index)
+    }
+}
+
+impl Default for FontQuery<'_> {
+    fn default() -> Self {
+        Self {
+            families: &[],
+            weight: 400,
+            width: Width::Normal,
+            style: Style::Normal,
+        }
+    }
+}
+
+/// The Unicode names with the given ID, with the US English ones first.
+fn names(face: &Face, name_id: u16) -> Vec<String> {
+    let mut names = face
+        .names()
+        .into_iter()
+        .filter(|name| name.name_id == name_id && name.is_unicode())
+        .collect::<Vec<Name>>();
+    names.sort_by_key(|name| name.language() != Language::English_UnitedStates);
+    names
+        .into_iter()
+        .filter_map(|name| name.to_string())
+        .collect()
+}
+
+/// Orders widths by how well they match the desired width, from best to worst.
+fn width_distance(desired: Width, actual: Width) -> (bool, u16) {
+    let (desired, actual) = (desired.to_number(), actual.to_number());
+    if desired <= Width::Normal.to_number() {
+        if actual <= desired {
+            (false, desired - actual)
+        } else {
+            (true, actual - desired)
+        }
+    } else if actual >= desired {
+        (false, actual - desired)
+    } else {
+        (true, desired - actual)
+    }
+}
+
+/// Orders styles by how well they match the desired style, from best to worst.
+fn style_distance(desired: Style, actual: Style) -> usize {
+    let order = match desired {
+        Style::Normal => [Style::Normal, Style::Oblique, Style::Italic],
+        Style::Italic => [Style::Italic, Style::Oblique, Style::Normal],
+        Style::Oblique => [Style::Oblique, Style::Italic, Style::Normal],
+    };
+    order.iter().position(|&style| style == actual).unwrap()
+}
+
+/// Orders weights by how well they match the desired weight, from best to worst.
+fn weight_distance(desired: u16, actual: u16) -> (u8, u16) {
+    if (400..=500).contains(&desired) {
+        // Weights up to 500 are tried first, then lighter weights, then heavier ones.
+        if (desired..=500).contains(&actual) {
+            (0, actual - desired)
+        } else if actual < desired {
+            (1, desired - actual)
+        } else {
+            (2, actual - desired)
+        }
+    } else if desired < 400 {
+        if actual <= desired {
+            (0, desired - actual)
+        } else {
+            (1, actual - desired)
+        }
+    } else if actual >= desired {
+        (0, actual - desired)
+    } else {
+        (1, desired - actual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{Style, Width};
+
+    use super::{FaceInfo, FontDatabase, FontQuery};
+    use crate::font::FONT_DATA;
+
+    fn database(faces: &[(&str, u16, Width, Style)]) -> FontDatabase {
+        let faces = faces
+            .iter()
+            .map(|&(family, weight, width, style)| FaceInfo {
+                path: None,
+                index: 0,
+                families: vec![family.to_owned()],
+                subfamily: String::new(),
+                ps_name: String::new(),
+                weight,
+                width,
+                style,
+                source: 0,
+            })
+            .collect();
+        FontDatabase {
+            sources: Vec::new(),
+            faces,
+        }
+    }
+
+    #[test]
+    fn test_load_data() {
+        let mut database = FontDatabase::new();
+        database.load_data(FONT_DATA.to_vec()).unwrap();
+
+        let (id, face) = database.faces().next().unwrap();
+        assert_eq!(face.families, ["Noto Serif"]);
+        assert_eq!(face.subfamily, "Regular");
+        assert_eq!(face.weight, 400);
+        assert_eq!(face.width, Width::Normal);
+        assert_eq!(face.style, Style::Normal);
+        assert_eq!(database.font(id).unwrap().ps_name, "NotoSerif");
+    }
+
+    #[test]
+    fn test_query() {
+        let database = database(&[
+            ("Serif", 400, Width::Normal, Style::Normal),
+            ("Serif", 700, Width::Normal, Style::Normal),
+            ("Serif", 400, Width::Normal, Style::Italic),
+            ("Serif", 300, Width::Condensed, Style::Normal),
+            ("Sans", 400, Width::Normal, Style::Normal),
+        ]);
+        let query = |families, weight, width, style| {
+            let query = FontQuery {
+                families,
+                weight,
+                width,
+                style,
+            };
+            database.query(&query).map(|id| id.0)
+        };
+
+        assert_eq!(
+            query(&["serif"], 400, Width::Normal, Style::Normal),
+            Some(0)
+        );
+        assert_eq!(
+            query(&["Serif"], 700, Width::Normal, Style::Normal),
+            Some(1)
+        );
+        // Bold italic falls back to the italic, since style takes precedence over weight.
+        assert_eq!(
+            query(&["Serif"], 700, Width::Normal, Style::Italic),
+            Some(2)
+        );
+        assert_eq!(
+            query(&["Serif"], 400, Width::Normal, Style::Oblique),
+            Some(2)
+        );
+        // Width takes precedence over weight.
+        assert_eq!(
+            query(&["Serif"], 400, Width::Condensed, Style::Normal),
+            Some(3)
+        );
+        // Medium weights fall back to lighter weights before heavier ones.
+        assert_eq!(
+            query(&["Serif"], 450, Width::Normal, Style::Normal),
+            Some(0)
+        );
+        assert_eq!(
+            query(&["Serif"], 600, Width::Normal, Style::Normal),
+            Some(1)
+        );
+        assert_eq!(
+            query(&["Mono", "Sans"], 700, Width::Normal, Style::Normal),
+            Some(4)
+        );
+        assert_eq!(query(&["Mono"], 400, Width::Normal, Style::Normal), None);
+    }
+}
@@ -1,11 +1,18 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ops::Range};
 
-use ttf_parser::{head::IndexToLocationFormat, Face, GlyphId, LazyArray16, RawFace, Tag};
+use ttf_parser::{
+    head::IndexToLocationFormat, GlyphId, LazyArray16, OutlineBuilder, Permissions, RawFace, Tag,
+};
 
-use crate::char_map::CharMap;
+use crate::char_map::{CharMap, Mapping};
 
-use super::Font;
+use super::{
+    cff::{convert_cff2, subset_cff},
+    Error, Font,
+};
 
+const CFF: Tag = Tag::from_bytes(b"CFF ");
+const CFF2: Tag = Tag::from_bytes(b"CFF2");
 const CMAP: Tag = Tag::from_bytes(b"cmap");
 const GLYF: Tag = Tag::from_bytes(b"glyf");
 const HEAD: Tag = Tag::from_bytes(b"head");
@@ -20,24 +27,55 @@ const FIXED_HEADER_LEN: usize = 12;
 const TABLE_RECORD_LEN: usize = 16;
 
 impl Font<'_> {
-    fn glyph_data(&self, glyph_id: GlyphId) -> &[u8] {
+    fn glyph_data(&self, glyph_id: GlyphId) -> Result<&[u8], Error> {
         let raw_face = *self.face.raw_face();
-        let loca_data = raw_face.table(LOCA).unwrap_or_default();
-        let glyf_data = raw_face.table(GLYF).unwrap_or_default();
+        let loca_data = raw_face.table(LOCA).ok_or(Error::MissingTable(LOCA))?;
+        let glyf_data = raw_face.table(GLYF).ok_or(Error::MissingTable(GLYF))?;
 
-        let loca = LazyArray16::<u32>::new(&loca_data);
-        let offset = loca.get(glyph_id.0).unwrap_or_default() as usize;
-        let next_offset = loca.get(glyph_id.0 + 1).unwrap_or(glyf_data.len() as u32) as usize;
+        let loca_format = self.face.tables().head.index_to_location_format;
+        glyph_range(loca_data, loca_format, glyph_id, glyf_data.len())
+            .and_then(|range| glyf_data.get(range))
+            .ok_or(Error::MalformedGlyph(glyph_id.0))
+    }
 
-        &glyf_data[offset..next_offset]
+    /// Counts the points of a glyph's outline, or its components if it's a composite glyph, which
+    /// is where the variations of its phantom points start in `gvar`.
+    pub(super) fn glyph_point_count(&self, glyph_id: GlyphId) -> Result<usize, Error> {
+        let glyph_data = self.glyph_data(glyph_id)?;
+        if glyph_data.is_empty() {
+            return Ok(0);
+        }
+
+        let num_contours = glyph_data
+            .get(0..2)
+            .map(|bytes| i16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or(Error::MalformedGlyph(glyph_id.0))?;
+        if num_contours == 0 {
+            return Ok(0);
+        } else if num_contours < 0 {
+            return Ok(component_offsets(glyph_data, glyph_id)?.len());
+        }
+
+        let last_end_point = 10 + 2 * num_contours as usize - 2;
+        glyph_data
+            .get(last_end_point..last_end_point + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize + 1)
+            .ok_or(Error::MalformedGlyph(glyph_id.0))
     }
 
-    pub fn subset(&self, char_map: &CharMap) -> Vec<u8> {
-        assert!(self.face.is_subsetting_allowed());
-        assert_eq!(
-            self.face.tables().head.index_to_location_format,
-            IndexToLocationFormat::Long
-        );
+    /// Creates a font with one glyph for each code in the char map, in the same order.
+    ///
+    /// `name` is the name of the font in the document, which shaped glyphs are recorded under.
+    ///
+    /// PDF has no support for variable fonts, so an instanced font is written as a static font,
+    /// with the variations applied to the outlines and advances.
+    pub fn subset(&self, name: &str, char_map: &CharMap) -> Result<Vec<u8>, Error> {
+        if self.face.permissions() == Some(Permissions::Restricted) {
+            return Err(Error::EmbeddingNotAllowed);
+        }
+        if !self.face.is_subsetting_allowed() {
+            return Err(Error::SubsettingNotAllowed);
+        }
 
         let raw_face = *self.face.raw_face();
 
@@ -45,126 +83,190 @@ impl Font<'_> {
         let mut glyph_map = char_map
             .mappings
             .iter()
-            .map(|&c| self.face.glyph_index(c).unwrap_or_default())
+            .map(|mapping| match mapping {
+                Mapping::Char(c) => self.face.glyph_index(*c).unwrap_or_default(),
+                Mapping::Glyph { font, id, .. } if font == name => *id,
+                Mapping::Glyph { .. } => GlyphId(0),
+            })
             .collect::<Vec<_>>();
         glyph_map[0] = GlyphId(0); // The first glyph must be the missing glyph
-        collect_glyph_dependencies(self, &mut glyph_map);
 
         // Use a BTreeMap to keep the tables sorted by tag
         let mut tables = BTreeMap::new();
         tables.insert(CMAP, generate_cmap());
-        let mut loca = Vec::new();
-        tables.insert(GLYF, generate_glyf(self, &glyph_map, &mut loca));
-        tables.insert(LOCA, generate_loca(&loca));
-        tables.insert(HEAD, generate_head(&raw_face));
-        tables.insert(HMTX, generate_hmtx(&self.face, &glyph_map));
-        tables.insert(HHEA, generate_hhea(&raw_face, glyph_map.len()));
-        tables.insert(MAXP, generate_maxp(&raw_face, glyph_map.len()));
+        let mut loca_format = IndexToLocationFormat::Long;
+        let is_cff = if let Some(cff) = raw_face.table(CFF) {
+            let cff = subset_cff(cff, &glyph_map).ok_or(Error::MalformedTable(CFF))?;
+            tables.insert(CFF, cff);
+            true
+        } else if let Some(cff2) = raw_face.table(CFF2) {
+            // CFF2 outlines are written as CFF, which is the only kind PDF describes.
+            let cff = convert_cff2(cff2, &glyph_map, &self.face, &self.ps_name)
+                .ok_or(Error::MalformedTable(CFF2))?;
+            tables.insert(CFF, cff);
+            true
+        } else {
+            // The outlines of an instance are flattened, so composite glyphs need no components.
+            if !self.is_instanced() {
+                collect_glyph_dependencies(self, &mut glyph_map)?;
+            }
+
+            let mut loca = Vec::new();
+            tables.insert(GLYF, generate_glyf(self, &glyph_map, &mut loca)?);
+
+            // Short offsets are kept if the glyphs still fit, and long ones otherwise.
+            let glyf_len = loca.last().copied().unwrap_or_default();
+            if self.face.tables().head.index_to_location_format == IndexToLocationFormat::Short
+                && glyf_len / 2 <= u16::MAX as u32
+            {
+                loca_format = IndexToLocationFormat::Short;
+            }
+            tables.insert(LOCA, generate_loca(&loca, loca_format));
+            false
+        };
+        tables.insert(HEAD, generate_head(&raw_face, loca_format)?);
+        tables.insert(HMTX, generate_hmtx(self, &glyph_map)?);
+        tables.insert(HHEA, generate_hhea(&raw_face, glyph_map.len())?);
+        tables.insert(
+            MAXP,
+            generate_maxp(&raw_face, glyph_map.len(), self.is_instanced())?,
+        );
 
         // Copy the rest of the required tables verbatim
         for tag in [NAME, POST] {
             tables.insert(tag, raw_face.table(tag).unwrap_or_default().to_owned());
         }
 
-        let mut contents = Vec::new();
+        let version = if is_cff {
+            u32::from_be_bytes(*b"OTTO")
+        } else {
+            0x00010000
+        };
+        Ok(write_sfnt(version, tables))
+    }
+}
 
-        // table directory
-        contents.push_u32(0x00010000); // version
-        contents.push_u16(tables.len() as u16); // num tables
-        let (search_range, entry_selector, range_shift) =
-            calculate_search_hints(tables.len() as u16);
-        contents.push_u16(16 * search_range); // search range
-        contents.push_u16(entry_selector); // entry selector
-        contents.push_u16(16 * range_shift); // range shift
-        for &tag in tables.keys() {
-            // table records
-            contents.push_u32(tag.0); // tag
-            contents.push_u32(0); // check sum (ignored)
-            contents.push_u32(0); // offset (backfilled later)
-            contents.push_u32(0); // length (backfilled later)
-        }
-        pad_to_multiple_of(&mut contents, 8);
+/// Writes a font file with the given sfnt version and tables, filling in the checksums.
+pub(super) fn write_sfnt(version: u32, tables: BTreeMap<Tag, Vec<u8>>) -> Vec<u8> {
+    let mut contents = Vec::new();
 
-        // tables
-        let mut table_offsets = BTreeMap::new();
-        for (i, (tag, mut table)) in tables.into_iter().enumerate() {
-            match table {
-                _ if tag == MAXP => {
-                    // num glyphs
-                    table[4..6].copy_from_slice(&(glyph_map.len() as u16).to_be_bytes());
-                }
-                _ => {}
-            }
+    // table directory
+    contents.push_u32(version); // version
+    contents.push_u16(tables.len() as u16); // num tables
+    let (search_range, entry_selector, range_shift) = calculate_search_hints(tables.len() as u16);
+    contents.push_u16(16 * search_range); // search range
+    contents.push_u16(entry_selector); // entry selector
+    contents.push_u16(16 * range_shift); // range shift
+    for &tag in tables.keys() {
+        // table records
+        contents.push_u32(tag.0); // tag
+        contents.push_u32(0); // check sum (ignored)
+        contents.push_u32(0); // offset (backfilled later)
+        contents.push_u32(0); // length (backfilled later)
+    }
+    pad_to_multiple_of(&mut contents, 8);
+
+    // tables
+    let mut table_offsets = BTreeMap::new();
+    for (i, (tag, mut table)) in tables.into_iter().enumerate() {
+        if tag == HEAD && table.len() >= 12 {
+            // checksum adjustment, which is worked out once the whole file is written
+            table[8..12].copy_from_slice(&[0; 4]);
+        }
 
-            pad_to_multiple_of(&mut table, 8);
-            let offset = contents.len() as u32;
-            let len = table.len() as u32;
-            contents.extend_from_slice(&table);
+        pad_to_multiple_of(&mut table, 8);
+        let offset = contents.len() as u32;
+        let len = table.len() as u32;
+        contents.extend_from_slice(&table);
 
-            let checksum = calculate_checksum(&table);
+        let checksum = calculate_checksum(&table);
 
-            // backfill the checksum, offset and length
-            let table_record_offset = FIXED_HEADER_LEN + TABLE_RECORD_LEN * i;
-            contents[table_record_offset + 4..table_record_offset + 8]
-                .copy_from_slice(&checksum.to_be_bytes());
-            contents[table_record_offset + 8..table_record_offset + 12]
-                .copy_from_slice(&offset.to_be_bytes());
-            contents[table_record_offset + 12..table_record_offset + 16]
-                .copy_from_slice(&len.to_be_bytes());
+        // backfill the checksum, offset and length
+        let table_record_offset = FIXED_HEADER_LEN + TABLE_RECORD_LEN * i;
+        contents[table_record_offset + 4..table_record_offset + 8]
+            .copy_from_slice(&checksum.to_be_bytes());
+        contents[table_record_offset + 8..table_record_offset + 12]
+            .copy_from_slice(&offset.to_be_bytes());
+        contents[table_record_offset + 12..table_record_offset + 16]
+            .copy_from_slice(&len.to_be_bytes());
 
-            table_offsets.insert(tag, offset);
-        }
+        table_offsets.insert(tag, offset);
+    }
 
-        // checksum adjustment
-        const TARGET_CHECKSUM: u32 = 0xB1B0AFBA;
+    // checksum adjustment
+    const TARGET_CHECKSUM: u32 = 0xB1B0AFBA;
+    if let Some(&head_offset) = table_offsets.get(&HEAD) {
         let checksum = calculate_checksum(&contents);
         let checksum_adjustment = TARGET_CHECKSUM.wrapping_sub(checksum);
-        let head_offset = table_offsets[&HEAD] as usize;
-        let head_table = &mut contents[head_offset..];
-        assert_eq!(&head_table[8..12], [0; 4]);
+        let head_table = &mut contents[head_offset as usize..];
         head_table[8..12].copy_from_slice(&checksum_adjustment.to_be_bytes());
         assert_eq!(calculate_checksum(&contents), TARGET_CHECKSUM);
-
-        contents
     }
+
+    contents
 }
 
 /// BFS to collect all the dependencies for composite glyphs.
-fn collect_glyph_dependencies(font: &Font, glyph_map: &mut Vec<GlyphId>) {
+fn collect_glyph_dependencies(font: &Font, glyph_map: &mut Vec<GlyphId>) -> Result<(), Error> {
     let mut i = 0;
     while i < glyph_map.len() {
         let glyph_id = glyph_map[i];
-        let glyph_data = font.glyph_data(glyph_id);
-        if glyph_data.is_empty() {
-            i += 1;
-            continue;
-        }
+        let glyph_data = font.glyph_data(glyph_id)?;
 
-        let num_contours = i16::from_be_bytes([glyph_data[0], glyph_data[1]]);
-        if num_contours == -1 {
-            let mut j = 10;
-            while j < glyph_data.len() {
-                let flags = u16::from_be_bytes([glyph_data[j], glyph_data[j + 1]]);
-                let component_glyph_id =
-                    GlyphId(u16::from_be_bytes([glyph_data[j + 2], glyph_data[j + 3]]));
+        for offset in component_offsets(glyph_data, glyph_id)? {
+            let component_glyph_id = GlyphId(u16::from_be_bytes([
+                glyph_data[offset],
+                glyph_data[offset + 1],
+            ]));
 
-                if !glyph_map.contains(&component_glyph_id) {
-                    glyph_map.push(component_glyph_id);
-                }
-
-                j += component_glyph_table_len(flags);
-                // More components flag == 0
-                if flags & 0x0020 == 0 {
-                    break;
-                }
+            if !glyph_map.contains(&component_glyph_id) {
+                glyph_map.push(component_glyph_id);
             }
         }
 
         i += 1;
     }
+
+    Ok(())
+}
+
+/// Finds where the glyph IDs of the components are in a composite glyph, checking that the
+/// component records fit in the glyph data. Simple glyphs have no components.
+fn component_offsets(glyph_data: &[u8], glyph_id: GlyphId) -> Result<Vec<usize>, Error> {
+    let malformed = || Error::MalformedGlyph(glyph_id.0);
+    let read_u16 = |offset: usize| {
+        glyph_data
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let mut offsets = Vec::new();
+    if glyph_data.is_empty() {
+        return Ok(offsets);
+    }
+
+    let num_contours = read_u16(0).ok_or_else(malformed)? as i16;
+    if num_contours < 0 {
+        let mut j = 10;
+        loop {
+            let flags = read_u16(j).ok_or_else(malformed)?;
+            if j + component_glyph_table_len(flags) > glyph_data.len() {
+                return Err(malformed());
+            }
+            offsets.push(j + 2);
+
+            j += component_glyph_table_len(flags);
+            // More components flag == 0
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+    }
+
+    Ok(offsets)
 }
 
-fn component_glyph_table_len(flags: u16) -> usize {
+pub(super) fn component_glyph_table_len(flags: u16) -> usize {
     let mut len = 4;
     if flags & 0x0001 != 0 {
         len += 4;
@@ -212,13 +314,23 @@ fn generate_cmap() -> Vec<u8> {
     contents
 }
 
-fn generate_glyf(font: &Font, glyph_map: &[GlyphId], loca: &mut Vec<u32>) -> Vec<u8> {
+fn generate_glyf(
+    font: &Font,
+    glyph_map: &[GlyphId],
+    loca: &mut Vec<u32>,
+) -> Result<Vec<u8>, Error> {
     const GLYF_LEN_MARKER: u32 = u32::MAX;
 
     let mut contents = Vec::new();
 
     for &glyph_id in glyph_map {
-        let glyph_data = font.glyph_data(glyph_id);
+        let instance_data;
+        let glyph_data = if font.is_instanced() {
+            instance_data = generate_instance_glyph(font, glyph_id);
+            &instance_data
+        } else {
+            font.glyph_data(glyph_id)?
+        };
         if glyph_data.is_empty() {
             loca.push(GLYF_LEN_MARKER);
             continue;
@@ -229,7 +341,10 @@ fn generate_glyf(font: &Font, glyph_map: &[GlyphId], loca: &mut Vec<u32>) -> Vec
         let start = contents.len();
         contents.extend_from_slice(glyph_data);
 
-        map_glyph_components(&mut contents[start..], glyph_map);
+        if !font.is_instanced() {
+            let component_offsets = component_offsets(glyph_data, glyph_id)?;
+            map_glyph_components(&mut contents[start..], &component_offsets, glyph_map);
+        }
 
         loca.push(start as u32);
     }
@@ -242,85 +357,264 @@ fn generate_glyf(font: &Font, glyph_map: &[GlyphId], loca: &mut Vec<u32>) -> Vec
         }
     }
 
-    contents
+    Ok(contents)
 }
 
-/// Updates the component glyph references to the new glyph indices if this is a composite glyph.
-fn map_glyph_components(glyph_data: &mut [u8], glyph_map: &[GlyphId]) {
-    let num_contours = i16::from_be_bytes([glyph_data[0], glyph_data[1]]);
-    if num_contours == -1 {
-        let mut j = 10;
-        while j < glyph_data.len() {
-            let flags = u16::from_be_bytes([glyph_data[j], glyph_data[j + 1]]);
-            let component_glyph_id =
-                GlyphId(u16::from_be_bytes([glyph_data[j + 2], glyph_data[j + 3]]));
+/// Writes a simple glyph with the outline of a glyph at the font's variation coordinates.
+///
+/// Components are drawn into the outline, and the hinting instructions are dropped since they were
+/// written for the default outline.
+fn generate_instance_glyph(font: &Font, glyph_id: GlyphId) -> Vec<u8> {
+    let mut outline = Contours::default();
+    if font.face.outline_glyph(glyph_id, &mut outline).is_none() {
+        return Vec::new();
+    }
+    outline.write(&[])
+}
 
-            let component_glyph_index = glyph_map
-                .iter()
-                .position(|&id| id == component_glyph_id)
-                .unwrap_or_default();
-            glyph_data[j + 2..j + 4].copy_from_slice(&(component_glyph_index as u16).to_be_bytes());
+fn minmax(values: impl Iterator<Item = i16>) -> (i16, i16) {
+    values.fold((i16::MAX, i16::MIN), |(min, max), value| {
+        (min.min(value), max.max(value))
+    })
+}
 
-            j += component_glyph_table_len(flags);
-            // More components flag == 0
-            if flags & 0x0020 == 0 {
-                break;
+/// The contours of a TrueType glyph, as points that are on or off the curve.
+#[derive(Debug, Default)]
+pub(super) struct Contours {
+    pub(super) points: Vec<(i16, i16, bool)>,
+    pub(super) end_points: Vec<u16>,
+}
+
+impl Contours {
+    fn push(&mut self, x: f32, y: f32, on_curve: bool) {
+        self.points
+            .push((x.round() as i16, y.round() as i16, on_curve));
+    }
+
+    /// Writes the contours as a simple glyph with the given hinting instructions, or nothing if
+    /// there are no contours.
+    pub(super) fn write(&self, instructions: &[u8]) -> Vec<u8> {
+        if self.points.is_empty() {
+            return Vec::new();
+        }
+
+        let (x_min, x_max) = minmax(self.points.iter().map(|point| point.0));
+        let (y_min, y_max) = minmax(self.points.iter().map(|point| point.1));
+
+        let mut contents = Vec::new();
+        contents.push_u16(self.end_points.len() as u16); // number of contours
+        contents.push_u16(x_min as u16);
+        contents.push_u16(y_min as u16);
+        contents.push_u16(x_max as u16);
+        contents.push_u16(y_max as u16);
+        for &end_point in &self.end_points {
+            contents.push_u16(end_point);
+        }
+        contents.push_u16(instructions.len() as u16);
+        contents.extend_from_slice(instructions);
+
+        // Coordinates are relative to the previous point, and written as a byte with a sign flag
+        // if they fit.
+        let mut flags = Vec::new();
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        let (mut last_x, mut last_y) = (0, 0);
+        for &(x, y, on_curve) in &self.points {
+            let mut flag = on_curve as u8;
+            for (delta, short_flag, same_or_positive_flag, coordinates) in [
+                (x as i32 - last_x, 0x02, 0x10, &mut xs),
+                (y as i32 - last_y, 0x04, 0x20, &mut ys),
+            ] {
+                if delta == 0 {
+                    flag |= same_or_positive_flag;
+                } else if delta.abs() <= u8::MAX as i32 {
+                    flag |= short_flag;
+                    if delta > 0 {
+                        flag |= same_or_positive_flag;
+                    }
+                    coordinates.push(delta.unsigned_abs() as u8);
+                } else {
+                    coordinates.push_u16(delta as u16);
+                }
             }
+            flags.push(flag);
+            (last_x, last_y) = (x as i32, y as i32);
+        }
+        contents.extend(flags);
+        contents.extend(xs);
+        contents.extend(ys);
+
+        contents
+    }
+}
+
+impl OutlineBuilder for Contours {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.push(x, y, true);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(x, y, true);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.push(x1, y1, false);
+        self.push(x, y, true);
+    }
+
+    // TrueType outlines only have quadratic curves.
+    fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, x: f32, y: f32) {
+        self.push(x, y, true);
+    }
+
+    fn close(&mut self) {
+        let start = self.end_points.last().map_or(0, |&end| end as usize + 1);
+        // Contours are closed implicitly, so the point that returns to the start isn't needed.
+        if self.points.len() > start + 1 && self.points.last() == self.points.get(start) {
+            self.points.pop();
+        }
+        if self.points.len() > start {
+            self.end_points.push(self.points.len() as u16 - 1);
         }
     }
 }
 
-fn generate_loca(loca: &[u32]) -> Vec<u8> {
+/// Updates the component glyph references to the new glyph indices if this is a composite glyph.
+fn map_glyph_components(glyph_data: &mut [u8], component_offsets: &[usize], glyph_map: &[GlyphId]) {
+    for &offset in component_offsets {
+        let component_glyph_id = GlyphId(u16::from_be_bytes([
+            glyph_data[offset],
+            glyph_data[offset + 1],
+        ]));
+
+        let component_glyph_index = glyph_map
+            .iter()
+            .position(|&id| id == component_glyph_id)
+            .unwrap_or_default();
+        glyph_data[offset..offset + 2]
+            .copy_from_slice(&(component_glyph_index as u16).to_be_bytes());
+    }
+}
+
+/// Finds the range of a glyph's data in the `glyf` table.
+///
+/// Short offsets are stored divided by two. If the last offset is missing, the glyph runs to the
+/// end of the table.
+fn glyph_range(
+    loca_data: &[u8],
+    format: IndexToLocationFormat,
+    glyph_id: GlyphId,
+    glyf_len: usize,
+) -> Option<Range<usize>> {
+    let offset = |i: u16| match format {
+        IndexToLocationFormat::Short => LazyArray16::<u16>::new(loca_data)
+            .get(i)
+            .map(|offset| 2 * offset as usize),
+        IndexToLocationFormat::Long => LazyArray16::<u32>::new(loca_data)
+            .get(i)
+            .map(|offset| offset as usize),
+    };
+
+    let start = offset(glyph_id.0)?;
+    let end = match glyph_id.0.checked_add(1) {
+        Some(next) => offset(next).unwrap_or(glyf_len),
+        None => glyf_len,
+    };
+    Some(start..end)
+}
+
+fn generate_loca(loca: &[u32], format: IndexToLocationFormat) -> Vec<u8> {
     let mut contents = Vec::new();
 
     for &offset in loca {
-        contents.push_u32(offset);
+        match format {
+            IndexToLocationFormat::Short => contents.push_u16((offset / 2) as u16),
+            IndexToLocationFormat::Long => contents.push_u32(offset),
+        }
     }
 
     contents
 }
 
-fn generate_hmtx(face: &Face, glyph_map: &[GlyphId]) -> Vec<u8> {
+/// Writes a long metric for every glyph, even if the original font shares the advance of its last
+/// long metric with the glyphs after it.
+///
+/// The metrics of an instance are varied, and its side bearings are the left of the varied
+/// outlines.
+fn generate_hmtx(font: &Font, glyph_map: &[GlyphId]) -> Result<Vec<u8>, Error> {
+    let hmtx = font.face.tables().hmtx.ok_or(Error::MissingTable(HMTX))?;
     let mut contents = Vec::new();
 
     for &glyph_id in glyph_map {
-        let advance_width = face.glyph_hor_advance(glyph_id).unwrap_or_default();
-        let left_side_bearing = face.glyph_hor_side_bearing(glyph_id).unwrap_or_default();
+        let (advance_width, left_side_bearing) = if font.is_instanced() {
+            let advance = font.glyph_advance(glyph_id);
+            let bbox = font.face.glyph_bounding_box(glyph_id);
+            (advance, bbox.map_or(0, |bbox| bbox.x_min))
+        } else {
+            // Some fonts leave out the side bearings of the glyphs without long metrics.
+            let side_bearing = hmtx.side_bearing(glyph_id).unwrap_or_default();
+            (hmtx.advance(glyph_id), side_bearing)
+        };
+        let advance_width = advance_width.ok_or(Error::MalformedGlyph(glyph_id.0))?;
 
         contents.push_u16(advance_width);
         contents.push_u16(left_side_bearing as u16);
     }
 
-    contents
+    Ok(contents)
 }
 
-fn generate_head(raw_face: &RawFace) -> Vec<u8> {
-    let mut contents = raw_face.table(HEAD).unwrap().to_owned();
+/// Copies a table, checking that it's long enough for the fields that are changed.
+fn copy_table(raw_face: &RawFace, tag: Tag, min_len: usize) -> Result<Vec<u8>, Error> {
+    let contents = raw_face.table(tag).ok_or(Error::MissingTable(tag))?;
+    if contents.len() < min_len {
+        return Err(Error::MalformedTable(tag));
+    }
+    Ok(contents.to_owned())
+}
+
+fn generate_head(raw_face: &RawFace, loca_format: IndexToLocationFormat) -> Result<Vec<u8>, Error> {
+    let mut contents = copy_table(raw_face, HEAD, 54)?;
 
     // checksum adjustment
     contents[8..12].copy_from_slice(&0u32.to_be_bytes());
     // index to loca format
-    contents[50..52].copy_from_slice(&1u16.to_be_bytes());
+    let loca_format = match loca_format {
+        IndexToLocationFormat::Short => 0u16,
+        IndexToLocationFormat::Long => 1u16,
+    };
+    contents[50..52].copy_from_slice(&loca_format.to_be_bytes());
 
-    contents
+    Ok(contents)
 }
 
-fn generate_hhea(raw_face: &RawFace, num_glyphs: usize) -> Vec<u8> {
-    let mut contents = raw_face.table(HHEA).unwrap().to_owned();
+fn generate_hhea(raw_face: &RawFace, num_glyphs: usize) -> Result<Vec<u8>, Error> {
+    let mut contents = copy_table(raw_face, HHEA, 36)?;
 
     // number of H-metrics
     contents[34..36].copy_from_slice(&(num_glyphs as u16).to_be_bytes());
 
-    contents
+    Ok(contents)
 }
 
-fn generate_maxp(raw_face: &RawFace, num_glyphs: usize) -> Vec<u8> {
-    let mut contents = raw_face.table(MAXP).unwrap().to_owned();
+fn generate_maxp(raw_face: &RawFace, num_glyphs: usize, instanced: bool) -> Result<Vec<u8>, Error> {
+    let mut contents = copy_table(raw_face, MAXP, 6)?;
 
     // num glyphs
     contents[4..6].copy_from_slice(&(num_glyphs as u16).to_be_bytes());
 
-    contents
+    // The composite glyphs of an instance become simple glyphs, with as many points and contours
+    // as their components had.
+    if instanced && contents.len() >= 14 {
+        for (simple, composite) in [(6, 10), (8, 12)] {
+            let max = u16::from_be_bytes([contents[simple], contents[simple + 1]]).max(
+                u16::from_be_bytes([contents[composite], contents[composite + 1]]),
+            );
+            contents[simple..simple + 2].copy_from_slice(&max.to_be_bytes());
+        }
+    }
+
+    Ok(contents)
 }
 
 fn calculate_checksum(data: &[u8]) -> u32 {
@@ -352,7 +646,7 @@ fn pad_to_multiple_of(contents: &mut Vec<u8>, alignment: usize) {
     }
 }
 
-trait VecExt: Extend<u8> {
+pub(super) trait VecExt: Extend<u8> {
     fn push_u16(&mut self, value: u16) {
         self.extend(value.to_be_bytes());
     }
@@ -363,3 +657,114 @@ trait VecExt: Extend<u8> {
 }
 
 impl VecExt for Vec<u8> {}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{glyf, head::IndexToLocationFormat, loca, GlyphId, OutlineBuilder};
+
+    use std::collections::BTreeMap;
+
+    use super::{component_offsets, generate_loca, glyph_range, write_sfnt, Contours, GLYF, LOCA};
+    use crate::font::{util::Path, Error, Font};
+
+    #[test]
+    fn test_glyph_range() {
+        let short = generate_loca(&[0, 8, 8, 20], IndexToLocationFormat::Short);
+        assert_eq!(short, [0, 0, 0, 4, 0, 4, 0, 10]);
+        assert_eq!(
+            glyph_range(&short, IndexToLocationFormat::Short, GlyphId(0), 20),
+            Some(0..8)
+        );
+        assert_eq!(
+            glyph_range(&short, IndexToLocationFormat::Short, GlyphId(1), 20),
+            Some(8..8)
+        );
+
+        let long = generate_loca(&[0, 8, 20], IndexToLocationFormat::Long);
+        assert_eq!(
+            glyph_range(&long, IndexToLocationFormat::Long, GlyphId(1), 20),
+            Some(8..20)
+        );
+        // The last offset runs to the end of the table.
+        assert_eq!(
+            glyph_range(&long, IndexToLocationFormat::Long, GlyphId(2), 24),
+            Some(20..24)
+        );
+        assert_eq!(
+            glyph_range(&long, IndexToLocationFormat::Long, GlyphId(3), 24),
+            None
+        );
+    }
+
+    #[test]
+    fn test_glyph_point_count() {
+        let font = Font::default();
+        // A glyph with only a header and a glyph with one contour of 4 points, both with a yMax of
+        // 500.
+        let mut glyf = vec![0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xf4];
+        glyf.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0, 0x01, 0xf4, 0, 3]);
+        let mut loca = vec![0, 10];
+        loca.resize(font.face.number_of_glyphs() as usize + 1, 22);
+        let loca_format = font.face.tables().head.index_to_location_format;
+
+        let raw_face = font.face.raw_face();
+        let mut tables = raw_face
+            .table_records
+            .into_iter()
+            .map(|record| (record.tag, raw_face.table(record.tag).unwrap().to_owned()))
+            .collect::<BTreeMap<_, _>>();
+        tables.insert(GLYF, glyf);
+        tables.insert(LOCA, generate_loca(&loca, loca_format));
+        let data = write_sfnt(0x00010000, tables);
+        let font = Font::new(&data).unwrap();
+
+        assert_eq!(font.glyph_point_count(GlyphId(0)).unwrap(), 0);
+        assert_eq!(font.glyph_point_count(GlyphId(1)).unwrap(), 4);
+        assert_eq!(font.glyph_point_count(GlyphId(2)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_component_offsets() {
+        let mut composite = vec![0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0];
+        // Two components with byte arguments, the first with the more components flag.
+        composite.extend_from_slice(&[0x00, 0x20, 0x00, 0x05, 0, 0]);
+        composite.extend_from_slice(&[0x00, 0x00, 0x00, 0x06, 0, 0]);
+        assert_eq!(component_offsets(&composite, GlyphId(1)).unwrap(), [12, 18]);
+
+        assert!(component_offsets(&[], GlyphId(1)).unwrap().is_empty());
+
+        let truncated = &composite[..20];
+        assert!(matches!(
+            component_offsets(truncated, GlyphId(1)),
+            Err(Error::MalformedGlyph(1))
+        ));
+    }
+
+    #[test]
+    fn test_write_contours() {
+        let mut contours = Contours::default();
+        contours.move_to(0.0, 0.0);
+        contours.line_to(700.0, 0.4);
+        contours.quad_to(700.0, 700.0, 0.0, 700.0);
+        contours.line_to(0.0, 0.0);
+        contours.close();
+        contours.move_to(100.0, 100.0);
+        contours.line_to(-200.0, 100.0);
+        contours.line_to(100.0, 100.0);
+        contours.close();
+        assert_eq!(contours.end_points, [3, 5]);
+
+        let glyph = contours.write(&[]);
+        let loca = generate_loca(&[0, glyph.len() as u32], IndexToLocationFormat::Long);
+        let loca = loca::Table::parse(1.try_into().unwrap(), IndexToLocationFormat::Long, &loca);
+        let table = glyf::Table::parse(loca.unwrap(), &glyph).unwrap();
+        let mut path = Path::default();
+        let bbox = table.outline(GlyphId(0), &mut path).unwrap();
+
+        assert_eq!(
+            path.0,
+            "M 0 0 L 700 0 Q 700 700 0 700 L 0 0 Z M 100 100 L -200 100 L 100 100 Z "
+        );
+        assert_eq!((bbox.x_min, bbox.y_max), (-200, 700));
+    }
+}
@@ -0,0 +1,210 @@
+use ttf_parser::{
+    math::{Constants, GlyphAssembly, GlyphConstruction, Kern},
+    GlyphId,
+};
+
+use super::Font;
+
+const MATH_FONT_DATA: &[u8] = include_bytes!(concat!(
+    env!("OUT_DIR"),
+    "/noto-sans-math/NotoSansMath-Regular.ttf"
+));
+
+/// A corner of a glyph, where math kerning moves scripts closer to their base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopRight,
+    TopLeft,
+    BottomRight,
+    BottomLeft,
+}
+
+/// The direction that a glyph is stretched in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Delimiters, radicals and big operators grow vertically.
+    Vertical,
+    /// Accents, arrows and braces over or under formulas grow horizontally.
+    Horizontal,
+}
+
+impl Font<'static> {
+    /// The math font that equations are set in by default, Noto Sans Math.
+    pub fn default_math() -> Self {
+        let font = Self::new(MATH_FONT_DATA).unwrap();
+        assert_eq!(font.ps_name, "NotoSansMath-Regular");
+        font
+    }
+}
+
+/// Reads the `MATH` table, which describes how to lay out equations with the font's glyphs. All
+/// values are in font units.
+impl<'a> Font<'a> {
+    /// Whether the font has a `MATH` table, which equations need.
+    pub fn has_math(&self) -> bool {
+        self.face.tables().math.is_some()
+    }
+
+    /// The constants that position the parts of an equation, such as the height of the math axis,
+    /// the thickness of fraction rules and how far superscripts are raised.
+    pub fn math_constants(&self) -> Option<Constants<'a>> {
+        self.face.tables().math?.constants
+    }
+
+    /// How far the top of a slanted glyph overhangs its advance, which is added before a
+    /// superscript or an upright glyph that follows it.
+    pub fn italic_correction(&self, glyph_id: GlyphId) -> i16 {
+        self.face
+            .tables()
+            .math
+            .and_then(|math| math.glyph_info?.italic_corrections?.get(glyph_id))
+            .map_or(0, |value| value.value)
+    }
+
+    /// Where an accent over the glyph is centred, which is the middle of its advance unless the
+    /// font says otherwise.
+    pub fn top_accent_attachment(&self, glyph_id: GlyphId) -> i16 {
+        self.face
+            .tables()
+            .math
+            .and_then(|math| math.glyph_info?.top_accent_attachments?.get(glyph_id))
+            .map_or_else(
+                || (self.face.glyph_hor_advance(glyph_id).unwrap_or_default() / 2) as i16,
+                |value| value.value,
+            )
+    }
+
+    /// Whether the glyph is an extended shape, such as a large operator or a stretched delimiter,
+    /// whose accents and scripts are placed by its full height rather than its flat height.
+    pub fn is_extended_shape(&self, glyph_id: GlyphId) -> bool {
+        self.face
+            .tables()
+            .math
+            .and_then(|math| math.glyph_info?.extended_shapes)
+            .is_some_and(|coverage| coverage.contains(glyph_id))
+    }
+
+    /// The kern at a corner of the glyph for a script whose nearest edge is at `height`, which is
+    /// negative to move the script closer.
+    pub fn math_kern(&self, glyph_id: GlyphId, corner: Corner, height: i16) -> i16 {
+        let Some(info) = self
+            .face
+            .tables()
+            .math
+            .and_then(|math| math.glyph_info?.kern_infos?.get(glyph_id))
+        else {
+            return 0;
+        };
+        let kern = match corner {
+            Corner::TopRight => info.top_right,
+            Corner::TopLeft => info.top_left,
+            Corner::BottomRight => info.bottom_right,
+            Corner::BottomLeft => info.bottom_left,
+        };
+        kern.map_or(0, |kern| kern_at(&kern, height))
+    }
+
+    /// The versions of the glyph that are bigger in the direction, from smallest to largest, with
+    /// their sizes. The first is usually the glyph itself.
+    pub fn glyph_variants(&self, glyph_id: GlyphId, axis: Axis) -> Vec<(GlyphId, u16)> {
+        self.glyph_construction(glyph_id, axis)
+            .map(|construction| {
+                construction
+                    .variants
+                    .into_iter()
+                    .map(|variant| (variant.variant_glyph, variant.advance_measurement))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The parts that the glyph can be built from, with extenders repeated as often as needed,
+    /// when none of its variants is big enough.
+    pub fn glyph_assembly(&self, glyph_id: GlyphId, axis: Axis) -> Option<GlyphAssembly<'a>> {
+        self.glyph_construction(glyph_id, axis)?.assembly
+    }
+
+    /// The least that adjacent parts of an assembly overlap.
+    pub fn min_connector_overlap(&self) -> u16 {
+        self.face
+            .tables()
+            .math
+            .and_then(|math| math.variants)
+            .map_or(0, |variants| variants.min_connector_overlap)
+    }
+
+    fn glyph_construction(&self, glyph_id: GlyphId, axis: Axis) -> Option<GlyphConstruction<'a>> {
+        let variants = self.face.tables().math?.variants?;
+        match axis {
+            Axis::Vertical => variants.vertical_constructions.get(glyph_id),
+            Axis::Horizontal => variants.horizontal_constructions.get(glyph_id),
+        }
+    }
+}
+
+/// Looks up the kern for a height, where each height in the table is where the next kern starts.
+fn kern_at(kern: &Kern, height: i16) -> i16 {
+    let index = (0..kern.count())
+        .find(|&i| kern.height(i).is_some_and(|value| height < value.value))
+        .unwrap_or(kern.count());
+    kern.kern(index).map_or(0, |value| value.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::GlyphId;
+
+    use super::{Axis, Corner};
+    use crate::font::Font;
+
+    #[test]
+    fn test_math_constants() {
+        let font = Font::default_math();
+        assert!(font.has_math());
+        assert!(!Font::default().has_math());
+
+        let constants = font.math_constants().unwrap();
+        assert!(constants.axis_height().value > 0);
+        assert!(constants.fraction_rule_thickness().value > 0);
+        assert!(constants.superscript_shift_up().value > 0);
+        assert!((50..100).contains(&constants.script_percent_scale_down()));
+    }
+
+    #[test]
+    fn test_glyph_variants() {
+        let font = Font::default_math();
+        let paren = font.face.glyph_index('(').unwrap();
+
+        let variants = font.glyph_variants(paren, Axis::Vertical);
+        assert!(variants.len() > 1);
+        assert!(variants.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert!(font.glyph_variants(paren, Axis::Horizontal).is_empty());
+
+        let assembly = font.glyph_assembly(paren, Axis::Vertical).unwrap();
+        assert!(assembly.parts.len() >= 2);
+        assert!(assembly
+            .parts
+            .into_iter()
+            .any(|part| part.part_flags.extender()));
+    }
+
+    #[test]
+    fn test_glyph_info() {
+        let font = Font::default_math();
+
+        // The integral sign slants, so its upper limit is moved right by its italic correction.
+        let integral = font.face.glyph_index('∫').unwrap();
+        assert!(font.italic_correction(integral) > 0);
+        assert_eq!(
+            font.italic_correction(font.face.glyph_index('+').unwrap()),
+            0
+        );
+
+        let x = font.face.glyph_index('x').unwrap();
+        let advance = font.face.glyph_hor_advance(x).unwrap() as i16;
+        assert!((0..advance).contains(&font.top_accent_attachment(x)));
+
+        assert!(!font.is_extended_shape(x));
+        assert_eq!(font.math_kern(GlyphId(0), Corner::TopRight, 0), 0);
+    }
+}
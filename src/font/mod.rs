@@ -1,14 +1,42 @@
 use std::ops::Mul;
 
 use thiserror::Error;
-use ttf_parser::{name_id, Face};
+use ttf_parser::{name_id, Face, Tag};
 
+mod cff;
+pub mod database;
 mod generate;
+pub mod math;
+pub mod shape;
+mod util;
+pub mod variation;
+pub mod woff;
+
+#[cfg(test)]
+pub(crate) use {cff::with_cff_outlines, util::Path};
 
 pub struct Font<'a> {
     pub data: &'a [u8],
     pub face: Face<'a>,
     pub ps_name: String,
+    /// Where the ascent, descent and line gap are read from, or `None` to use the `OS/2`
+    /// typographic metrics if the font sets `USE_TYPO_METRICS`, and `hhea` otherwise.
+    pub metrics_source: Option<MetricsSource>,
+}
+
+/// The table fields that a font's ascent, descent and line gap are read from. Fonts often set
+/// them to different values, and applications disagree about which to use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsSource {
+    /// The ascender, descender and line gap in `hhea`, which macOS uses.
+    #[default]
+    Hhea,
+    /// The typographic ascender, descender and line gap in `OS/2`, which browsers use when the
+    /// font sets `USE_TYPO_METRICS`.
+    Typo,
+    /// The Windows ascent and descent in `OS/2`, with no line gap, which older Windows
+    /// applications use. They cover every glyph, so text is never clipped.
+    Win,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
@@ -16,6 +44,25 @@ pub struct TextMetrics {
     pub ascent: f32,
     pub descent: f32,
     pub line_gap: f32,
+    /// Where the ascent, descent and line gap were read from.
+    pub source: MetricsSource,
+    /// The height of flat capital letters such as 'H'.
+    pub cap_height: f32,
+    /// The height of flat lowercase letters such as 'x', or zero if it isn't known.
+    pub x_height: f32,
+}
+
+/// The properties of a font that PDF viewers use to lay out text when the embedded font can't be
+/// used, and to pick a substitute for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontDescriptor {
+    pub flags: u32,
+    /// The height of flat capital letters, in thousandths of an em.
+    pub cap_height: i32,
+    /// The height of flat lowercase letters, in thousandths of an em.
+    pub x_height: i32,
+    /// The thickness of the vertical stems, estimated from the weight.
+    pub stem_v: i32,
 }
 
 #[derive(Debug, Error)]
@@ -26,8 +73,37 @@ pub enum Error {
     MissingPostScriptName,
     #[error("non-unicode string")]
     NonUnicodeString,
+    #[error("the font's license does not allow embedding")]
+    EmbeddingNotAllowed,
+    #[error("the font's license does not allow subsetting")]
+    SubsettingNotAllowed,
+    #[error("missing '{0}' table")]
+    MissingTable(Tag),
+    #[error("malformed '{0}' table")]
+    MalformedTable(Tag),
+    #[error("malformed glyph {0}")]
+    MalformedGlyph(u16),
+    #[error("the font has no '{0}' axis")]
+    UnknownAxis(Tag),
+    #[error("the font has no instance named '{0}'")]
+    UnknownInstance(String),
+    #[error("malformed WOFF data")]
+    MalformedWoff,
+    #[error("WOFF2 font collections are not supported")]
+    UnsupportedWoff2Collection,
+    #[error("failed to read font file: {0}")]
+    Io(#[from] std::io::Error),
 }
 
+// The font descriptor flags, from the PDF specification.
+const FIXED_PITCH: u32 = 1 << 0;
+const SERIF: u32 = 1 << 1;
+const SYMBOLIC: u32 = 1 << 2;
+const ITALIC: u32 = 1 << 6;
+
+const HEAD: Tag = Tag::from_bytes(b"head");
+const OS2: Tag = Tag::from_bytes(b"OS/2");
+
 const FONT_DATA: &[u8] = include_bytes!(concat!(
     env!("OUT_DIR"),
     "/noto-serif/NotoSerif-Regular.ttf"
@@ -43,7 +119,13 @@ impl Default for Font<'static> {
 
 impl<'a> Font<'a> {
     pub fn new(data: &'a [u8]) -> Result<Self, Error> {
-        let face = Face::parse(data, 0)?;
+        Self::with_index(data, 0)
+    }
+
+    /// Loads one of the faces of a TrueType or OpenType collection, by its index in the
+    /// collection. A font that isn't a collection only has face 0.
+    pub fn with_index(data: &'a [u8], index: u32) -> Result<Self, Error> {
+        let face = Face::parse(data, index)?;
 
         let ps_name = face
             .names()
@@ -57,20 +139,151 @@ impl<'a> Font<'a> {
             data,
             face,
             ps_name,
+            metrics_source: None,
         })
     }
 
     /// Converts from font units to thousanths of an em.
-    pub fn to_milli_em(&self, units: i16) -> i32 {
-        1000 * units as i32 / self.face.units_per_em() as i32
+    pub fn to_milli_em(&self, units: impl Into<i32>) -> i32 {
+        (1000 * units.into() as i64 / self.face.units_per_em() as i64) as i32
     }
 
     pub fn metrics(&self) -> TextMetrics {
         let scale = 1.0 / self.face.units_per_em() as f32;
+        let (source, ascent, descent, line_gap) = self.line_metrics();
         TextMetrics {
-            ascent: self.face.ascender() as f32 * scale,
-            descent: self.face.descender() as f32 * scale,
-            line_gap: self.face.line_gap() as f32 * scale,
+            ascent: ascent as f32 * scale,
+            descent: descent as f32 * scale,
+            line_gap: line_gap as f32 * scale,
+            source,
+            cap_height: self.cap_height() as f32 * scale,
+            x_height: self.x_height() as f32 * scale,
+        }
+    }
+
+    /// Reads the ascent, descent and line gap from the chosen source, falling back to `hhea` if
+    /// the font has no `OS/2` table.
+    fn line_metrics(&self) -> (MetricsSource, i16, i16, i16) {
+        let hhea = self.face.tables().hhea;
+        let Some(os2) = self.face.tables().os2 else {
+            return (
+                MetricsSource::Hhea,
+                hhea.ascender,
+                hhea.descender,
+                hhea.line_gap,
+            );
+        };
+
+        let source = self.metrics_source.unwrap_or_else(|| {
+            // Some fonts leave `hhea` empty and rely on `OS/2` alone.
+            let is_hhea_empty = hhea.ascender == 0 && hhea.descender == 0;
+            if os2.use_typographic_metrics() || is_hhea_empty {
+                MetricsSource::Typo
+            } else {
+                MetricsSource::Hhea
+            }
+        });
+        match source {
+            MetricsSource::Hhea => (source, hhea.ascender, hhea.descender, hhea.line_gap),
+            MetricsSource::Typo => (
+                source,
+                self.vary_metric(b"hasc", os2.typographic_ascender()),
+                self.vary_metric(b"hdsc", os2.typographic_descender()),
+                self.vary_metric(b"hlgp", os2.typographic_line_gap()),
+            ),
+            MetricsSource::Win => (
+                source,
+                self.vary_metric(b"hcla", os2.windows_ascender()),
+                // The delta applies to the positive descent that the table stores.
+                -self.vary_metric(b"hcld", -os2.windows_descender()),
+                0,
+            ),
+        }
+    }
+
+    /// Applies the `MVAR` delta with the given tag to a metric, for variable fonts.
+    fn vary_metric(&self, tag: &[u8; 4], value: i16) -> i16 {
+        let delta = self.face.tables().mvar.and_then(|mvar| {
+            mvar.metric_offset(Tag::from_bytes(tag), self.face.variation_coordinates())
+        });
+        match delta {
+            Some(delta) => (value as f32 + delta).round() as i16,
+            None => value,
+        }
+    }
+
+    /// The height of capital letters, from `OS/2`, or else measured from 'H', or else the ascender.
+    fn cap_height(&self) -> i16 {
+        self.face
+            .capital_height()
+            .filter(|&height| height > 0)
+            .or_else(|| self.glyph_top('H'))
+            .unwrap_or_else(|| self.face.ascender())
+    }
+
+    /// The height of lowercase letters, from `OS/2`, or else measured from 'x', or else zero.
+    fn x_height(&self) -> i16 {
+        self.face
+            .x_height()
+            .filter(|&height| height > 0)
+            .or_else(|| self.glyph_top('x'))
+            .unwrap_or_default()
+    }
+
+    fn glyph_top(&self, c: char) -> Option<i16> {
+        let glyph_id = self.face.glyph_index(c)?;
+        Some(self.face.glyph_bounding_box(glyph_id)?.y_max)
+    }
+
+    pub fn descriptor(&self) -> FontDescriptor {
+        // Fonts are embedded with their own encoding, which viewers only use for symbolic fonts.
+        let mut flags = SYMBOLIC;
+        if self.face.is_monospaced() {
+            flags |= FIXED_PITCH;
+        }
+        if self.is_serif() {
+            flags |= SERIF;
+        }
+        // The italic bit of `macStyle`, in the low byte of the big-endian field.
+        let is_italic_mac_style = self
+            .face
+            .raw_face()
+            .table(HEAD)
+            .and_then(|head| head.get(45))
+            .is_some_and(|&mac_style| mac_style & 2 != 0);
+        if self.face.is_italic()
+            || self.face.italic_angle().is_some_and(|angle| angle != 0.0)
+            || is_italic_mac_style
+        {
+            flags |= ITALIC;
+        }
+
+        // A common estimate, which gives 95 for regular and 169 for bold.
+        let weight = self.face.weight().to_number() as f32;
+        let stem_v = 10.0 + 0.244 * (weight - 50.0);
+
+        FontDescriptor {
+            flags,
+            cap_height: self.to_milli_em(self.cap_height()),
+            x_height: self.to_milli_em(self.x_height()),
+            stem_v: stem_v.round() as i32,
+        }
+    }
+
+    /// Whether the font has serifs, going by its IBM family class or else its PANOSE
+    /// classification in `OS/2`.
+    fn is_serif(&self) -> bool {
+        let Some(os2) = self.face.raw_face().table(OS2) else {
+            return false;
+        };
+        match os2.get(30..34) {
+            // Oldstyle, transitional, modern, clarendon, slab and freeform serifs.
+            Some(&[class, ..]) if class != 0 => matches!(class, 1..=5 | 7),
+            // Latin text with any of the serif styles.
+            Some(&[_, _, family_kind, serif_style]) => {
+                family_kind == 2 && (2..=10).contains(&serif_style)
+            }
+            _ => false,
         }
     }
 }
@@ -81,6 +294,9 @@ impl TextMetrics {
             ascent: self.ascent.max(other.ascent), // Choose the uppermost ascent.
             descent: self.descent.min(other.descent), // Choose the lowermost descent.
             line_gap: self.line_gap.max(other.line_gap), // Choose the largest line gap.
+            source: self.source,                   // Keep the source of the first metrics.
+            cap_height: self.cap_height.max(other.cap_height),
+            x_height: self.x_height.max(other.x_height),
         }
     }
 
@@ -97,13 +313,16 @@ impl Mul<f32> for TextMetrics {
             ascent: self.ascent * rhs,
             descent: self.descent * rhs,
             line_gap: self.line_gap * rhs,
+            source: self.source,
+            cap_height: self.cap_height * rhs,
+            x_height: self.x_height * rhs,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::font::{Font, TextMetrics};
+    use crate::font::{generate::VecExt, woff, Font, MetricsSource, TextMetrics};
 
     #[allow(clippy::format_collect)] // We don't care about small optimisations in tests.
     fn sha256_as_hex(data: &[u8]) -> String {
@@ -127,6 +346,47 @@ mod tests {
         );
     }
 
+    /// Bundles fonts into a TrueType collection, moving their table offsets along.
+    fn collection(fonts: &[&[u8]]) -> Vec<u8> {
+        let mut data = b"ttcf\0\x01\0\0".to_vec();
+        data.push_u32(fonts.len() as u32);
+        let mut offset = 12 + 4 * fonts.len();
+        for font in fonts {
+            data.push_u32(offset as u32);
+            offset += font.len().next_multiple_of(4);
+        }
+        for font in fonts {
+            let base = data.len();
+            data.extend_from_slice(font);
+            data.resize(data.len().next_multiple_of(4), 0);
+            let num_tables = u16::from_be_bytes([data[base + 4], data[base + 5]]) as usize;
+            for i in 0..num_tables {
+                let at = base + 12 + 16 * i + 8;
+                let table_offset = u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+                data[at..at + 4].copy_from_slice(&(table_offset + base as u32).to_be_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_with_index() {
+        let font = Font::default();
+        let woff2 = include_bytes!("../../tests/fonts/OpenSans-Regular.woff2");
+        let open_sans = woff::decode(woff2).unwrap();
+        let data = collection(&[font.data, &open_sans]);
+
+        let first = Font::with_index(&data, 0).unwrap();
+        assert_eq!(first.ps_name, font.ps_name);
+        assert_eq!(first.face.number_of_glyphs(), font.face.number_of_glyphs());
+        let second = Font::with_index(&data, 1).unwrap();
+        assert_eq!(second.ps_name, "OpenSans-Regular");
+        assert_eq!(second.face.number_of_glyphs(), 902);
+        assert!(Font::with_index(&data, 2).is_err());
+        // A single font only has face 0.
+        assert!(Font::with_index(font.data, 1).is_err());
+    }
+
     #[test]
     fn test_font_details() {
         let font = Font::default();
@@ -137,6 +397,41 @@ mod tests {
         assert_eq!(metrics.ascent, 1.0688477);
         assert_eq!(metrics.descent, -0.29296875);
         assert_eq!(metrics.line_gap, 0.0);
+        assert_eq!(metrics.source, MetricsSource::Hhea);
+        assert_eq!(metrics.cap_height, 0.7138672);
+        assert_eq!(metrics.x_height, 0.5361328);
+    }
+
+    #[test]
+    fn test_metrics_source() {
+        let mut font = Font::default();
+        let os2 = font.face.tables().os2.unwrap();
+        let scale = 1.0 / font.face.units_per_em() as f32;
+
+        font.metrics_source = Some(MetricsSource::Typo);
+        let metrics = font.metrics();
+        assert_eq!(metrics.source, MetricsSource::Typo);
+        assert_eq!(metrics.ascent, os2.typographic_ascender() as f32 * scale);
+        assert_eq!(metrics.descent, os2.typographic_descender() as f32 * scale);
+        assert_eq!(metrics.line_gap, os2.typographic_line_gap() as f32 * scale);
+
+        font.metrics_source = Some(MetricsSource::Win);
+        let metrics = font.metrics();
+        assert_eq!(metrics.source, MetricsSource::Win);
+        assert_eq!(metrics.ascent, os2.windows_ascender() as f32 * scale);
+        assert_eq!(metrics.descent, os2.windows_descender() as f32 * scale);
+        assert_eq!(metrics.line_gap, 0.0);
+    }
+
+    #[test]
+    fn test_descriptor() {
+        let font = Font::default();
+
+        let descriptor = font.descriptor();
+        assert_eq!(descriptor.flags, 6); // Serif and symbolic.
+        assert_eq!(descriptor.cap_height, 713);
+        assert_eq!(descriptor.x_height, 536);
+        assert_eq!(descriptor.stem_v, 95);
     }
 
     #[test]
@@ -147,6 +442,10 @@ mod tests {
         assert_eq!(font.to_milli_em(0), 0);
         assert_eq!(font.to_milli_em(256), 125);
         assert_eq!(font.to_milli_em(-512), -250);
+
+        // Advances from shaping and glyph widths can be bigger than an `i16`.
+        assert_eq!(font.to_milli_em(40960), 20000);
+        assert_eq!(font.to_milli_em(u16::MAX), 31999);
     }
 
     #[test]
@@ -155,17 +454,26 @@ mod tests {
             ascent: 1.2,
             descent: -0.2,
             line_gap: 0.0,
+            source: MetricsSource::Hhea,
+            cap_height: 0.7,
+            x_height: 0.5,
         };
         let metrics2 = TextMetrics {
             ascent: 1.0,
             descent: -0.5,
             line_gap: 0.1,
+            source: MetricsSource::Typo,
+            cap_height: 0.75,
+            x_height: 0.45,
         };
 
         let max_metrics = metrics1.max(metrics2);
         assert_eq!(max_metrics.ascent, 1.2);
         assert_eq!(max_metrics.descent, -0.5);
         assert_eq!(max_metrics.line_gap, 0.1);
+        assert_eq!(max_metrics.source, MetricsSource::Hhea);
+        assert_eq!(max_metrics.cap_height, 0.75);
+        assert_eq!(max_metrics.x_height, 0.5);
     }
 
     #[test]
@@ -174,6 +482,7 @@ mod tests {
             ascent: 1.0688477,
             descent: -0.29296875,
             line_gap: 0.0,
+            ..Default::default()
         };
         assert_eq!(metrics.line_height(), 1.3618164);
     }
@@ -184,11 +493,17 @@ mod tests {
             ascent: 1.0688477,
             descent: -0.29296875,
             line_gap: 0.0,
+            source: MetricsSource::Hhea,
+            cap_height: 0.7138672,
+            x_height: 0.5361328,
         };
 
         let scaled_metrics = metrics * 12.0;
         assert_eq!(scaled_metrics.ascent, 12.826172);
         assert_eq!(scaled_metrics.descent, -3.515625);
         assert_eq!(scaled_metrics.line_gap, 0.0);
+        assert_eq!(scaled_metrics.source, MetricsSource::Hhea);
+        assert_eq!(scaled_metrics.cap_height, 8.566406);
+        assert_eq!(scaled_metrics.x_height, 6.4335938);
     }
 }
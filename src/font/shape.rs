@@ -0,0 +1,128 @@
+use rustybuzz::UnicodeBuffer;
+use ttf_parser::GlyphId;
+use unicode_script::{Script, UnicodeScript};
+
+use super::Font;
+
+/// A glyph positioned by the shaper, with distances in thousandths of an em.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyph {
+    pub id: GlyphId,
+    /// The text of the cluster that the glyph starts, or an empty string for the other glyphs of
+    /// the cluster.
+    pub text: String,
+    /// How far the shaper moves along the line after this glyph.
+    pub advance: i32,
+    /// The advance in the font's metrics, which is what a PDF viewer moves by.
+    pub glyph_advance: i32,
+    pub x_offset: i32,
+    pub y_offset: i32,
+}
+
+/// Whether a character belongs to a script whose glyphs depend on the characters around them, such
+/// as the joining forms of Arabic or the conjuncts of Devanagari.
+pub fn needs_shaping(c: char) -> bool {
+    matches!(
+        c.script(),
+        Script::Arabic
+            | Script::Syriac
+            | Script::Nko
+            | Script::Devanagari
+            | Script::Bengali
+            | Script::Gurmukhi
+            | Script::Gujarati
+            | Script::Oriya
+            | Script::Tamil
+            | Script::Telugu
+            | Script::Kannada
+            | Script::Malayalam
+            | Script::Sinhala
+    )
+}
+
+impl Font<'_> {
+    /// Shapes a run of text with the OpenType features that its script needs.
+    ///
+    /// The script and direction are guessed from the text, and the glyphs are returned in visual
+    /// order, so right-to-left runs come out reversed.
+    pub fn shape(&self, text: &str) -> Vec<Glyph> {
+        let face = rustybuzz::Face::from_face(self.face.clone());
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(&face, &[], buffer);
+
+        let infos = output.glyph_infos();
+
+        // Clusters are numbered by the byte offset of their first character.
+        let mut cluster_starts = infos
+            .iter()
+            .map(|info| info.cluster as usize)
+            .collect::<Vec<_>>();
+        cluster_starts.sort_unstable();
+        cluster_starts.dedup();
+        let cluster_text = |start: usize| {
+            let end = cluster_starts
+                .iter()
+                .find(|&&i| i > start)
+                .map_or(text.len(), |&i| i);
+            text[start..end].to_owned()
+        };
+
+        let mut glyphs = Vec::<Glyph>::new();
+        for (i, (info, position)) in infos.iter().zip(output.glyph_positions()).enumerate() {
+            let id = GlyphId(info.glyph_id as u16);
+            let cluster = info.cluster as usize;
+            let starts_cluster = !infos[..i].iter().any(|other| other.cluster == info.cluster);
+
+            glyphs.push(Glyph {
+                id,
+                text: if starts_cluster {
+                    cluster_text(cluster)
+                } else {
+                    String::new()
+                },
+                advance: self.to_milli_em(position.x_advance),
+                glyph_advance: self.to_milli_em(self.glyph_advance(id).unwrap_or_default()),
+                x_offset: self.to_milli_em(position.x_offset),
+                y_offset: self.to_milli_em(position.y_offset),
+            });
+        }
+
+        glyphs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::font::Font;
+
+    use super::needs_shaping;
+
+    #[test]
+    fn test_needs_shaping() {
+        assert!(needs_shaping('ب'));
+        assert!(needs_shaping('क'));
+        assert!(needs_shaping('\u{94d}'));
+        assert!(!needs_shaping('a'));
+        assert!(!needs_shaping(' '));
+    }
+
+    #[test]
+    fn test_shape_clusters() {
+        let font = Font::default();
+
+        // The accent is composed with its base, and the glyph stands for both characters.
+        let glyphs = font.shape("ae\u{301}");
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!(glyphs[0].id, font.face.glyph_index('a').unwrap());
+        assert_eq!(glyphs[0].text, "a");
+        assert_eq!(glyphs[1].id, font.face.glyph_index('é').unwrap());
+        assert_eq!(glyphs[1].text, "e\u{301}");
+
+        // The last glyph isn't kerned against anything, so it moves by its own advance.
+        assert_eq!(glyphs[1].advance, glyphs[1].glyph_advance);
+        assert_eq!(glyphs[1].x_offset, 0);
+        assert_eq!(glyphs[1].y_offset, 0);
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+use std::fmt::Write;
+
+#[cfg(test)]
+use ttf_parser::OutlineBuilder;
+
+/// Reads big-endian values from font data one after another, returning `None` past its end.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Reader<'a> {
+    pub(super) data: &'a [u8],
+    pub(super) offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(data: &'a [u8], offset: usize) -> Self {
+        Self { data, offset }
+    }
+
+    pub(super) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    pub(super) fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    pub(super) fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(super) fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|value| value as i16)
+    }
+
+    pub(super) fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Records an outline as text.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct Path(pub(crate) String);
+
+#[cfg(test)]
+impl OutlineBuilder for Path {
+    fn move_to(&mut self, x: f32, y: f32) {
+        write!(self.0, "M {x} {y} ").unwrap();
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        write!(self.0, "L {x} {y} ").unwrap();
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        write!(self.0, "Q {x1} {y1} {x} {y} ").unwrap();
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        write!(self.0, "C {x1} {y1} {x2} {y2} {x} {y} ").unwrap();
+    }
+
+    fn close(&mut self) {
+        write!(self.0, "Z ").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reader;
+
+    #[test]
+    fn test_reader() {
+        let mut reader = Reader::new(&[0, 1, 0xff, 0xfe, 0, 0, 1, 0, 7], 1);
+        assert_eq!(reader.u8(), Some(1));
+        assert_eq!(reader.i16(), Some(-2));
+        assert_eq!(reader.u32(), Some(256));
+        assert_eq!(reader.u16(), None);
+        assert_eq!(reader.offset, 8);
+        assert_eq!(reader.bytes(usize::MAX), None);
+    }
+}
@@ -0,0 +1,410 @@
+use ttf_parser::{GlyphId, Tag};
+
+use super::{util::Reader, Error, Font};
+
+const FVAR: Tag = Tag::from_bytes(b"fvar");
+const GVAR: Tag = Tag::from_bytes(b"gvar");
+
+/// A point in the design space of a variable font that the font gives a name, such as "Bold" or
+/// "Condensed Light".
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedInstance {
+    pub name: String,
+    pub coordinates: Vec<(Tag, f32)>,
+}
+
+impl<'a> Font<'a> {
+    /// Loads a variable font at the given axis coordinates, in the units of each axis, such as
+    /// `(Tag::from_bytes(b"wght"), 700.0)`. Axes that aren't given keep their default values.
+    pub fn with_variations(data: &'a [u8], coordinates: &[(Tag, f32)]) -> Result<Self, Error> {
+        let mut font = Self::new(data)?;
+        for &(axis, value) in coordinates {
+            let has_axis = font
+                .face
+                .variation_axes()
+                .into_iter()
+                .any(|variation_axis| variation_axis.tag == axis);
+            if !has_axis {
+                return Err(Error::UnknownAxis(axis));
+            }
+            font.face.set_variation(axis, value);
+        }
+        Ok(font)
+    }
+
+    /// Loads a variable font at one of its named instances.
+    pub fn with_named_instance(data: &'a [u8], name: &str) -> Result<Self, Error> {
+        let font = Self::new(data)?;
+        let instance = font
+            .named_instances()
+            .into_iter()
+            .find(|instance| instance.name == name)
+            .ok_or_else(|| Error::UnknownInstance(name.to_owned()))?;
+        Self::with_variations(data, &instance.coordinates)
+    }
+
+    /// The named instances in the font's `fvar` table, which static fonts don't have.
+    pub fn named_instances(&self) -> Vec<NamedInstance> {
+        let raw_face = self.face.raw_face();
+        let axes = self
+            .face
+            .variation_axes()
+            .into_iter()
+            .map(|axis| axis.tag)
+            .collect::<Vec<_>>();
+        let Some(fvar) = raw_face.table(FVAR) else {
+            return Vec::new();
+        };
+
+        let read_u16 = |offset: usize| {
+            fvar.get(offset..offset + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+        };
+        let (Some(axes_offset), Some(axis_count), Some(axis_size), Some(count), Some(size)) = (
+            read_u16(4),
+            read_u16(8),
+            read_u16(10),
+            read_u16(12),
+            read_u16(14),
+        ) else {
+            return Vec::new();
+        };
+        if axis_count != axes.len() {
+            return Vec::new();
+        }
+
+        // The instance records follow the axis records.
+        let instances_offset = axes_offset + axis_count * axis_size;
+        (0..count)
+            .filter_map(|i| {
+                let offset = instances_offset + i * size;
+                let name_id = read_u16(offset)? as u16;
+                let coordinates = axes
+                    .iter()
+                    .enumerate()
+                    .map(|(j, &axis)| {
+                        let bytes = fvar.get(offset + 4 + 4 * j..offset + 8 + 4 * j)?;
+                        let fixed = i32::from_be_bytes(bytes.try_into().ok()?);
+                        Some((axis, fixed as f32 / 65536.0))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+
+                let name = self
+                    .face
+                    .names()
+                    .into_iter()
+                    .filter(|name| name.name_id == name_id)
+                    .find_map(|name| name.to_string())?;
+
+                Some(NamedInstance { name, coordinates })
+            })
+            .collect()
+    }
+
+    /// Whether the font is a variable font set away from its default instance, so that its
+    /// glyphs and metrics have to be varied before they're used.
+    pub fn is_instanced(&self) -> bool {
+        self.face.is_variable() && self.face.has_non_default_variation_coordinates()
+    }
+
+    /// The horizontal advance of a glyph at the font's variation coordinates.
+    ///
+    /// The `HVAR` table holds the variations of the advances if the font has one. Otherwise they
+    /// are the variations of the phantom points at the glyph's origin and advance in `gvar`.
+    pub fn glyph_advance(&self, glyph_id: GlyphId) -> Option<u16> {
+        let advance = self.face.glyph_hor_advance(glyph_id)?;
+        if !self.is_instanced() || self.face.tables().hvar.is_some() {
+            return Some(advance);
+        }
+
+        let Some(gvar) = self.face.raw_face().table(GVAR) else {
+            return Some(advance);
+        };
+        let coordinates = self
+            .face
+            .variation_coordinates()
+            .iter()
+            .map(|coordinate| coordinate.get())
+            .collect::<Vec<_>>();
+        let point_count = self.glyph_point_count(glyph_id).ok()?;
+        let delta = phantom_advance_delta(gvar, glyph_id, &coordinates, point_count)?;
+
+        u16::try_from((advance as f32 + delta).round() as i32).ok()
+    }
+}
+
+/// Finds how far the variations in a `gvar` table move the advance of a glyph with
+/// `point_count` points, from the deltas of the second phantom point less those of the first.
+///
+/// The coordinates are normalized, in F2DOT14.
+fn phantom_advance_delta(
+    gvar: &[u8],
+    glyph_id: GlyphId,
+    coordinates: &[i16],
+    point_count: usize,
+) -> Option<f32> {
+    let mut reader = Reader::new(gvar, 4);
+    let axis_count = reader.u16()? as usize;
+    let shared_tuple_count = reader.u16()? as usize;
+    let shared_tuples_offset = reader.u32()? as usize;
+    let _glyph_count = reader.u16()?;
+    let flags = reader.u16()?;
+    let data_offset = reader.u32()? as usize;
+    if axis_count != coordinates.len() {
+        return None;
+    }
+
+    // Short offsets are stored divided by two.
+    let glyph_range = if flags & 1 == 0 {
+        let mut offsets = Reader::new(gvar, 20 + 2 * glyph_id.0 as usize);
+        2 * offsets.u16()? as usize..2 * offsets.u16()? as usize
+    } else {
+        let mut offsets = Reader::new(gvar, 20 + 4 * glyph_id.0 as usize);
+        offsets.u32()? as usize..offsets.u32()? as usize
+    };
+    if glyph_range.is_empty() {
+        return Some(0.0);
+    }
+    let data = gvar.get(data_offset + glyph_range.start..data_offset + glyph_range.end)?;
+
+    let shared_tuple = |index: usize| {
+        if index >= shared_tuple_count {
+            return None;
+        }
+        let mut tuple = Reader::new(gvar, shared_tuples_offset + 2 * axis_count * index);
+        (0..axis_count)
+            .map(|_| tuple.i16())
+            .collect::<Option<Vec<_>>>()
+    };
+
+    let mut headers = Reader::new(data, 0);
+    let tuple_count = headers.u16()?;
+    let mut serialized = Reader::new(data, headers.u16()? as usize);
+    let shared_points = if tuple_count & 0x8000 != 0 {
+        Some(read_points(&mut serialized)?)
+    } else {
+        None
+    };
+
+    // The phantom points come after the points of the outline.
+    let origin = point_count;
+    let advance = point_count + 1;
+    let total_points = point_count + 4;
+
+    let mut delta = 0.0;
+    for _ in 0..tuple_count & 0x0fff {
+        let size = headers.u16()? as usize;
+        let index = headers.u16()?;
+        let read_tuple = |headers: &mut Reader| {
+            (0..axis_count)
+                .map(|_| headers.i16())
+                .collect::<Option<Vec<_>>>()
+        };
+        let peak = if index & 0x8000 != 0 {
+            read_tuple(&mut headers)?
+        } else {
+            shared_tuple((index & 0x0fff) as usize)?
+        };
+        let intermediate = if index & 0x4000 != 0 {
+            Some((read_tuple(&mut headers)?, read_tuple(&mut headers)?))
+        } else {
+            None
+        };
+
+        let mut tuple_data = Reader::new(serialized.bytes(size)?, 0);
+        let scalar = tuple_scalar(coordinates, &peak, intermediate.as_ref());
+        if scalar == 0.0 {
+            continue;
+        }
+
+        let points = if index & 0x2000 != 0 {
+            read_points(&mut tuple_data)?
+        } else {
+            shared_points.clone()?
+        };
+        let points = points.unwrap_or_else(|| (0..total_points).collect());
+        let x_deltas = read_deltas(&mut tuple_data, points.len())?;
+
+        for (&point, &x_delta) in points.iter().zip(&x_deltas) {
+            if point == advance {
+                delta += scalar * x_delta as f32;
+            } else if point == origin {
+                delta -= scalar * x_delta as f32;
+            }
+        }
+    }
+
+    Some(delta)
+}
+
+/// How much of a tuple's deltas apply at the coordinates, from its peak and, if it has one, the
+/// start and end of its intermediate region.
+fn tuple_scalar(
+    coordinates: &[i16],
+    peak: &[i16],
+    intermediate: Option<&(Vec<i16>, Vec<i16>)>,
+) -> f32 {
+    let mut scalar = 1.0;
+    for (i, (&coordinate, &peak)) in coordinates.iter().zip(peak).enumerate() {
+        let (coordinate, peak) = (coordinate as f32, peak as f32);
+        if peak == 0.0 || coordinate == peak {
+            continue;
+        }
+        if coordinate == 0.0 {
+            return 0.0;
+        }
+
+        if let Some((start, end)) = intermediate {
+            let (start, end) = (start[i] as f32, end[i] as f32);
+            if coordinate <= start || coordinate >= end {
+                return 0.0;
+            }
+            scalar *= if coordinate < peak {
+                (coordinate - start) / (peak - start)
+            } else {
+                (end - coordinate) / (end - peak)
+            };
+        } else {
+            if coordinate < peak.min(0.0) || coordinate > peak.max(0.0) {
+                return 0.0;
+            }
+            scalar *= coordinate / peak;
+        }
+    }
+    scalar
+}
+
+/// Reads packed point numbers, which are `None` if the deltas are for all the points.
+fn read_points(reader: &mut Reader) -> Option<Option<Vec<usize>>> {
+    let first = reader.u8()? as usize;
+    let count = if first & 0x80 != 0 {
+        (first & 0x7f) << 8 | reader.u8()? as usize
+    } else {
+        first
+    };
+    if count == 0 {
+        return Some(None);
+    }
+
+    let mut points = Vec::with_capacity(count);
+    let mut point = 0;
+    while points.len() < count {
+        let control = reader.u8()?;
+        let run = (control & 0x7f) as usize + 1;
+        for _ in 0..run {
+            // Each point number is the difference from the one before.
+            point += if control & 0x80 != 0 {
+                reader.u16()? as usize
+            } else {
+                reader.u8()? as usize
+            };
+            points.push(point);
+        }
+    }
+    Some(Some(points))
+}
+
+/// Reads `count` packed deltas.
+fn read_deltas(reader: &mut Reader, count: usize) -> Option<Vec<i16>> {
+    let mut deltas = Vec::with_capacity(count);
+    while deltas.len() < count {
+        let control = reader.u8()?;
+        let run = (control & 0x3f) as usize + 1;
+        for _ in 0..run {
+            deltas.push(if control & 0x80 != 0 {
+                0
+            } else if control & 0x40 != 0 {
+                reader.i16()?
+            } else {
+                reader.u8()? as i8 as i16
+            });
+        }
+    }
+    Some(deltas)
+}
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::GlyphId;
+
+    use crate::font::Font;
+
+    use super::{phantom_advance_delta, read_deltas, read_points, tuple_scalar, Reader};
+
+    #[test]
+    fn test_tuple_scalar() {
+        // Halfway to the peak of the axis
+        assert_eq!(tuple_scalar(&[0x2000], &[0x4000], None), 0.5);
+        assert_eq!(tuple_scalar(&[-0x2000], &[0x4000], None), 0.0);
+        assert_eq!(tuple_scalar(&[0x2000, 0], &[0x4000, 0], None), 0.5);
+        assert_eq!(
+            tuple_scalar(&[0x2000, 0x1000], &[0x4000, 0x2000], None),
+            0.25
+        );
+
+        let region = (vec![0x1000], vec![0x4000]);
+        assert_eq!(tuple_scalar(&[0x3000], &[0x2000], Some(&region)), 0.5);
+        assert_eq!(tuple_scalar(&[0x0800], &[0x2000], Some(&region)), 0.0);
+    }
+
+    #[test]
+    fn test_read_packed() {
+        // A run of two byte points and a run of one word point, each a difference from the last
+        let data = [3, 0x01, 1, 2, 0x80, 0x01, 0x00];
+        let points = read_points(&mut Reader::new(&data, 0)).unwrap();
+        assert_eq!(points, Some(vec![1, 3, 259]));
+        assert_eq!(read_points(&mut Reader::new(&[0], 0)).unwrap(), None);
+
+        // Two byte deltas, three zeros and a word delta
+        let data = [0x01, 0xff, 5, 0x82, 0x40, 0x01, 0x00];
+        let deltas = read_deltas(&mut Reader::new(&data, 0), 6).unwrap();
+        assert_eq!(deltas, [-1, 5, 0, 0, 0, 256]);
+    }
+
+    #[test]
+    fn test_phantom_advance_delta() {
+        let mut gvar = vec![
+            0, 1, 0, 0, // version
+            0, 1, // axis count
+            0, 0, // shared tuple count
+            0, 0, 0, 20, // shared tuples offset
+            0, 1, // glyph count
+            0, 0, // flags
+            0, 0, 0, 24, // glyph variation data offset
+            0, 0, 0, 0, // glyph variation data offsets / 2 (backfilled later)
+        ];
+        let glyph = [
+            0, 1, // tuple count
+            0, 10, // serialized data offset
+            0, 8, // variation data size
+            0xa0, 0x00, // embedded peak and private points
+            0x40, 0x00, // peak
+            2, 0x01, 2, 1, // the phantom points of a glyph with two points
+            0x01, 0xf6, 30,   // x deltas
+            0x81, // y deltas
+        ];
+        gvar[23] = (glyph.len() / 2) as u8;
+        gvar.extend(glyph);
+
+        // The origin moves left by 10 and the advance right by 30.
+        let delta = phantom_advance_delta(&gvar, GlyphId(0), &[0x4000], 2).unwrap();
+        assert_eq!(delta, 40.0);
+        let delta = phantom_advance_delta(&gvar, GlyphId(0), &[0x2000], 2).unwrap();
+        assert_eq!(delta, 20.0);
+        let delta = phantom_advance_delta(&gvar, GlyphId(0), &[0], 2).unwrap();
+        assert_eq!(delta, 0.0);
+    }
+
+    #[test]
+    fn test_static_font() {
+        let font = Font::default();
+
+        assert!(font.named_instances().is_empty());
+        assert!(!font.is_instanced());
+        let glyph_id = font.face.glyph_index('a').unwrap();
+        assert_eq!(
+            font.glyph_advance(glyph_id),
+            font.face.glyph_hor_advance(glyph_id)
+        );
+    }
+}
@@ -0,0 +1,589 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use ttf_parser::Tag;
+
+use super::{
+    generate::{component_glyph_table_len, write_sfnt, Contours, VecExt},
+    util::Reader,
+    Error,
+};
+
+const GLYF: Tag = Tag::from_bytes(b"glyf");
+const HHEA: Tag = Tag::from_bytes(b"hhea");
+const HMTX: Tag = Tag::from_bytes(b"hmtx");
+const LOCA: Tag = Tag::from_bytes(b"loca");
+const MAXP: Tag = Tag::from_bytes(b"maxp");
+
+const WOFF_SIGNATURE: &[u8] = b"wOFF";
+const WOFF2_SIGNATURE: &[u8] = b"wOF2";
+const COLLECTION_FLAVOR: u32 = u32::from_be_bytes(*b"ttcf");
+
+/// The contents of the tables of a font by their tags.
+type Tables = BTreeMap<Tag, Vec<u8>>;
+
+/// The tags that a WOFF2 table directory refers to by index.
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// Turns WOFF or WOFF2 data into the sfnt data that [`Font::new`](super::Font::new) reads, and
+/// passes any other data through.
+pub fn decode(data: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    if data.starts_with(WOFF_SIGNATURE) {
+        decode_woff(data).map(Cow::Owned)
+    } else if data.starts_with(WOFF2_SIGNATURE) {
+        decode_woff2(data).map(Cow::Owned)
+    } else {
+        Ok(Cow::Borrowed(data))
+    }
+}
+
+/// Decodes a WOFF file, whose tables are each compressed with zlib if that makes them smaller.
+pub fn decode_woff(data: &[u8]) -> Result<Vec<u8>, Error> {
+    read_woff(data).ok_or(Error::MalformedWoff)
+}
+
+fn read_woff(data: &[u8]) -> Option<Vec<u8>> {
+    let mut header = Reader::new(data, 4);
+    let flavor = header.u32()?;
+    let _length = header.u32()?;
+    let num_tables = header.u16()?;
+
+    let mut directory = Reader::new(data, 44);
+    let mut tables = BTreeMap::new();
+    for _ in 0..num_tables {
+        let tag = Tag(directory.u32()?);
+        let offset = directory.u32()? as usize;
+        let compressed_len = directory.u32()? as usize;
+        let len = directory.u32()? as usize;
+        let _checksum = directory.u32()?;
+
+        let table = Reader::new(data, offset).bytes(compressed_len)?;
+        let table = if compressed_len < len {
+            miniz_oxide::inflate::decompress_to_vec_zlib(table).ok()?
+        } else {
+            table.to_vec()
+        };
+        if table.len() != len {
+            return None;
+        }
+        tables.insert(tag, table);
+    }
+
+    Some(write_sfnt(flavor, tables))
+}
+
+/// Decodes a WOFF2 file, whose tables are compressed together with Brotli.
+///
+/// The `glyf` and `loca` tables are usually transformed into streams that compress better, and
+/// `hmtx` can leave out side bearings that match the glyphs' bounding boxes, so these are rebuilt.
+pub fn decode_woff2(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let flavor = Reader::new(data, 4).u32().ok_or(Error::MalformedWoff)?;
+    if flavor == COLLECTION_FLAVOR {
+        return Err(Error::UnsupportedWoff2Collection);
+    }
+    let (mut tables, mut transformed_tables) =
+        read_woff2_tables(data).ok_or(Error::MalformedWoff)?;
+
+    // The side bearings of hmtx come from the bounding boxes in glyf, so glyf goes first.
+    let mut x_mins = Vec::new();
+    if let Some(glyf) = transformed_tables.remove(&GLYF) {
+        x_mins = reconstruct_glyf(&glyf, &mut tables).ok_or(Error::MalformedWoff)?;
+    }
+    transformed_tables.remove(&LOCA);
+    if let Some(hmtx) = transformed_tables.remove(&HMTX) {
+        let hmtx = reconstruct_hmtx(&hmtx, &tables, &x_mins)?;
+        tables.insert(HMTX, hmtx);
+    }
+    if !transformed_tables.is_empty() {
+        return Err(Error::MalformedWoff);
+    }
+
+    Ok(write_sfnt(flavor, tables))
+}
+
+/// Decompresses the tables of a WOFF2 file, returning the ones that are stored as they are and the
+/// ones that are transformed separately.
+fn read_woff2_tables(data: &[u8]) -> Option<(Tables, Tables)> {
+    let mut header = Reader::new(data, 12);
+    let num_tables = header.u16()?;
+    let _reserved = header.u16()?;
+    let _total_sfnt_size = header.u32()?;
+    let compressed_len = header.u32()? as usize;
+
+    // Each entry is the tag, the transform version and the length in the decompressed stream.
+    let mut directory = Reader::new(data, 48);
+    let mut entries = Vec::new();
+    for _ in 0..num_tables {
+        let flags = directory.u8()?;
+        let tag = match flags & 0x3f {
+            63 => Tag(directory.u32()?),
+            index => Tag::from_bytes(KNOWN_TAGS[index as usize]),
+        };
+        let version = flags >> 6;
+        let len = directory.base128()?;
+        // The glyf and loca tables are transformed unless their version is 3, and other tables
+        // are transformed unless it's 0.
+        let transformed = if tag == GLYF || tag == LOCA {
+            version != 3
+        } else {
+            version != 0
+        };
+        let len = if transformed {
+            directory.base128()?
+        } else {
+            len
+        };
+        entries.push((tag, transformed, len as usize));
+    }
+
+    let mut compressed = directory.bytes(compressed_len)?;
+    let mut stream = Vec::new();
+    brotli_decompressor::BrotliDecompress(&mut compressed, &mut stream).ok()?;
+
+    let mut stream = Reader::new(&stream, 0);
+    let mut tables = BTreeMap::new();
+    let mut transformed_tables = BTreeMap::new();
+    for (tag, transformed, len) in entries {
+        let table = stream.bytes(len)?.to_vec();
+        if transformed {
+            transformed_tables.insert(tag, table);
+        } else {
+            tables.insert(tag, table);
+        }
+    }
+    Some((tables, transformed_tables))
+}
+
+/// Rebuilds the `glyf` and `loca` tables from the streams of a transformed `glyf` table, returning
+/// the left of each glyph's bounding box.
+fn reconstruct_glyf(data: &[u8], tables: &mut Tables) -> Option<Vec<i16>> {
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+    let mut header = Reader::new(data, 2);
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+
+    // The streams follow the header one after another.
+    let mut offset = 36;
+    let mut next_stream = || {
+        let len = header.u32()? as usize;
+        let stream = Reader::new(data, offset).bytes(len)?;
+        offset += len;
+        Some(stream)
+    };
+    let mut n_contours = Reader::new(next_stream()?, 0);
+    let mut n_points = Reader::new(next_stream()?, 0);
+    let mut flags = Reader::new(next_stream()?, 0);
+    let mut glyphs = Reader::new(next_stream()?, 0);
+    let mut composites = Reader::new(next_stream()?, 0);
+    let bboxes = next_stream()?;
+    let mut instructions = Reader::new(next_stream()?, 0);
+    // Which glyphs have an explicit bounding box, as a bitmap padded to a multiple of 4 bytes
+    let bbox_bitmap = Reader::new(bboxes, 0).bytes(4 * num_glyphs.div_ceil(32))?;
+    let mut bboxes = Reader::new(bboxes, bbox_bitmap.len());
+    if option_flags & 1 != 0 {
+        // The overlap bitmap only matters to rasterizers, which don't see it in PDF.
+        Reader::new(data, offset).bytes(num_glyphs.div_ceil(8))?;
+    }
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::new();
+    let mut x_mins = Vec::new();
+    for i in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let has_bbox = bbox_bitmap[i / 8] & (0x80 >> (i % 8)) != 0;
+        let num_contours = n_contours.i16()?;
+
+        let mut glyph = Vec::new();
+        if num_contours == 0 {
+            if has_bbox {
+                return None;
+            }
+        } else if num_contours < 0 {
+            // The components are stored as they are in glyf, but the bounding box is required.
+            if !has_bbox {
+                return None;
+            }
+            glyph.push_u16(num_contours as u16);
+            glyph.extend_from_slice(bboxes.bytes(8)?);
+
+            let mut has_instructions = false;
+            loop {
+                let component_flags = composites.u16()?;
+                glyph.push_u16(component_flags);
+                let len = component_glyph_table_len(component_flags);
+                glyph.extend_from_slice(composites.bytes(len - 2)?);
+                has_instructions |= component_flags & WE_HAVE_INSTRUCTIONS != 0;
+                if component_flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+            if has_instructions {
+                let len = glyphs.u255()?;
+                glyph.push_u16(len);
+                glyph.extend_from_slice(instructions.bytes(len as usize)?);
+            }
+        } else {
+            let mut contours = Contours::default();
+            let mut point_count = 0;
+            for _ in 0..num_contours {
+                let points = n_points.u255()? as usize;
+                if points == 0 {
+                    return None;
+                }
+                point_count += points;
+                contours
+                    .end_points
+                    .push(u16::try_from(point_count - 1).ok()?);
+            }
+
+            let (mut x, mut y) = (0i16, 0i16);
+            for _ in 0..point_count {
+                let flag = flags.u8()?;
+                let (dx, dy) = decode_triplet(flag & 0x7f, &mut glyphs)?;
+                x = x.wrapping_add(dx);
+                y = y.wrapping_add(dy);
+                contours.points.push((x, y, flag & 0x80 == 0));
+            }
+
+            let len = glyphs.u255()?;
+            glyph = contours.write(instructions.bytes(len as usize)?);
+            if has_bbox {
+                glyph[2..10].copy_from_slice(bboxes.bytes(8)?);
+            }
+        }
+
+        x_mins.push(match glyph.get(2..4) {
+            Some(x_min) => i16::from_be_bytes([x_min[0], x_min[1]]),
+            None => 0,
+        });
+        glyf.extend(glyph);
+        // Short offsets are stored divided by two, so the glyphs have to stay aligned.
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::new();
+    for offset in offsets {
+        if index_format == 0 {
+            loca.push_u16((offset / 2) as u16);
+        } else {
+            loca.push_u32(offset as u32);
+        }
+    }
+
+    tables.insert(GLYF, glyf);
+    tables.insert(LOCA, loca);
+    Some(x_mins)
+}
+
+/// Reads the change in position to a point from the triplet encoding of WOFF2, where the flag says
+/// how many bytes the change takes and which signs its parts have.
+fn decode_triplet(flag: u8, glyphs: &mut Reader) -> Option<(i16, i16)> {
+    let with_sign = |flag: u8, value: i32| {
+        let value = value as i16;
+        if flag & 1 != 0 {
+            value
+        } else {
+            -value
+        }
+    };
+    let flag_i = flag as i32;
+
+    Some(match flag {
+        0..=9 => {
+            let b0 = glyphs.u8()? as i32;
+            (0, with_sign(flag, ((flag_i & 14) << 7) + b0))
+        }
+        10..=19 => {
+            let b0 = glyphs.u8()? as i32;
+            (with_sign(flag, (((flag_i - 10) & 14) << 7) + b0), 0)
+        }
+        20..=83 => {
+            let b0 = flag_i - 20;
+            let b1 = glyphs.u8()? as i32;
+            (
+                with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+                with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+            )
+        }
+        84..=119 => {
+            let b0 = flag_i - 84;
+            let (b1, b2) = (glyphs.u8()? as i32, glyphs.u8()? as i32);
+            (
+                with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+                with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+            )
+        }
+        120..=123 => {
+            let (b1, b2, b3) = (
+                glyphs.u8()? as i32,
+                glyphs.u8()? as i32,
+                glyphs.u8()? as i32,
+            );
+            (
+                with_sign(flag, (b1 << 4) + (b2 >> 4)),
+                with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+            )
+        }
+        _ => {
+            let (x, y) = (glyphs.u16()? as i32, glyphs.u16()? as i32);
+            (with_sign(flag, x), with_sign(flag >> 1, y))
+        }
+    })
+}
+
+/// Rebuilds an `hmtx` table whose side bearings were left out where they equal the left of the
+/// glyph's bounding box.
+fn reconstruct_hmtx(data: &[u8], tables: &Tables, x_mins: &[i16]) -> Result<Vec<u8>, Error> {
+    let read_u16 = |tag: Tag, offset: usize| {
+        let table = tables.get(&tag).ok_or(Error::MissingTable(tag))?;
+        Reader::new(table, offset)
+            .u16()
+            .ok_or(Error::MalformedTable(tag))
+    };
+    let num_metrics = read_u16(HHEA, 34)? as usize;
+    let num_glyphs = read_u16(MAXP, 4)? as usize;
+    if num_metrics == 0 || num_metrics > num_glyphs {
+        return Err(Error::MalformedTable(HMTX));
+    }
+
+    let mut reader = Reader::new(data, 0);
+    let flags = reader.u8().ok_or(Error::MalformedWoff)?;
+    let advances = (0..num_metrics)
+        .map(|_| reader.u16())
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Error::MalformedWoff)?;
+    let mut side_bearings = |present: bool, glyphs: std::ops::Range<usize>| {
+        glyphs
+            .map(|i| match present {
+                true => reader.i16(),
+                false => x_mins.get(i).copied(),
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error::MalformedWoff)
+    };
+    let proportional = side_bearings(flags & 1 == 0, 0..num_metrics)?;
+    let monospaced = side_bearings(flags & 2 == 0, num_metrics..num_glyphs)?;
+
+    let mut contents = Vec::new();
+    for (advance, side_bearing) in advances.into_iter().zip(proportional) {
+        contents.push_u16(advance);
+        contents.push_u16(side_bearing as u16);
+    }
+    for side_bearing in monospaced {
+        contents.push_u16(side_bearing as u16);
+    }
+    Ok(contents)
+}
+
+impl Reader<'_> {
+    /// Reads a UIntBase128, which is big-endian in groups of seven bits, with the top bit of each
+    /// byte set if another follows.
+    fn base128(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            // Leading zeros aren't allowed, and the value has to fit in 32 bits.
+            if i == 0 && byte == 0x80 || value >> 25 != 0 {
+                return None;
+            }
+            value = value << 7 | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Reads a 255UInt16, which takes one byte for small values and up to three for larger ones.
+    fn u255(&mut self) -> Option<u16> {
+        const WORD_CODE: u8 = 253;
+        const ONE_MORE_BYTE_CODE_2: u8 = 254;
+        const ONE_MORE_BYTE_CODE_1: u8 = 255;
+        const LOWEST_U_CODE: u16 = 253;
+
+        Some(match self.u8()? {
+            WORD_CODE => self.u16()?,
+            ONE_MORE_BYTE_CODE_1 => self.u8()? as u16 + LOWEST_U_CODE,
+            ONE_MORE_BYTE_CODE_2 => self.u8()? as u16 + 2 * LOWEST_U_CODE,
+            code => code as u16,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use ttf_parser::{GlyphId, Rect};
+
+    use crate::font::{generate::VecExt, Font};
+
+    use super::{decode, decode_triplet, reconstruct_glyf, Tables};
+    use crate::font::util::{Path, Reader};
+
+    /// Wraps the default font in a WOFF file.
+    fn woff() -> Vec<u8> {
+        let font = Font::default();
+        let tables = font
+            .face
+            .raw_face()
+            .table_records
+            .into_iter()
+            .map(|record| {
+                let range = record.offset as usize..(record.offset + record.length) as usize;
+                let table = &font.data[range];
+                // Tables that don't get smaller are stored as they are.
+                let compressed = miniz_oxide::deflate::compress_to_vec_zlib(table, 6);
+                let stored = if compressed.len() < table.len() {
+                    compressed
+                } else {
+                    table.to_vec()
+                };
+                (record.tag, table.len(), stored)
+            })
+            .collect::<Vec<_>>();
+
+        let mut contents = Vec::new();
+        contents.extend(b"wOFF");
+        contents.extend(&font.data[..4]); // flavor
+        contents.push_u32(0); // length
+        contents.push_u16(tables.len() as u16);
+        contents.extend([0; 30]);
+
+        let mut offset = 44 + 20 * tables.len();
+        for (tag, len, compressed) in &tables {
+            contents.push_u32(tag.0);
+            contents.push_u32(offset as u32);
+            contents.push_u32(compressed.len() as u32);
+            contents.push_u32(*len as u32);
+            contents.push_u32(0); // checksum
+            offset += compressed.len();
+        }
+        for (_, _, compressed) in tables {
+            contents.extend(compressed);
+        }
+        contents
+    }
+
+    #[test]
+    fn test_decode_woff() {
+        let font = Font::default();
+        let woff = woff();
+        let sfnt = decode(&woff).unwrap();
+        let decoded = Font::new(&sfnt).unwrap();
+
+        assert_eq!(decoded.ps_name, font.ps_name);
+        assert_eq!(
+            decoded.face.number_of_glyphs(),
+            font.face.number_of_glyphs()
+        );
+        let glyph_id = font.face.glyph_index('a').unwrap();
+        assert_eq!(
+            decoded.face.glyph_bounding_box(glyph_id),
+            font.face.glyph_bounding_box(glyph_id)
+        );
+
+        // Fonts that aren't wrapped are passed through.
+        assert!(matches!(decode(font.data), Ok(Cow::Borrowed(_))));
+        assert!(decode(&woff[..100]).is_err());
+    }
+
+    #[test]
+    fn test_read_numbers() {
+        let data = [0x3f, 0x81, 0x00, 0x8f, 0xff, 0xff, 0xff, 0x7f];
+        let mut reader = Reader::new(&data, 0);
+        assert_eq!(reader.base128().unwrap(), 63);
+        assert_eq!(reader.base128().unwrap(), 128);
+        assert_eq!(reader.base128().unwrap(), u32::MAX);
+        assert_eq!(Reader::new(&[0x80, 0x01], 0).base128(), None);
+
+        let data = [252, 255, 0, 254, 0, 253, 0x12, 0x34];
+        let mut reader = Reader::new(&data, 0);
+        assert_eq!(reader.u255().unwrap(), 252);
+        assert_eq!(reader.u255().unwrap(), 253);
+        assert_eq!(reader.u255().unwrap(), 506);
+        assert_eq!(reader.u255().unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn test_decode_triplet() {
+        let decode = |flag, data: &[u8]| decode_triplet(flag, &mut Reader::new(data, 0)).unwrap();
+
+        assert_eq!(decode(0, &[10]), (0, -10));
+        assert_eq!(decode(3, &[10]), (0, 266));
+        assert_eq!(decode(11, &[10]), (10, 0));
+        // Four bits each, with both signs positive
+        assert_eq!(decode(23, &[0x21]), (3, 2));
+        assert_eq!(decode(84, &[1, 2]), (-2, -3));
+        assert_eq!(decode(123, &[0x12, 0x34, 0x56]), (0x123, 0x456));
+        assert_eq!(decode(124, &[0x01, 0x00, 0x00, 0x10]), (-256, -16));
+    }
+
+    #[test]
+    fn test_decode_woff2() {
+        let woff2 = include_bytes!("../../tests/fonts/OpenSans-Regular.woff2");
+        let sfnt = decode(woff2).unwrap();
+        let font = Font::new(&sfnt).unwrap();
+
+        assert_eq!(font.ps_name, "OpenSans-Regular");
+        assert_eq!(font.face.number_of_glyphs(), 902);
+        let glyph_id = font.face.glyph_index('a').unwrap();
+        assert_eq!(
+            font.face.glyph_bounding_box(glyph_id),
+            Some(Rect {
+                x_min: 94,
+                y_min: -20,
+                x_max: 973,
+                y_max: 1114
+            })
+        );
+        // The reconstructed left side bearings match the reconstructed outlines.
+        for id in 0..font.face.number_of_glyphs() {
+            let id = GlyphId(id);
+            let mut path = Path::default();
+            if let Some(bbox) = font.face.outline_glyph(id, &mut path) {
+                assert!(!path.0.is_empty());
+                assert_eq!(font.face.glyph_hor_side_bearing(id), Some(bbox.x_min));
+            }
+        }
+        assert!(decode(&woff2[..100]).is_err());
+    }
+
+    fn glyf_stream(n_contours: i16, n_points: &[u8]) -> Vec<u8> {
+        let n_contours = n_contours.to_be_bytes();
+        let streams: [&[u8]; 7] = [&n_contours, n_points, &[], &[], &[], &[0; 4], &[]];
+        let mut data = vec![0, 0, 0, 0, 0, 1, 0, 0];
+        for stream in streams {
+            data.push_u32(stream.len() as u32);
+        }
+        for stream in streams {
+            data.extend_from_slice(stream);
+        }
+        data
+    }
+
+    #[test]
+    fn test_malformed_contours() {
+        // A contour without points
+        assert_eq!(
+            reconstruct_glyf(&glyf_stream(1, &[0]), &mut Tables::new()),
+            None
+        );
+        // More points than a glyph can number
+        let data = glyf_stream(2, &[253, 0xff, 0xff, 253, 0xff, 0xff]);
+        assert_eq!(reconstruct_glyf(&data, &mut Tables::new()), None);
+    }
+}
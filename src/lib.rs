@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use crate::{
     char_map::CharMap,
     document::Document,
-    font::Font,
+    font::{Font, FontDescriptor},
     pdf::{page::PageBuilder, PDFBuilder},
     text_layout::layout_document,
 };
@@ -15,28 +15,89 @@ pub mod font;
 pub mod pdf;
 pub mod text_layout;
 
-pub fn generate_pdf(document: Document) -> String {
+/// Lays out a document and writes it as a PDF.
+///
+/// Returns an error if a font can't be embedded, such as one whose license doesn't allow it.
+pub fn generate_pdf(document: Document) -> Result<String, font::Error> {
     let pages = layout_document(&document).unwrap();
 
-    let char_map = CharMap::from_document(&document);
+    let mut char_map = CharMap::from_document(&document);
+    char_map.extend(&pages);
     let new_font_buffers = document
         .fonts
         .iter()
-        .map(|(&name, font)| (name, font.subset(&char_map)))
-        .collect::<BTreeMap<&str, Vec<u8>>>();
+        .map(|(&name, font)| Ok((name, font.subset(name, &char_map)?)))
+        .collect::<Result<BTreeMap<&str, Vec<u8>>, font::Error>>()?;
     let new_fonts = new_font_buffers
         .iter()
-        .map(|(&name, buffer)| (name, Font::new(buffer).unwrap()))
-        .collect::<BTreeMap<&str, Font>>();
+        .map(|(&name, buffer)| {
+            let descriptor = document.fonts[name].descriptor();
+            Ok((name, (Font::new(buffer)?, descriptor)))
+        })
+        .collect::<Result<BTreeMap<&str, (Font, FontDescriptor)>, font::Error>>()?;
 
     let mut pdf_builder = PDFBuilder::new();
     for page in pages {
         let mut builder = PageBuilder::new();
         builder.text(&page.lines, &char_map).unwrap();
+        builder.rules(&page.rules).unwrap();
         let page = builder.build();
         pdf_builder.page(&page).unwrap();
     }
     pdf_builder.catalog(&new_fonts, &char_map).unwrap();
     let content = pdf_builder.build().unwrap();
-    content
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use glam::vec2;
+
+    use crate::{
+        document::{Block, Document, Inline, Style, TextAlign, TextBlock, TextInline},
+        font::{Error, Font},
+        generate_pdf,
+    };
+
+    #[test]
+    fn test_restricted_font() {
+        // Sets the fsType in OS/2 to restricted license embedding.
+        let mut data = Font::default().data.to_owned();
+        let os2 = Font::default()
+            .face
+            .raw_face()
+            .table_records
+            .into_iter()
+            .find(|record| &record.tag.to_bytes() == b"OS/2")
+            .unwrap();
+        let fs_type = os2.offset as usize + 8;
+        data[fs_type..fs_type + 2].copy_from_slice(&2u16.to_be_bytes());
+        let font = Font::new(&data).unwrap();
+
+        let style = Style {
+            font: &font.ps_name,
+            font_size: 12.0,
+            ..Default::default()
+        };
+        let document = Document {
+            blocks: vec![Block::Text(TextBlock {
+                inlines: vec![Inline::Text(TextInline {
+                    style,
+                    text: "Restricted",
+                })],
+                align: TextAlign::Left,
+            })],
+            fonts: BTreeMap::from([(&*font.ps_name, &font)]),
+            page_size: vec2(595.0, 842.0),
+            margin: 72.0,
+            columns: Default::default(),
+        };
+
+        assert!(matches!(
+            generate_pdf(document),
+            Err(Error::EmbeddingNotAllowed)
+        ));
+    }
 }
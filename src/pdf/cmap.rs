@@ -1,11 +1,25 @@
 use std::fmt::{self, Write};
 
-use crate::char_map::CharMap;
+use crate::char_map::{CharMap, Mapping};
 
 use super::{PDFBuilder, Ref};
 
 pub struct MappedStr<'a>(pub &'a str, pub &'a CharMap);
 
+/// Writes a code in hex, as one byte or two depending on how many codes the char map has.
+pub struct Code<'a>(pub u16, pub &'a CharMap);
+
+impl fmt::Display for Code<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(code, char_map) = self;
+        if char_map.is_wide() {
+            write!(f, "{:04x}", code)
+        } else {
+            write!(f, "{:02x}", code)
+        }
+    }
+}
+
 impl fmt::Display for MappedStr<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "<")?;
@@ -16,7 +30,7 @@ impl fmt::Display for MappedStr<'_> {
                 eprintln!("Character not found in char map: {c:?}");
                 fmt::Error
             })?;
-            write!(f, "{:02x}", code)?;
+            write!(f, "{}", Code(code, char_map))?;
         }
 
         write!(f, ">")?;
@@ -33,6 +47,52 @@ impl PDFBuilder {
     }
 }
 
+impl PDFBuilder {
+    /// Writes an encoding that maps each one-byte code to the CID with the same number.
+    pub(super) fn identity_cmap(&mut self) -> Result<Ref, fmt::Error> {
+        let mut cmap = String::new();
+        write_identity_cmap(&mut cmap)?;
+
+        let ref_ = self.start_object()?;
+        write!(
+            self.content,
+            "<< /Type /CMap /CMapName /{IDENTITY_CMAP_NAME} {CID_SYSTEM_INFO} ",
+        )?;
+        writeln!(self.content, "/Length {} >>", cmap.len())?;
+        writeln!(self.content, "stream")?;
+        self.content.push_str(&cmap);
+        write!(self.content, "\nendstream\n")?;
+        self.end_object()?;
+
+        Ok(ref_)
+    }
+}
+
+const IDENTITY_CMAP_NAME: &str = "Identity-OneByte";
+pub(super) const CID_SYSTEM_INFO: &str =
+    "/CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >>";
+
+fn write_identity_cmap(s: &mut String) -> Result<(), fmt::Error> {
+    writeln!(s, "/CIDInit /ProcSet findresource begin")?;
+    writeln!(s, "12 dict begin")?;
+    writeln!(s, "begincmap")?;
+    writeln!(s, "{CID_SYSTEM_INFO} def")?;
+    writeln!(s, "/CMapName /{IDENTITY_CMAP_NAME} def")?;
+    writeln!(s, "/CMapType 1 def")?;
+    writeln!(s, "1 begincodespacerange")?;
+    writeln!(s, "<00> <FF>")?;
+    writeln!(s, "endcodespacerange")?;
+    writeln!(s, "1 begincidrange")?;
+    writeln!(s, "<00> <FF> 0")?;
+    writeln!(s, "endcidrange")?;
+    writeln!(s, "endcmap")?;
+    writeln!(s, "CMapName currentdict /CMap defineresource pop")?;
+    writeln!(s, "end")?;
+    writeln!(s, "end")?;
+
+    Ok(())
+}
+
 fn write_cmap(s: &mut String, char_map: &CharMap) -> Result<(), fmt::Error> {
     // Copied from LibreOffice output
     writeln!(s, "/CIDInit /ProcSet findresource begin")?;
@@ -46,14 +106,33 @@ fn write_cmap(s: &mut String, char_map: &CharMap) -> Result<(), fmt::Error> {
     writeln!(s, "/CMapName /Adobe-Identity-UCS def")?;
     writeln!(s, "/CMapType 2 def")?;
     writeln!(s, "1 begincodespacerange")?;
-    writeln!(s, "<00> <FF>")?;
+    if char_map.is_wide() {
+        writeln!(s, "<0000> <FFFF>")?;
+    } else {
+        writeln!(s, "<00> <FF>")?;
+    }
     writeln!(s, "endcodespacerange")?;
 
-    writeln!(s, "{} beginbfchar", char_map.mappings.len())?;
-    for (i, &c) in char_map.mappings.iter().enumerate() {
-        writeln!(s, "<{:02x}> <{:04x}>", i, c as u32)?;
+    // Glyphs that continue a cluster don't stand for any text of their own.
+    let mappings = char_map
+        .mappings
+        .iter()
+        .map(Mapping::text)
+        .enumerate()
+        .filter(|(_, text)| !text.is_empty())
+        .collect::<Vec<_>>();
+    // Each block can only have 100 mappings.
+    for block in mappings.chunks(100) {
+        writeln!(s, "{} beginbfchar", block.len())?;
+        for (i, text) in block {
+            write!(s, "<{}> <", Code(*i as u16, char_map))?;
+            for unit in text.encode_utf16() {
+                write!(s, "{:04x}", unit)?;
+            }
+            writeln!(s, ">")?;
+        }
+        writeln!(s, "endbfchar")?;
     }
-    writeln!(s, "endbfchar")?;
 
     writeln!(s, "endcmap")?;
     writeln!(s, "CMapName currentdict /CMap defineresource pop")?;
@@ -3,9 +3,17 @@ use std::{
     fmt::{self, Write},
 };
 
-use crate::{char_map::CharMap, font::Font};
+use ttf_parser::{GlyphId, Tag};
 
-use self::page::{PAGE_HEIGHT, PAGE_WIDTH};
+use crate::{
+    char_map::CharMap,
+    font::{Font, FontDescriptor},
+};
+
+use self::{
+    cmap::CID_SYSTEM_INFO,
+    page::{PAGE_HEIGHT, PAGE_WIDTH},
+};
 
 mod cmap;
 pub mod page;
@@ -98,16 +106,46 @@ impl PDFBuilder {
         Ok(ref_)
     }
 
-    fn font(&mut self, font: &Font, char_map: &CharMap, cmap_ref: Ref) -> Result<Ref, fmt::Error> {
-        let len = font.data.len();
-        let encoded = ascii85_encode(font.data);
+    fn font(
+        &mut self,
+        font: &Font,
+        descriptor: &FontDescriptor,
+        char_map: &CharMap,
+        cmap_ref: Ref,
+    ) -> Result<Ref, fmt::Error> {
+        // Fonts with CFF outlines can only be used as CID fonts. CID-keyed CFF tables, which CFF2
+        // outlines are written as, are embedded bare, and other CFF fonts are embedded whole as
+        // OpenType.
+        let tables = font.face.tables();
+        let is_cff = tables.cff.is_some();
+        let cid_keyed_cff = tables
+            .cff
+            .as_ref()
+            .filter(|cff| cff.glyph_cid(GlyphId(0)).is_some())
+            .and_then(|_| font.face.raw_face().table(Tag::from_bytes(b"CFF ")));
+
+        let data = cid_keyed_cff.unwrap_or(font.data);
+        let len = data.len();
+        let encoded = ascii85_encode(data);
         let encoded_len = encoded.len();
 
-        let font_file2 = self.start_object()?;
-        writeln!(
-            self.content,
-            "<< /Length {encoded_len} /Length1 {len} /Filter /ASCII85Decode >>",
-        )?;
+        let font_file = self.start_object()?;
+        if cid_keyed_cff.is_some() {
+            writeln!(
+                self.content,
+                "<< /Length {encoded_len} /Subtype /CIDFontType0C /Filter /ASCII85Decode >>",
+            )?;
+        } else if is_cff {
+            writeln!(
+                self.content,
+                "<< /Length {encoded_len} /Subtype /OpenType /Filter /ASCII85Decode >>",
+            )?;
+        } else {
+            writeln!(
+                self.content,
+                "<< /Length {encoded_len} /Length1 {len} /Filter /ASCII85Decode >>",
+            )?;
+        }
         writeln!(self.content, "stream")?;
         self.content.push_str(&encoded);
         write!(self.content, "\nendstream\n")?;
@@ -116,8 +154,9 @@ impl PDFBuilder {
         let font_descriptor = self.start_object()?;
         write!(
             self.content,
-            "<< /Type /FontDescriptor /FontName /{ps_name} /Flags 6 ",
+            "<< /Type /FontDescriptor /FontName /{ps_name} /Flags {flags} ",
             ps_name = font.ps_name,
+            flags = descriptor.flags,
         )?;
         let bbox = font.face.global_bounding_box();
         write!(
@@ -137,22 +176,34 @@ impl PDFBuilder {
         )?;
         write!(
             self.content,
-            "/Leading {leading} /CapHeight {cap_height} /StemV {stem_v} /FontFile2 {font_file2} >>",
-            cap_height = font.to_milli_em(font.face.ascender()),
+            "/Leading {leading} /CapHeight {cap_height} /XHeight {x_height} /StemV {stem_v} ",
             leading = font
                 .to_milli_em(font.face.line_gap() + font.face.ascender() - font.face.descender()),
-            stem_v = 100,
-            font_file2 = font_file2,
+            cap_height = descriptor.cap_height,
+            x_height = descriptor.x_height,
+            stem_v = descriptor.stem_v,
+        )?;
+        write!(
+            self.content,
+            "/{font_file_key} {font_file} >>",
+            font_file_key = if is_cff { "FontFile3" } else { "FontFile2" },
+            font_file = font_file,
         )?;
         self.end_object()?;
 
+        // Simple fonts only have one-byte codes, so TrueType fonts are CID fonts too once there are
+        // more codes than that.
+        if is_cff || char_map.is_wide() {
+            return self.cid_font(font, is_cff, char_map, cmap_ref, font_descriptor);
+        }
+
         let widths_ref = self.start_object()?;
         write!(self.content, "[ ")?;
         for (i, _c) in char_map.mappings.iter().enumerate() {
             // The new font uses the mapping index as the CID
             let glyph_id = font.face.glyph_index(i as u8 as char).unwrap_or_default();
             let width = font.face.glyph_hor_advance(glyph_id).unwrap_or_default();
-            let width = font.to_milli_em(width as i16);
+            let width = font.to_milli_em(width);
             write!(self.content, "{width} ")?;
         }
         write!(self.content, "]")?;
@@ -175,6 +226,64 @@ impl PDFBuilder {
         Ok(font_ref)
     }
 
+    /// Writes a Type 0 font whose codes are the CIDs, and so the glyph IDs, of its descendant
+    /// font. The codes take one byte or two, like the codes of the char map.
+    fn cid_font(
+        &mut self,
+        font: &Font,
+        is_cff: bool,
+        char_map: &CharMap,
+        cmap_ref: Ref,
+        font_descriptor: Ref,
+    ) -> Result<Ref, fmt::Error> {
+        let encoding = if char_map.is_wide() {
+            "/Identity-H".to_owned()
+        } else {
+            self.identity_cmap()?.to_string()
+        };
+
+        let cid_font = self.start_object()?;
+        if is_cff {
+            write!(
+                self.content,
+                "<< /Type /Font /Subtype /CIDFontType0 /BaseFont /{ps_name} ",
+                ps_name = font.ps_name,
+            )?;
+        } else {
+            write!(
+                self.content,
+                "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{ps_name} /CIDToGIDMap /Identity ",
+                ps_name = font.ps_name,
+            )?;
+        }
+        write!(self.content, "{CID_SYSTEM_INFO} ",)?;
+        write!(self.content, "/FontDescriptor {font_descriptor} /W [ 0 [ ")?;
+        for i in 0..char_map.mappings.len() {
+            let width = font
+                .face
+                .glyph_hor_advance(GlyphId(i as u16))
+                .unwrap_or_default();
+            let width = font.to_milli_em(width);
+            write!(self.content, "{width} ")?;
+        }
+        write!(self.content, "] ] >>")?;
+        self.end_object()?;
+
+        let font_ref = self.start_object()?;
+        write!(
+            self.content,
+            "<< /Type /Font /Subtype /Type0 /BaseFont /{ps_name} /Encoding {encoding} ",
+            ps_name = font.ps_name,
+        )?;
+        write!(
+            self.content,
+            "/DescendantFonts [ {cid_font} ] /ToUnicode {cmap_ref} >>",
+        )?;
+        self.end_object()?;
+
+        Ok(font_ref)
+    }
+
     pub fn page(&mut self, content: &str) -> Result<(), fmt::Error> {
         let contents = self.stream_object(content)?;
 
@@ -191,16 +300,19 @@ impl PDFBuilder {
         Ok(())
     }
 
+    /// Writes the fonts and the page tree. Each font is the subset that gets embedded, along with
+    /// the descriptor of the font it was made from, since subsets leave out the tables that
+    /// descriptors are read from.
     pub fn catalog(
         &mut self,
-        fonts: &BTreeMap<&str, Font>,
+        fonts: &BTreeMap<&str, (Font, FontDescriptor)>,
         char_map: &CharMap,
     ) -> Result<(), fmt::Error> {
         let cmap_ref = self.cmap(char_map)?;
         let font_refs = fonts
             .iter()
-            .map(|(ps_name, font)| {
-                let ref_ = self.font(font, char_map, cmap_ref)?;
+            .map(|(ps_name, (font, descriptor))| {
+                let ref_ = self.font(font, descriptor, char_map, cmap_ref)?;
                 Ok((ps_name, ref_))
             })
             .collect::<Result<Vec<_>, _>>()?;
@@ -295,3 +407,167 @@ fn ascii85_encode(data: &[u8]) -> String {
 
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use ttf_parser::{cff, Face, GlyphId, Tag};
+
+    use crate::{
+        char_map::{CharMap, Mapping},
+        font::{with_cff_outlines, Font, Path},
+    };
+
+    use super::{
+        ascii85_encode,
+        cmap::{MappedStr, CID_SYSTEM_INFO},
+        PDFBuilder,
+    };
+
+    #[test]
+    fn test_cid_font() {
+        let data = with_cff_outlines(&Font::default(), false);
+        let font = Font::new(&data).unwrap();
+        let char_map = CharMap {
+            mappings: vec![Mapping::Char('\0'), Mapping::Char('a')],
+        };
+        let subset = font.subset(&font.ps_name, &char_map).unwrap();
+        let subset = Font::new(&subset).unwrap();
+        assert!(subset.face.tables().cff.is_some());
+
+        let mut pdf = PDFBuilder::new();
+        let cmap_ref = pdf.cmap(&char_map).unwrap();
+        pdf.font(&subset, &font.descriptor(), &char_map, cmap_ref)
+            .unwrap();
+        let objects = pdf.content.split("endobj\n").collect::<Vec<_>>();
+
+        // The whole subset is embedded as an OpenType font file.
+        let (dict, stream) = objects[1].split_once("\nstream\n").unwrap();
+        let encoded = stream.strip_suffix("\nendstream\n").unwrap();
+        assert_eq!(
+            dict,
+            format!(
+                "3 0 obj\n<< /Length {} /Subtype /OpenType /Filter /ASCII85Decode >>",
+                encoded.len()
+            )
+        );
+        assert_eq!(encoded, ascii85_encode(subset.data));
+        assert!(objects[2].ends_with("/FontFile3 3 0 R >>"));
+
+        let width = |glyph_id| {
+            subset.to_milli_em(subset.face.glyph_hor_advance(GlyphId(glyph_id)).unwrap())
+        };
+        assert_eq!(
+            objects[4],
+            format!(
+                "6 0 obj\n<< /Type /Font /Subtype /CIDFontType0 /BaseFont /{} {CID_SYSTEM_INFO} \
+                /FontDescriptor 4 0 R /W [ 0 [ {} {} ] ] >>",
+                font.ps_name,
+                width(0),
+                width(1),
+            )
+        );
+        assert_eq!(
+            objects[5],
+            format!(
+                "7 0 obj\n<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding 5 0 R \
+                /DescendantFonts [ 6 0 R ] /ToUnicode 2 0 R >>",
+                font.ps_name,
+            )
+        );
+    }
+
+    #[test]
+    fn test_cff2_font() {
+        let data = with_cff_outlines(&Font::default(), true);
+        let font = Font::new(&data).unwrap();
+        let char_map = CharMap {
+            mappings: vec![Mapping::Char('\0'), Mapping::Char('a')],
+        };
+        let subset = font.subset(&font.ps_name, &char_map).unwrap();
+        let subset = Font::new(&subset).unwrap();
+        assert!(subset.face.tables().cff2.is_none());
+
+        let mut pdf = PDFBuilder::new();
+        let cmap_ref = pdf.cmap(&char_map).unwrap();
+        pdf.font(&subset, &font.descriptor(), &char_map, cmap_ref)
+            .unwrap();
+        let objects = pdf.content.split("endobj\n").collect::<Vec<_>>();
+
+        // The outlines are embedded as a bare CID-keyed CFF table.
+        let cff_data = subset
+            .face
+            .raw_face()
+            .table(Tag::from_bytes(b"CFF "))
+            .unwrap();
+        let (dict, stream) = objects[1].split_once("\nstream\n").unwrap();
+        let encoded = stream.strip_suffix("\nendstream\n").unwrap();
+        assert_eq!(
+            dict,
+            format!(
+                "3 0 obj\n<< /Length {} /Subtype /CIDFontType0C /Filter /ASCII85Decode >>",
+                encoded.len()
+            )
+        );
+        assert_eq!(encoded, ascii85_encode(cff_data));
+        assert!(objects[4].contains("/Subtype /CIDFontType0 "));
+
+        let cff = cff::Table::parse(cff_data).unwrap();
+        assert_eq!(cff.number_of_glyphs(), 2);
+        assert_eq!(cff.glyph_cid(GlyphId(1)), Some(1));
+        let outline = |face: &Face, glyph_id| {
+            let mut path = Path::default();
+            face.outline_glyph(glyph_id, &mut path);
+            path.0
+        };
+        let mut path = Path::default();
+        cff.outline(GlyphId(1), &mut path).unwrap();
+        // The last contour is closed by the endchar that CFF2 charstrings don't have.
+        assert_eq!(
+            path.0.strip_suffix("Z "),
+            Some(outline(&font.face, font.face.glyph_index('a').unwrap()).as_str())
+        );
+    }
+
+    #[test]
+    fn test_wide_codes() {
+        let font = Font::default();
+        let mut char_map = CharMap {
+            mappings: vec![Mapping::Char('\0')],
+        };
+        char_map.extend(('\u{4e00}'..).take(300));
+        assert!(char_map.is_wide());
+        assert_eq!(
+            MappedStr("\u{4e00}\u{4f2b}", &char_map).to_string(),
+            "<0001012c>"
+        );
+
+        let subset = font.subset(&font.ps_name, &char_map).unwrap();
+        let subset = Font::new(&subset).unwrap();
+        let mut pdf = PDFBuilder::new();
+        let cmap_ref = pdf.cmap(&char_map).unwrap();
+        pdf.font(&subset, &font.descriptor(), &char_map, cmap_ref)
+            .unwrap();
+        let objects = pdf.content.split("endobj\n").collect::<Vec<_>>();
+
+        // The mappings are split into blocks of 100.
+        assert!(objects[0].contains("<0000> <FFFF>"));
+        assert_eq!(objects[0].matches("beginbfchar").count(), 4);
+        assert!(objects[0].contains("<012c> <4f2b>"));
+
+        // TrueType fonts become CID fonts too.
+        assert!(objects[2].ends_with("/FontFile2 3 0 R >>"));
+        assert!(objects[3].starts_with(&format!(
+            "5 0 obj\n<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{} /CIDToGIDMap /Identity \
+            {CID_SYSTEM_INFO} /FontDescriptor 4 0 R /W [ 0 [ ",
+            font.ps_name,
+        )));
+        assert_eq!(
+            objects[4],
+            format!(
+                "6 0 obj\n<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H \
+                /DescendantFonts [ 5 0 R ] /ToUnicode 2 0 R >>",
+                font.ps_name,
+            )
+        );
+    }
+}
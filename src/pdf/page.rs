@@ -2,9 +2,13 @@ use std::fmt::{self, Write};
 
 use glam::Vec2;
 
-use crate::{char_map::CharMap, document::Style, text_layout::Line};
+use crate::{
+    char_map::CharMap,
+    document::Style,
+    text_layout::{Chunk, Line, Rule},
+};
 
-use super::cmap::MappedStr;
+use super::cmap::{Code, MappedStr};
 
 // A4 page size
 pub const PAGE_WIDTH: f32 = 8.27 * 72.0;
@@ -35,37 +39,98 @@ impl PageBuilder {
         writeln!(self.content, "ET")
     }
 
-    fn style(&mut self, style: &Style) -> Result<(), fmt::Error> {
-        writeln!(self.content, "/{} {} Tf", style.font, style.font_size)
+    fn style(&mut self, previous: &Style, style: &Style) -> Result<(), fmt::Error> {
+        writeln!(self.content, "/{} {} Tf", style.font, style.font_size)?;
+
+        // Synthetic bold fills and strokes the glyphs, and spaces them out by the stroke width so
+        // that the strokes don't overlap.
+        if style.synthetic_bold != previous.synthetic_bold {
+            let mode = if style.synthetic_bold { 2 } else { 0 };
+            writeln!(self.content, "{} Tr", mode)?;
+        }
+        let stroke_width = style.bold_stroke_width();
+        if stroke_width != previous.bold_stroke_width() {
+            if style.synthetic_bold {
+                writeln!(self.content, "{} w", stroke_width)?;
+            }
+            writeln!(self.content, "{} Tc", stroke_width)?;
+        }
+        Ok(())
+    }
+
+    fn rise(&mut self, rise: f32) -> Result<(), fmt::Error> {
+        writeln!(self.content, "{} Ts", rise)
     }
 
     fn text_line_delta(&mut self, delta: Vec2) -> Result<(), fmt::Error> {
         writeln!(self.content, "{} {} Td", delta.x, delta.y)
     }
 
+    /// Moves to an absolute position, slanting the text that follows by `skew`.
+    fn text_matrix(&mut self, skew: f32, position: Vec2) -> Result<(), fmt::Error> {
+        writeln!(
+            self.content,
+            "1 0 {} 1 {} {} Tm",
+            skew, position.x, position.y
+        )
+    }
+
     pub fn text(&mut self, lines: &[Line], char_map: &CharMap) -> Result<(), fmt::Error> {
         self.begin_text()?;
 
         let mut current_style = Style::default();
+        let mut current_rise = 0.0;
+        // Slanted text needs a skewed text matrix, which has to be placed absolutely, so the start
+        // of each line is tracked along with whether the matrix has moved away from it.
+        let mut line_start = Vec2::ZERO;
+        let mut current_skew = 0.0;
+        let mut displaced = false;
 
         for line in lines {
-            self.text_line_delta(line.delta)?;
+            line_start += line.delta;
+            let skew = line
+                .chunks
+                .first()
+                .map_or(current_skew, |chunk| chunk.style.skew());
+            if skew != 0.0 || current_skew != 0.0 || displaced {
+                self.text_matrix(skew, line_start)?;
+                current_skew = skew;
+                displaced = false;
+            } else {
+                self.text_line_delta(line.delta)?;
+            }
 
             let Some(first_chunk) = line.chunks.first() else {
                 continue;
             };
 
             if first_chunk.style != current_style {
+                self.style(&current_style, &first_chunk.style)?;
                 current_style = first_chunk.style;
-                self.style(&current_style)?;
+            }
+            if first_chunk.rise != current_rise {
+                current_rise = first_chunk.rise;
+                self.rise(current_rise)?;
             }
 
             write!(self.content, "[")?;
+            let mut x = 0.0;
             for chunk in &line.chunks {
-                if chunk.style != current_style {
+                if chunk.style != current_style || chunk.rise != current_rise {
                     writeln!(self.content, "] TJ")?;
-                    current_style = chunk.style;
-                    self.style(&current_style)?;
+                    if chunk.style.skew() != current_skew {
+                        current_skew = chunk.style.skew();
+                        self.text_matrix(current_skew, line_start + Vec2::new(x, 0.0))?;
+                        displaced = true;
+                    }
+                    if chunk.style != current_style {
+                        self.style(&current_style, &chunk.style)?;
+                        current_style = chunk.style;
+                    }
+                    if chunk.rise != current_rise {
+                        current_rise = chunk.rise;
+                        self.rise(current_rise)?;
+                    }
                     write!(self.content, "[")?;
                 }
 
@@ -77,7 +142,12 @@ impl PageBuilder {
                     )?;
                 }
 
-                write!(self.content, "{}", MappedStr(&chunk.text, char_map))?;
+                if chunk.glyphs.is_empty() {
+                    write!(self.content, "{}", MappedStr(&chunk.text, char_map))?;
+                } else {
+                    self.glyphs(chunk, current_rise, char_map)?;
+                }
+                x += chunk.width - chunk.left_adjust;
             }
             writeln!(self.content, "] TJ")?;
         }
@@ -86,7 +156,148 @@ impl PageBuilder {
         Ok(())
     }
 
+    /// Writes the shaped glyphs of a chunk inside a `TJ` array, moving each one to where the shaper
+    /// placed it.
+    fn glyphs(&mut self, chunk: &Chunk, rise: f32, char_map: &CharMap) -> Result<(), fmt::Error> {
+        let mut codes = String::new();
+        for glyph in &chunk.glyphs {
+            let code = char_map.get_glyph(chunk.style.font, glyph).ok_or_else(|| {
+                eprintln!("Glyph not found in char map: {glyph:?}");
+                fmt::Error
+            })?;
+
+            // The viewer moves by the glyph's own advance, so anything else is an adjustment.
+            let before = -glyph.x_offset;
+            let after = glyph.x_offset + glyph.glyph_advance - glyph.advance;
+            if before == 0 && glyph.y_offset == 0 {
+                write!(codes, "{}", Code(code, char_map))?;
+            } else {
+                self.codes(&mut codes)?;
+                if glyph.y_offset != 0 {
+                    writeln!(self.content, "] TJ")?;
+                    self.rise(rise + glyph.y_offset as f32 * chunk.style.font_size / 1000.0)?;
+                    write!(self.content, "[")?;
+                }
+                if before != 0 {
+                    write!(self.content, "{before}")?;
+                }
+                write!(self.content, "<{}>", Code(code, char_map))?;
+            }
+
+            if after != 0 || glyph.y_offset != 0 {
+                self.codes(&mut codes)?;
+                if glyph.y_offset != 0 {
+                    writeln!(self.content, "] TJ")?;
+                    self.rise(rise)?;
+                    write!(self.content, "[")?;
+                }
+                if after != 0 {
+                    write!(self.content, "{after}")?;
+                }
+            }
+        }
+        self.codes(&mut codes)
+    }
+
+    /// Writes the codes collected so far as one string.
+    fn codes(&mut self, codes: &mut String) -> Result<(), fmt::Error> {
+        if !codes.is_empty() {
+            write!(self.content, "<{codes}>")?;
+            codes.clear();
+        }
+        Ok(())
+    }
+
+    pub fn rules(&mut self, rules: &[Rule]) -> Result<(), fmt::Error> {
+        for rule in rules {
+            writeln!(self.content, "{} w", rule.width)?;
+            writeln!(self.content, "{} {} m", rule.start.x, rule.start.y)?;
+            writeln!(self.content, "{} {} l", rule.end.x, rule.end.y)?;
+            writeln!(self.content, "S")?;
+        }
+
+        Ok(())
+    }
+
     pub fn build(self) -> String {
         self.content
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use crate::{
+        char_map::{CharMap, Mapping},
+        document::Style,
+        font::TextMetrics,
+        text_layout::{Chunk, Line},
+    };
+
+    use super::PageBuilder;
+
+    fn line<'a>(delta: Vec2, chunks: &[(&'a str, Style<'a>)]) -> Line<'a> {
+        Line {
+            chunks: chunks
+                .iter()
+                .map(|&(text, style)| Chunk {
+                    text: text.into(),
+                    style,
+                    width: 5.0,
+                    ..Default::default()
+                })
+                .collect(),
+            text_metrics: TextMetrics::default(),
+            text_total_width: 5.0 * chunks.len() as f32,
+            delta,
+        }
+    }
+
+    #[test]
+    fn test_synthetic_styles() {
+        let char_map = CharMap {
+            mappings: vec![Mapping::Char('\0'), Mapping::Char('a'), Mapping::Char('b')],
+        };
+        let regular = Style {
+            font: "F0",
+            font_size: 10.0,
+            ..Default::default()
+        };
+        let bold = Style {
+            synthetic_bold: true,
+            ..regular
+        };
+        let oblique = Style {
+            synthetic_oblique: true,
+            ..regular
+        };
+        let lines = [
+            line(
+                Vec2::new(72.0, 700.0),
+                &[("a", regular), ("b", bold), ("a", oblique)],
+            ),
+            line(Vec2::new(0.0, -12.0), &[("b", oblique)]),
+            line(Vec2::new(0.0, -12.0), &[("a", regular)]),
+            line(Vec2::new(0.0, -12.0), &[("b", regular)]),
+        ];
+
+        let mut page = PageBuilder::new();
+        page.text(&lines, &char_map).unwrap();
+        let stroke = bold.bold_stroke_width();
+        let skew = oblique.skew();
+        assert_eq!(
+            page.build(),
+            format!(
+                "BT\n\
+                72 700 Td\n/F0 10 Tf\n[<01>] TJ\n\
+                /F0 10 Tf\n2 Tr\n{stroke} w\n{stroke} Tc\n[<02>] TJ\n\
+                1 0 {skew} 1 82 700 Tm\n/F0 10 Tf\n0 Tr\n0 Tc\n[<01>] TJ\n\
+                1 0 {skew} 1 72 688 Tm\n[<02>] TJ\n\
+                1 0 0 1 72 676 Tm\n/F0 10 Tf\n[<01>] TJ\n\
+                0 -12 Td\n[<02>] TJ\n\
+                ET\n"
+            )
+        );
+    }
+}
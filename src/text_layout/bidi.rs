@@ -0,0 +1,235 @@
+use std::{borrow::Cow, collections::BTreeMap, ops::Range};
+
+use unicode_bidi::{bidi_class, BidiClass, Level, ParagraphBidiInfo};
+use unicode_bidi_mirroring::get_mirrored;
+
+use crate::font::Font;
+
+use super::{chunk_text, substring, Chunk, Error, Line};
+
+/// Resolves the embedding level of each chunk in a paragraph, following the Unicode
+/// Bidirectional Algorithm (UAX #9).
+///
+/// Chunks are split where the level changes, and characters in right-to-left runs are replaced
+/// with their mirrored forms, such as ")" for "(". Returns the level of the paragraph, which is
+/// taken from its first strong character.
+pub(super) fn resolve_levels<'a>(
+    fonts: &BTreeMap<&str, &'a Font>,
+    chunks: &mut Vec<Chunk<'a>>,
+) -> Result<Level, Error> {
+    let text = chunks.iter().map(|chunk| &*chunk.text).collect::<String>();
+    let info = ParagraphBidiInfo::new(&text, None);
+    if info.is_pure_ltr {
+        return Ok(info.paragraph_level);
+    }
+
+    let mut resolved = Vec::with_capacity(chunks.len());
+    let mut start = 0;
+    for chunk in chunks.drain(..) {
+        let levels = &info.levels[start..start + chunk.text.len()];
+        start += chunk.text.len();
+
+        let runs = level_runs(levels);
+        if let [(level, _)] = runs[..] {
+            if level.is_ltr() || !chunk.text.chars().any(|c| get_mirrored(c).is_some()) {
+                resolved.push(Chunk {
+                    level: level.number(),
+                    ..chunk
+                });
+                continue;
+            }
+        }
+
+        let run_count = runs.len();
+        let mut footnote = Some(chunk.footnote);
+        for (i, (level, range)) in runs.into_iter().enumerate() {
+            let mut text = substring(&chunk.text, range);
+            if level.is_rtl() && text.chars().any(|c| get_mirrored(c).is_some()) {
+                let mirrored = text.chars().map(|c| get_mirrored(c).unwrap_or(c)).collect();
+                text = Cow::Owned(mirrored);
+            }
+
+            // The pieces are measured again, since mirrored glyphs can have different widths.
+            for mut piece in chunk_text(fonts, text, chunk.style)? {
+                piece.text_metrics = chunk.text_metrics;
+                piece.rise = chunk.rise;
+                piece.level = level.number();
+                resolved.push(piece);
+            }
+            if i + 1 == run_count {
+                if let Some(last_chunk) = resolved.last_mut() {
+                    last_chunk.footnote = footnote.take().unwrap_or_default();
+                    last_chunk.line_break = chunk.line_break;
+                }
+            }
+        }
+    }
+
+    *chunks = resolved;
+    Ok(info.paragraph_level)
+}
+
+/// Groups the bytes of a chunk into ranges with the same level.
+fn level_runs(levels: &[Level]) -> Vec<(Level, Range<usize>)> {
+    let mut runs = Vec::<(Level, Range<usize>)>::new();
+    for (i, &level) in levels.iter().enumerate() {
+        match runs.last_mut() {
+            Some((run_level, range)) if *run_level == level => range.end = i + 1,
+            _ => runs.push((level, i..i + 1)),
+        }
+    }
+    runs
+}
+
+/// Reorders the chunks of each line from logical to visual order.
+pub(super) fn reorder_lines(lines: &mut [Line], paragraph_level: Level) {
+    for line in lines {
+        // Whitespace at the end of a line takes the paragraph's direction (rule L1).
+        for chunk in line.chunks.iter_mut().rev() {
+            if !chunk.is_whitespace {
+                break;
+            }
+            chunk.level = paragraph_level.number();
+        }
+
+        let Some(max_level) = line.chunks.iter().map(|chunk| chunk.level).max() else {
+            continue;
+        };
+        let min_odd_level = line
+            .chunks
+            .iter()
+            .map(|chunk| chunk.level)
+            .filter(|level| level % 2 == 1)
+            .min();
+        let Some(min_odd_level) = min_odd_level else {
+            continue;
+        };
+
+        // Reverse every run at or above each level, from the highest down (rule L2).
+        for level in (min_odd_level..=max_level).rev() {
+            let mut i = 0;
+            while i < line.chunks.len() {
+                if line.chunks[i].level < level {
+                    i += 1;
+                    continue;
+                }
+                let run_start = i;
+                while i < line.chunks.len() && line.chunks[i].level >= level {
+                    i += 1;
+                }
+                line.chunks[run_start..i].reverse();
+            }
+        }
+
+        // Shaped glyphs are already in visual order.
+        for chunk in &mut line.chunks {
+            if chunk.level % 2 == 1 && chunk.glyphs.is_empty() {
+                chunk.text = Cow::Owned(reverse_text(&chunk.text));
+            }
+        }
+    }
+}
+
+/// Reverses the characters of right-to-left text, keeping combining marks after their base
+/// characters.
+fn reverse_text(text: &str) -> String {
+    let mut clusters = Vec::<&str>::new();
+    let mut cluster_start = 0;
+    for (i, c) in text.char_indices().skip(1) {
+        if bidi_class(c) != BidiClass::NSM {
+            clusters.push(&text[cluster_start..i]);
+            cluster_start = i;
+        }
+    }
+    clusters.push(&text[cluster_start..]);
+
+    clusters.into_iter().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+    use ttf_parser::GlyphId;
+    use unicode_bidi::{LTR_LEVEL, RTL_LEVEL};
+
+    use crate::{
+        font::{shape::Glyph, TextMetrics},
+        text_layout::{Chunk, Line},
+    };
+
+    use super::{reorder_lines, reverse_text};
+
+    fn test_line(chunks: &[(&'static str, u8)]) -> Line<'static> {
+        let chunks = chunks
+            .iter()
+            .map(|&(text, level)| Chunk {
+                text: text.into(),
+                is_whitespace: text == " ",
+                level,
+                ..Chunk::default()
+            })
+            .collect();
+        Line {
+            chunks,
+            text_metrics: TextMetrics::default(),
+            text_total_width: 0.0,
+            delta: vec2(0.0, 0.0),
+        }
+    }
+
+    fn line_text(line: &Line) -> String {
+        line.chunks.iter().map(|chunk| &*chunk.text).collect()
+    }
+
+    #[test]
+    fn test_reorder_mixed_line() {
+        let mut lines = vec![test_line(&[
+            ("one", 0),
+            (" ", 0),
+            ("אבג", 1),
+            (" ", 1),
+            ("דהו", 1),
+            (" ", 0),
+            ("two", 0),
+        ])];
+        reorder_lines(&mut lines, LTR_LEVEL);
+        assert_eq!(line_text(&lines[0]), "one והד גבא two");
+    }
+
+    #[test]
+    fn test_reorder_rtl_paragraph() {
+        // Numbers keep their order inside a right-to-left paragraph.
+        let mut lines = vec![test_line(&[
+            ("אבג", 1),
+            (" ", 1),
+            ("123", 2),
+            (" ", 1),
+            ("דהו", 1),
+            (" ", 1),
+        ])];
+        reorder_lines(&mut lines, RTL_LEVEL);
+        assert_eq!(line_text(&lines[0]), " והד 123 גבא");
+    }
+
+    #[test]
+    fn test_reorder_shaped_chunk() {
+        // The shaper has already put the glyphs in visual order, so the text is left alone.
+        let mut lines = vec![test_line(&[("بت", 1)])];
+        lines[0].chunks[0].glyphs = vec![Glyph {
+            id: GlyphId(1),
+            text: "بت".to_owned(),
+            advance: 500,
+            glyph_advance: 500,
+            x_offset: 0,
+            y_offset: 0,
+        }];
+        reorder_lines(&mut lines, RTL_LEVEL);
+        assert_eq!(line_text(&lines[0]), "بت");
+    }
+
+    #[test]
+    fn test_reverse_text() {
+        // The points stay after the letters they belong to.
+        assert_eq!(reverse_text("שָׁלוֹם"), "םוֹלשָׁ");
+    }
+}
@@ -0,0 +1,134 @@
+use std::borrow::Cow;
+
+use crate::document::{Inline, TableOfContents};
+
+use super::{
+    chunk_text, find_line_breaks, layout_lines, Chunk, Context, Error, Item, Line, PARAGRAPH_GAP,
+};
+
+/// The space on either side of a dot leader.
+const LEADER_GAP: f32 = 6.0;
+
+/// Lists the headings of the document, with dot leaders running up to their page numbers.
+pub(super) fn layout_contents<'a>(
+    context: &Context<'a, '_>,
+    target_width: f32,
+    contents: &TableOfContents<'a>,
+) -> Result<Vec<Item<'a>>, Error> {
+    let dot = chunk_text(context.fonts, Cow::Borrowed("."), contents.style)?;
+    let Some(dot) = dot.into_iter().next() else {
+        return Ok(Vec::new());
+    };
+
+    let mut lines = Vec::new();
+    for &(anchor, heading) in &context.headings {
+        let indent = heading.level.saturating_sub(1) as f32 * contents.indent;
+        let entry_width = target_width - indent;
+
+        // The page numbers are left blank until the headings have been placed once.
+        let page_number = context.page_number(anchor);
+        let page_number = chunk_text(context.fonts, Cow::Owned(page_number), contents.style)?;
+        let page_number_width = page_number.iter().map(|chunk| chunk.width).sum::<f32>();
+
+        let mut chunks = Vec::new();
+        for inline in &heading.block.inlines {
+            if let Inline::Text(inline) = inline {
+                let text = Cow::Borrowed(inline.text);
+                chunks.extend(chunk_text(context.fonts, text, contents.style)?);
+            }
+        }
+
+        // Wrapped headings leave room for at least one dot before the page number.
+        let text_width = entry_width - page_number_width - 2.0 * LEADER_GAP - dot.width;
+        find_line_breaks(&mut chunks);
+        let mut entry = layout_lines(text_width, chunks);
+        let Some(last_line) = entry.last_mut() else {
+            continue;
+        };
+        add_leader(last_line, entry_width, &dot, page_number);
+
+        for line in &mut entry {
+            if let Some(first_chunk) = line.chunks.first_mut() {
+                first_chunk.left_adjust -= indent;
+            }
+        }
+        lines.extend(entry);
+    }
+
+    if let Some(first_line) = lines.first_mut() {
+        first_line.delta.y -= PARAGRAPH_GAP;
+        first_line.text_metrics.line_gap += PARAGRAPH_GAP;
+    }
+
+    Ok(lines.into_iter().map(Item::Line).collect())
+}
+
+/// Fills the rest of the line with dots, followed by the right-aligned page number.
+fn add_leader<'a>(
+    line: &mut Line<'a>,
+    width: f32,
+    dot: &Chunk<'a>,
+    mut page_number: Vec<Chunk<'a>>,
+) {
+    let page_number_width = page_number.iter().map(|chunk| chunk.width).sum::<f32>();
+    let space = width - line.text_total_width - page_number_width;
+    let dot_count = ((space - 2.0 * LEADER_GAP) / dot.width).floor().max(0.0) as usize;
+
+    let leader = Chunk {
+        text: Cow::Owned(".".repeat(dot_count)),
+        width: dot_count as f32 * dot.width,
+        left_adjust: -LEADER_GAP,
+        ..dot.clone()
+    };
+    if let Some(first_chunk) = page_number.first_mut() {
+        first_chunk.left_adjust = -(space - leader.width - LEADER_GAP);
+    }
+
+    line.text_total_width += leader.width + page_number_width;
+    line.chunks.push(leader);
+    line.chunks.extend(page_number);
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+
+    use crate::{
+        font::TextMetrics,
+        text_layout::{Chunk, Line},
+    };
+
+    use super::{add_leader, LEADER_GAP};
+
+    #[test]
+    fn test_add_leader() {
+        let chunk = |width| Chunk {
+            width,
+            ..Chunk::default()
+        };
+        let mut line = Line {
+            chunks: vec![chunk(50.0)],
+            text_metrics: TextMetrics::default(),
+            text_total_width: 50.0,
+            delta: vec2(0.0, -10.0),
+        };
+
+        add_leader(&mut line, 200.0, &chunk(3.0), vec![chunk(10.0)]);
+
+        // 140 points are left between the text and the page number, 12 of which are gaps.
+        let leader = &line.chunks[1];
+        assert_eq!(leader.text, ".".repeat(42));
+        assert_eq!(leader.width, 126.0);
+        assert_eq!(leader.left_adjust, -LEADER_GAP);
+        assert_eq!(line.chunks[2].left_adjust, -8.0);
+
+        // The page number ends at the edge of the line.
+        let end = line.text_total_width
+            - line
+                .chunks
+                .iter()
+                .map(|chunk| chunk.left_adjust)
+                .sum::<f32>();
+        assert_eq!(end, 200.0);
+    }
+}
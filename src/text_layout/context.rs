@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+
+use crate::{
+    document::{Block, Document, Heading, Inline, LabelKind, ReferenceKind},
+    font::Font,
+};
+
+use super::Error;
+
+/// What blocks need to know about the rest of the document during a layout pass.
+pub(super) struct Context<'a, 'd> {
+    pub(super) fonts: &'d BTreeMap<&'a str, &'a Font<'a>>,
+    /// The headings of the document in order, with the anchors that mark them.
+    pub(super) headings: Vec<(usize, &'d Heading<'a>)>,
+    labels: BTreeMap<&'a str, Target>,
+    /// The page number of each anchor from the previous pass, if there was one.
+    pub(super) page_numbers: Vec<usize>,
+}
+
+/// The number and anchor of a label.
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    /// The text of a reference to the label's number, such as "Figure 4".
+    number: String,
+    anchor: usize,
+}
+
+impl<'a, 'd> Context<'a, 'd> {
+    /// Numbers the headings and labels of the document, checking that labels are unique and that
+    /// every reference has a label to refer to.
+    pub(super) fn new(document: &'d Document<'a>) -> Result<Self, Error> {
+        let mut collector = Collector::default();
+        collector.blocks(&document.blocks);
+
+        if !collector.duplicates.is_empty() {
+            let mut duplicates = collector
+                .duplicates
+                .into_iter()
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            duplicates.sort();
+            duplicates.dedup();
+            return Err(Error::DuplicateLabels(duplicates));
+        }
+
+        let mut unresolved = collector
+            .references
+            .into_iter()
+            .filter(|label| !collector.labels.contains_key(label))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        if !unresolved.is_empty() {
+            unresolved.sort();
+            unresolved.dedup();
+            return Err(Error::UnresolvedReferences(unresolved));
+        }
+
+        Ok(Self {
+            fonts: &document.fonts,
+            headings: collector.headings,
+            labels: collector.labels,
+            page_numbers: Vec::new(),
+        })
+    }
+
+    /// The page that an anchor was placed on in the previous pass, or an empty string in the
+    /// first pass.
+    pub(super) fn page_number(&self, anchor: usize) -> String {
+        self.page_numbers
+            .get(anchor)
+            .map_or_else(String::new, ToString::to_string)
+    }
+
+    /// The text that replaces a reference to a label.
+    pub(super) fn reference_text(&self, label: &str, kind: ReferenceKind) -> String {
+        let Some(target) = self.labels.get(label) else {
+            return String::new();
+        };
+        match kind {
+            ReferenceKind::Number => target.number.clone(),
+            ReferenceKind::Page => format!("page {}", self.page_number(target.anchor)),
+        }
+    }
+}
+
+/// Walks through the document in order, numbering anchors, sections and labels.
+#[derive(Default)]
+struct Collector<'a, 'd> {
+    headings: Vec<(usize, &'d Heading<'a>)>,
+    labels: BTreeMap<&'a str, Target>,
+    /// The labels that were given to more than one anchor.
+    duplicates: Vec<&'a str>,
+    references: Vec<&'a str>,
+    anchor_count: usize,
+    /// The number of the current section at each level, such as `[3, 2]` for section 3.2.
+    section: Vec<usize>,
+    figure_count: usize,
+    table_count: usize,
+    equation_count: usize,
+}
+
+impl<'a, 'd> Collector<'a, 'd> {
+    fn blocks(&mut self, blocks: &'d [Block<'a>]) {
+        for block in blocks {
+            match block {
+                Block::Text(block) => self.inlines(&block.inlines),
+                Block::Table(table) => {
+                    for cell in table.rows.iter().flatten() {
+                        self.inlines(&cell.inlines);
+                    }
+                }
+                Block::List(list) => {
+                    for blocks in &list.items {
+                        self.blocks(blocks);
+                    }
+                }
+                Block::Heading(heading) => {
+                    let level = heading.level.max(1);
+                    self.section.resize(level, 0);
+                    self.section[level - 1] += 1;
+
+                    if let Some(label) = heading.label {
+                        let number = self.section.iter().map(ToString::to_string).join(".");
+                        self.label(label, format!("Section {number}"));
+                    }
+                    self.headings.push((self.anchor_count, heading));
+                    self.anchor_count += 1;
+
+                    self.inlines(&heading.block.inlines);
+                }
+                Block::TableOfContents(_) => {}
+                Block::Label(label) => {
+                    let number = match label.kind {
+                        LabelKind::Figure => {
+                            self.figure_count += 1;
+                            format!("Figure {}", self.figure_count)
+                        }
+                        LabelKind::Table => {
+                            self.table_count += 1;
+                            format!("Table {}", self.table_count)
+                        }
+                        LabelKind::Equation => {
+                            self.equation_count += 1;
+                            format!("({})", self.equation_count)
+                        }
+                    };
+                    self.label(label.name, number);
+                    self.anchor_count += 1;
+                }
+                Block::Span(blocks) => self.blocks(blocks),
+            }
+        }
+    }
+
+    fn inlines(&mut self, inlines: &'d [Inline<'a>]) {
+        for inline in inlines {
+            match inline {
+                Inline::Text(_) => {}
+                Inline::Footnote(footnote) => self.inlines(&footnote.inlines),
+                Inline::Reference(reference) => self.references.push(reference.label),
+            }
+        }
+    }
+
+    /// Records a label for the anchor that is about to be added.
+    fn label(&mut self, name: &'a str, number: String) {
+        let target = Target {
+            number,
+            anchor: self.anchor_count,
+        };
+        if self.labels.insert(name, target).is_some() {
+            self.duplicates.push(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::{
+        Block, Document, Heading, Inline, Label, LabelKind, Reference, ReferenceKind, TextAlign,
+        TextBlock,
+    };
+
+    use super::Context;
+
+    fn heading(level: usize, label: Option<&str>) -> Block<'_> {
+        Block::Heading(Heading {
+            level,
+            block: TextBlock {
+                inlines: Vec::new(),
+                align: TextAlign::Left,
+            },
+            label,
+        })
+    }
+
+    fn reference(label: &str) -> Block<'_> {
+        Block::Text(TextBlock {
+            inlines: vec![Inline::Reference(Reference {
+                label,
+                kind: ReferenceKind::Number,
+                style: Default::default(),
+            })],
+            align: TextAlign::Left,
+        })
+    }
+
+    fn test_document(blocks: Vec<Block>) -> Document {
+        Document {
+            blocks,
+            fonts: Default::default(),
+            page_size: Default::default(),
+            margin: 0.0,
+            columns: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_reference_numbers() {
+        let document = test_document(vec![
+            heading(1, None),
+            heading(1, None),
+            heading(2, None),
+            heading(2, Some("intro")),
+            Block::Label(Label {
+                name: "fig",
+                kind: LabelKind::Figure,
+            }),
+            Block::Label(Label {
+                name: "eq",
+                kind: LabelKind::Equation,
+            }),
+            heading(1, Some("end")),
+        ]);
+        let mut context = Context::new(&document).unwrap();
+        context.page_numbers = vec![1, 1, 2, 3, 3, 4, 5];
+
+        let number = |label| context.reference_text(label, ReferenceKind::Number);
+        assert_eq!(number("intro"), "Section 2.2");
+        assert_eq!(number("end"), "Section 3");
+        assert_eq!(number("fig"), "Figure 1");
+        assert_eq!(number("eq"), "(1)");
+        assert_eq!(context.reference_text("eq", ReferenceKind::Page), "page 4");
+        assert_eq!(context.headings.len(), 5);
+        assert_eq!(context.headings[4].0, 6);
+    }
+
+    #[test]
+    fn test_unresolved_references() {
+        let document = test_document(vec![
+            reference("b"),
+            heading(1, Some("c")),
+            reference("a"),
+            reference("c"),
+            reference("b"),
+        ]);
+        let error = Context::new(&document).err().unwrap();
+        assert_eq!(error.to_string(), "unresolved references: a, b");
+    }
+
+    #[test]
+    fn test_duplicate_labels() {
+        let document = test_document(vec![
+            heading(1, Some("b")),
+            heading(1, Some("a")),
+            Block::Label(Label {
+                name: "b",
+                kind: LabelKind::Figure,
+            }),
+            heading(1, Some("c")),
+            heading(1, Some("a")),
+            heading(1, Some("b")),
+        ]);
+        let error = Context::new(&document).err().unwrap();
+        assert_eq!(error.to_string(), "duplicate labels: a, b");
+    }
+}
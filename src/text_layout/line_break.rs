@@ -0,0 +1,79 @@
+use unicode_linebreak::{break_property, linebreaks, BreakClass};
+
+use super::Chunk;
+
+/// Marks the chunks that a line can or must end after, following the Unicode Line Breaking
+/// Algorithm (UAX #14) across the whole paragraph.
+pub(super) fn find_line_breaks(chunks: &mut [Chunk]) {
+    let text = chunks.iter().map(|chunk| &*chunk.text).collect::<String>();
+    let mut breaks = linebreaks(&text)
+        .filter(|&(i, _)| i < text.len())
+        .peekable();
+
+    let mut end = 0;
+    for chunk in chunks {
+        end += chunk.text.len();
+        while breaks.next_if(|&(i, _)| i < end).is_some() {}
+        chunk.line_break = breaks
+            .next_if(|&(i, _)| i == end)
+            .map(|(_, opportunity)| opportunity);
+    }
+}
+
+/// Whether a character separates words, which excludes no-break spaces such as U+00A0.
+pub(super) fn is_word_space(c: char) -> bool {
+    c.is_whitespace() && break_property(c as u32) != BreakClass::NonBreakingGlue
+}
+
+#[cfg(test)]
+mod tests {
+    use unicode_linebreak::BreakOpportunity;
+
+    use crate::text_layout::Chunk;
+
+    use super::{find_line_breaks, is_word_space};
+
+    fn test_chunks(texts: &[&'static str]) -> Vec<Chunk<'static>> {
+        texts
+            .iter()
+            .map(|&text| Chunk {
+                text: text.into(),
+                ..Chunk::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_line_breaks() {
+        let mut chunks = test_chunks(&["well-", "known", " ", "日", "本", "。", "\n", "end"]);
+        find_line_breaks(&mut chunks);
+
+        let line_breaks = chunks
+            .iter()
+            .map(|chunk| chunk.line_break)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            line_breaks,
+            vec![
+                Some(BreakOpportunity::Allowed),
+                None,
+                Some(BreakOpportunity::Allowed),
+                Some(BreakOpportunity::Allowed),
+                // Lines can't start with a full stop.
+                None,
+                None,
+                Some(BreakOpportunity::Mandatory),
+                // The end of the paragraph isn't a break between chunks.
+                None,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_is_word_space() {
+        assert!(is_word_space(' '));
+        assert!(is_word_space('\t'));
+        assert!(!is_word_space('\u{a0}'));
+        assert!(!is_word_space('\u{202f}'));
+    }
+}
@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+
+use itertools::Itertools;
+
+use crate::{
+    document::{List, ListMarker},
+    font::TextMetrics,
+};
+
+use super::{chunk_text, layout_block, layout_lines, Chunk, Context, Error, Item, Line};
+
+/// The indent of each level of nesting, which the markers are placed in.
+const LIST_INDENT: f32 = 18.0;
+/// The space between the end of a marker and the start of the item's text.
+const LIST_MARKER_GAP: f32 = 6.0;
+
+pub(super) fn layout_list<'a>(
+    context: &Context<'a, '_>,
+    target_width: f32,
+    list: &List<'a>,
+) -> Result<Vec<Item<'a>>, Error> {
+    let item_width = target_width - LIST_INDENT;
+
+    let mut items = Vec::new();
+    for (i, blocks) in list.items.iter().enumerate() {
+        let mut item = blocks
+            .iter()
+            .map(|block| layout_block(context, item_width, block))
+            .flatten_ok()
+            .collect::<Result<Vec<_>, _>>()?;
+        indent_items(&mut item, LIST_INDENT);
+
+        let text = marker_text(list.marker, i + 1);
+        let marker = chunk_text(context.fonts, Cow::Owned(text), list.style)?;
+        // Anchors take up no space, so the marker goes on the line after them.
+        match item.iter_mut().find(|item| !matches!(item, Item::Anchor)) {
+            Some(Item::Line(first_line)) => add_marker(first_line, marker),
+            // Items that start with a table get a line of their own for the marker.
+            _ => {
+                let marker_start = marker_start(&marker);
+                let mut lines = layout_lines(target_width, marker);
+                for line in &mut lines {
+                    if let Some(first_chunk) = line.chunks.first_mut() {
+                        first_chunk.left_adjust = -marker_start;
+                    }
+                }
+                items.extend(lines.into_iter().map(Item::Line));
+            }
+        }
+
+        items.extend(item);
+    }
+
+    Ok(items)
+}
+
+/// Moves items to the right, so that wrapped lines line up with the start of the item.
+fn indent_items(items: &mut [Item], indent: f32) {
+    for item in items {
+        match item {
+            Item::Line(line) => {
+                if let Some(first_chunk) = line.chunks.first_mut() {
+                    first_chunk.left_adjust -= indent;
+                }
+            }
+            Item::Row(row) => row.indent(indent),
+            Item::Anchor => {}
+        }
+    }
+}
+
+/// Inserts the marker at the start of the first line of an item, which has already been
+/// indented.
+fn add_marker<'a>(line: &mut Line<'a>, mut marker: Vec<Chunk<'a>>) {
+    let marker_width = marker.iter().map(|chunk| chunk.width).sum::<f32>();
+    let marker_metrics = marker
+        .iter()
+        .fold(TextMetrics::default(), |metrics, chunk| {
+            metrics.max(chunk.text_metrics)
+        });
+
+    let marker_start = marker_start(&marker);
+    if let Some(first_chunk) = marker.first_mut() {
+        first_chunk.left_adjust = -marker_start;
+    }
+    if let Some(first_chunk) = line.chunks.first_mut() {
+        first_chunk.left_adjust += marker_start + marker_width;
+    }
+    line.chunks.splice(0..0, marker);
+    line.text_total_width += marker_width;
+
+    let text_metrics = line.text_metrics.max(marker_metrics);
+    line.delta.y -= text_metrics.ascent - line.text_metrics.ascent;
+    line.text_metrics = text_metrics;
+}
+
+/// The offset that right-aligns the marker in the indent, unless it is too wide.
+fn marker_start(marker: &[Chunk]) -> f32 {
+    let marker_width = marker.iter().map(|chunk| chunk.width).sum::<f32>();
+    (LIST_INDENT - LIST_MARKER_GAP - marker_width).max(0.0)
+}
+
+fn marker_text(marker: ListMarker, number: usize) -> String {
+    match marker {
+        ListMarker::Bullet => "•".to_owned(),
+        ListMarker::Decimal => format!("{number}."),
+        ListMarker::LowerAlpha => format!("{}.", alphabetic(number)),
+        ListMarker::UpperAlpha => format!("{}.", alphabetic(number).to_uppercase()),
+        ListMarker::LowerRoman => format!("{}.", roman(number)),
+        ListMarker::UpperRoman => format!("{}.", roman(number).to_uppercase()),
+    }
+}
+
+/// Converts a number to letters, continuing with "aa" after "z".
+fn alphabetic(mut number: usize) -> String {
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push((b'a' + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    let mut numeral = String::new();
+    for (value, symbol) in NUMERALS {
+        while number >= value {
+            numeral.push_str(symbol);
+            number -= value;
+        }
+    }
+    numeral
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+
+    use crate::{
+        document::ListMarker,
+        font::TextMetrics,
+        text_layout::{Chunk, Line},
+    };
+
+    use super::{add_marker, marker_text, LIST_INDENT, LIST_MARKER_GAP};
+
+    #[test]
+    fn test_marker_text() {
+        assert_eq!(marker_text(ListMarker::Bullet, 3), "•");
+        assert_eq!(marker_text(ListMarker::Decimal, 12), "12.");
+        assert_eq!(marker_text(ListMarker::LowerAlpha, 2), "b.");
+        assert_eq!(marker_text(ListMarker::UpperAlpha, 28), "AB.");
+        assert_eq!(marker_text(ListMarker::LowerRoman, 14), "xiv.");
+        assert_eq!(marker_text(ListMarker::UpperRoman, 1994), "MCMXCIV.");
+    }
+
+    #[test]
+    fn test_add_marker() {
+        let text_metrics = TextMetrics {
+            ascent: 8.0,
+            descent: -2.0,
+            line_gap: 0.0,
+            ..Default::default()
+        };
+        let mut line = Line {
+            chunks: vec![Chunk {
+                width: 50.0,
+                left_adjust: -LIST_INDENT,
+                ..Chunk::default()
+            }],
+            text_metrics,
+            text_total_width: 50.0,
+            delta: vec2(0.0, -8.0),
+        };
+        let marker = Chunk {
+            width: 4.0,
+            text_metrics: TextMetrics {
+                ascent: 10.0,
+                ..text_metrics
+            },
+            ..Chunk::default()
+        };
+
+        add_marker(&mut line, vec![marker]);
+
+        // The marker ends a gap before the indented text, which stays in place.
+        let marker_start = LIST_INDENT - LIST_MARKER_GAP - 4.0;
+        assert_eq!(line.chunks[0].left_adjust, -marker_start);
+        assert_eq!(
+            line.chunks[1].left_adjust,
+            -LIST_INDENT + marker_start + 4.0
+        );
+        assert_eq!(line.text_total_width, 54.0);
+        // The taller marker moves the baseline down.
+        assert_eq!(line.text_metrics.ascent, 10.0);
+        assert_eq!(line.delta.y, -10.0);
+    }
+}
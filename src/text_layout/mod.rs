@@ -0,0 +1,1729 @@
+use std::{borrow::Cow, collections::BTreeMap, mem, ops::Range};
+
+use glam::{vec2, Vec2};
+use itertools::Itertools;
+use thiserror::Error;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+use crate::{
+    document::{Block, Document, Footnote, Inline, Style, TextAlign, TextBlock, TextInline},
+    font::{
+        shape::{needs_shaping, Glyph},
+        Font, TextMetrics,
+    },
+};
+
+use self::{
+    bidi::{reorder_lines, resolve_levels},
+    contents::layout_contents,
+    context::Context,
+    line_break::{find_line_breaks, is_word_space},
+    list::layout_list,
+    table::{layout_table, Row},
+};
+
+mod bidi;
+mod contents;
+mod context;
+mod line_break;
+mod list;
+mod table;
+
+const PARAGRAPH_GAP: f32 = 12.0;
+const SUPERSCRIPT_SCALE: f32 = 0.7;
+const SUPERSCRIPT_RISE: f32 = 0.35;
+const FOOTNOTE_SCALE: f32 = 0.8;
+/// The space between the body text and the footnotes, with the separator rule in the middle.
+const FOOTNOTE_SEPARATOR: f32 = 12.0;
+const FOOTNOTE_RULE_LENGTH: f32 = 72.0;
+const FOOTNOTE_RULE_WIDTH: f32 = 0.5;
+/// The largest part of the page height that header rows can take and still be repeated when a
+/// table continues in a new column or page.
+const MAX_HEADER_SHARE: f32 = 0.5;
+/// The most times a document is laid out while waiting for the page numbers to settle.
+const MAX_LAYOUT_PASSES: usize = 8;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Page<'a> {
+    pub lines: Vec<Line<'a>>,
+    pub rules: Vec<Rule>,
+    /// The anchors that start on this page, numbered in document order.
+    pub anchors: Vec<usize>,
+}
+
+/// A straight line drawn on the page, such as the separator above the footnotes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rule {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub width: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line<'a> {
+    pub chunks: Vec<Chunk<'a>>,
+    pub text_metrics: TextMetrics,
+    pub text_total_width: f32,
+    pub delta: Vec2,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chunk<'a> {
+    pub text: Cow<'a, str>,
+    pub style: Style<'a>,
+    pub text_metrics: TextMetrics,
+    pub width: f32,
+    pub is_whitespace: bool,
+    pub left_adjust: f32,
+    /// How far the baseline is raised, for superscripts.
+    pub rise: f32,
+    /// The lines of the footnote that this chunk marks.
+    pub footnote: Vec<Line<'a>>,
+    /// The bidi embedding level, which is odd for right-to-left text.
+    pub level: u8,
+    /// Whether a line can or must end after this chunk.
+    pub line_break: Option<BreakOpportunity>,
+    /// The shaped glyphs of the text in visual order, or empty if each character has its own
+    /// glyph.
+    pub glyphs: Vec<Glyph>,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("missing font '{0}'")]
+    MissingFont(String),
+    #[error("missing glyph '{0}'")]
+    MissingGlyph(char),
+    #[error("missing glyph data '{0}'")]
+    MissingGlyphData(char),
+    #[error("unresolved references: {}", .0.join(", "))]
+    UnresolvedReferences(Vec<String>),
+    #[error("duplicate labels: {}", .0.join(", "))]
+    DuplicateLabels(Vec<String>),
+    #[error("table row {0} has {1} cells, more than the number of columns ({2})")]
+    TooManyCells(usize, usize, usize),
+}
+
+/// Lays out the document repeatedly until the page numbers that it refers to stop changing.
+///
+/// A table of contents can push the headings it lists onto later pages, and references can change
+/// line breaks, so each pass uses the page numbers from the one before. If they never settle, the
+/// last pass is returned.
+pub fn layout_document<'a>(document: &Document<'a>) -> Result<Vec<Page<'a>>, Error> {
+    let mut context = Context::new(document)?;
+
+    let mut pass = 1;
+    loop {
+        let pages = layout_pass(&context, document)?;
+
+        let page_numbers = pages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, page)| page.anchors.iter().map(move |_| i + 1))
+            .collect::<Vec<_>>();
+        if page_numbers == context.page_numbers || pass == MAX_LAYOUT_PASSES {
+            return Ok(pages);
+        }
+
+        context.page_numbers = page_numbers;
+        pass += 1;
+    }
+}
+
+fn layout_pass<'a>(
+    context: &Context<'a, '_>,
+    document: &Document<'a>,
+) -> Result<Vec<Page<'a>>, Error> {
+    let target_width = document.page_size.x - 2.0 * document.margin;
+    let target_height = document.page_size.y - 2.0 * document.margin;
+    let column_width = document.columns.width(target_width);
+
+    let mut sections = Vec::<Section>::new();
+    for block in &document.blocks {
+        let span = matches!(block, Block::Span(_));
+        let width = if span { target_width } else { column_width };
+        let items = layout_block(context, width, block)?;
+
+        match sections.last_mut() {
+            Some(section) if section.span == span => section.items.extend(items),
+            _ => sections.push(Section { items, span }),
+        }
+    }
+
+    let pages = layout_pages(sections, target_height, document);
+
+    Ok(pages)
+}
+
+fn layout_pages<'a>(
+    sections: Vec<Section<'a>>,
+    target_height: f32,
+    document: &Document<'a>,
+) -> Vec<Page<'a>> {
+    let mut layout = PageLayout::new(document, target_height);
+
+    let section_count = sections.len();
+    for (i, section) in sections.into_iter().enumerate() {
+        layout.start_region(section.span);
+        for item in section.items {
+            layout.push_item(item);
+        }
+
+        // Columns are always balanced before a spanning block, so that it can be placed below them.
+        let is_last = i + 1 == section_count;
+        layout.finish_region(!is_last || document.columns.balance);
+    }
+
+    layout.finish()
+}
+
+/// A run of consecutive items that either flows through the columns or spans all of them.
+struct Section<'a> {
+    items: Vec<Item<'a>>,
+    span: bool,
+}
+
+/// Something that is placed into a column as a single unit.
+#[derive(Debug, Clone, PartialEq)]
+enum Item<'a> {
+    Line(Line<'a>),
+    Row(Row<'a>),
+    /// Marks the start of a heading or label, so that the page it is placed on can be recorded.
+    Anchor,
+}
+
+/// Places items into columns and pages.
+///
+/// Each page is made up of regions stacked vertically. A region is either split into columns,
+/// or spans the full width of the page.
+struct PageLayout<'a, 'd> {
+    document: &'d Document<'a>,
+    target_height: f32,
+    column_width: f32,
+    pages: Vec<Page<'a>>,
+    current_page: Page<'a>,
+    /// The position of the last line placed on the current page.
+    position: Vec2,
+    /// The height taken up by the previous regions on the current page.
+    region_top: f32,
+    span: bool,
+    columns: Vec<Vec<Item<'a>>>,
+    column_height: f32,
+    /// The height of the tallest finished column in the current region.
+    region_height: f32,
+    /// The footnote lines placed at the bottom of the current page.
+    footnotes: Vec<Line<'a>>,
+    /// The height reserved for footnotes on the current page, including the separator.
+    footnotes_height: f32,
+    /// The footnote lines that did not fit on the current page.
+    deferred_footnotes: Vec<Line<'a>>,
+    anchor_count: usize,
+    /// The anchors waiting to be placed on a page along with the item that follows them.
+    pending_anchors: Vec<usize>,
+}
+
+impl<'a, 'd> PageLayout<'a, 'd> {
+    fn new(document: &'d Document<'a>, target_height: f32) -> Self {
+        let target_width = document.page_size.x - 2.0 * document.margin;
+        Self {
+            document,
+            target_height,
+            column_width: document.columns.width(target_width),
+            pages: Vec::new(),
+            current_page: Page::default(),
+            position: Vec2::ZERO,
+            region_top: 0.0,
+            span: false,
+            columns: vec![Vec::new()],
+            column_height: 0.0,
+            region_height: 0.0,
+            footnotes: Vec::new(),
+            footnotes_height: 0.0,
+            deferred_footnotes: Vec::new(),
+            anchor_count: 0,
+            pending_anchors: Vec::new(),
+        }
+    }
+
+    fn column_count(&self) -> usize {
+        if self.span {
+            1
+        } else {
+            self.document.columns.count.max(1)
+        }
+    }
+
+    fn start_region(&mut self, span: bool) {
+        self.span = span;
+        self.columns = vec![Vec::new()];
+        self.column_height = 0.0;
+        self.region_height = 0.0;
+    }
+
+    fn column_is_empty(&self) -> bool {
+        self.columns.last().is_none_or(Vec::is_empty)
+    }
+
+    /// Moves on to the next column, or to the next page if this was the last column.
+    fn break_column(&mut self) {
+        if !self.column_is_empty() && self.columns.len() < self.column_count() {
+            self.columns.push(Vec::new());
+            self.region_height = self.region_height.max(self.column_height);
+        } else {
+            self.finish_region(false);
+            self.new_page();
+        }
+        self.column_height = 0.0;
+    }
+
+    fn push_item(&mut self, item: Item<'a>) {
+        match item {
+            Item::Line(line) => self.push_line(line),
+            Item::Row(row) => self.push_row(row),
+            Item::Anchor => {
+                self.pending_anchors.push(self.anchor_count);
+                self.anchor_count += 1;
+            }
+        }
+    }
+
+    fn push_line(&mut self, line: Line<'a>) {
+        let footnotes = footnote_lines(&line);
+
+        let first_on_page = self.column_is_empty() && self.region_top == 0.0;
+        let line_height = line_height(&line, first_on_page);
+
+        let available_height = self.target_height - self.region_top - self.footnotes_height;
+        let overflows = self.column_height + line_height > available_height
+            || !self.footnotes_fit(&footnotes, self.column_height + line_height);
+        if overflows && !first_on_page {
+            self.break_column();
+        }
+
+        self.place(Item::Line(line), line_height, footnotes);
+    }
+
+    fn push_row(&mut self, mut row: Row<'a>) {
+        // Whether the column holds only the header rows repeated for this row, so that moving on
+        // to the next column wouldn't leave more space for it.
+        let mut after_header = false;
+        loop {
+            let first_on_page = self.column_is_empty() && self.region_top == 0.0;
+            let height = row_height(&row, first_on_page);
+            let footnotes = row.footnote_lines();
+
+            let available_height = self.target_height - self.region_top - self.footnotes_height;
+            let fits = self.column_height + height <= available_height
+                && self.footnotes_fit(&footnotes, self.column_height + height);
+            if fits {
+                self.place(Item::Row(row), height, footnotes);
+                return;
+            }
+
+            // Rows that are too tall for a whole page are split, rather than moved as a unit.
+            let force = first_on_page || after_header;
+            if force || row.height + row.header_height() > self.target_height {
+                let space = available_height - self.column_height - (height - row.height);
+                let (first, rest) = row.split(space, force);
+                if !first.is_empty() {
+                    let height = row_height(&first, first_on_page);
+                    let footnotes = first.footnote_lines();
+                    self.place(Item::Row(first), height, footnotes);
+                }
+                if rest.is_empty() {
+                    return;
+                }
+                row = rest;
+            }
+
+            self.break_column();
+            after_header = false;
+            if row.header_height() <= MAX_HEADER_SHARE * self.target_height {
+                for header_row in row.header.clone() {
+                    let first_on_page = self.column_is_empty() && self.region_top == 0.0;
+                    let height = row_height(&header_row, first_on_page);
+                    self.place(Item::Row(header_row), height, Vec::new());
+                    after_header = true;
+                }
+            }
+        }
+    }
+
+    /// Adds an item to the current column without checking whether it fits.
+    fn place(&mut self, item: Item<'a>, height: f32, footnotes: Vec<Line<'a>>) {
+        match self.columns.last_mut() {
+            Some(column) => column.push(item),
+            None => self.columns.push(vec![item]),
+        }
+        self.column_height += height;
+        self.current_page.anchors.append(&mut self.pending_anchors);
+
+        self.push_footnotes(footnotes, false);
+    }
+
+    /// The height left for footnote lines if the current column were `column_height` tall.
+    fn footnote_space(&self, column_height: f32) -> f32 {
+        let mut space = self.target_height
+            - self.region_top
+            - self.region_height.max(column_height)
+            - self.footnotes_height;
+        if self.footnotes.is_empty() {
+            space -= FOOTNOTE_SEPARATOR;
+        }
+        space
+    }
+
+    /// Checks whether at least the first line of the footnotes would fit on the current page.
+    fn footnotes_fit(&self, footnotes: &[Line], column_height: f32) -> bool {
+        match footnotes.first() {
+            Some(first_line) if self.deferred_footnotes.is_empty() => {
+                line_height(first_line, self.footnotes.is_empty())
+                    <= self.footnote_space(column_height)
+            }
+            _ => true,
+        }
+    }
+
+    /// Reserves space for as many footnote lines as will fit, deferring the rest to the next page.
+    fn push_footnotes(&mut self, mut lines: Vec<Line<'a>>, force_first: bool) {
+        if !self.deferred_footnotes.is_empty() {
+            self.deferred_footnotes.extend(lines);
+            return;
+        }
+
+        let mut space = self.footnote_space(self.column_height);
+        let mut count = 0;
+        for line in &lines {
+            let line_height = line_height(line, count == 0 && self.footnotes.is_empty());
+            if line_height > space && !(force_first && count == 0) {
+                break;
+            }
+            space -= line_height;
+            count += 1;
+        }
+
+        self.deferred_footnotes = lines.split_off(count);
+        for line in lines {
+            self.footnotes_height += line_height(&line, self.footnotes.is_empty());
+            if self.footnotes.is_empty() {
+                self.footnotes_height += FOOTNOTE_SEPARATOR;
+            }
+            self.footnotes.push(line);
+        }
+    }
+
+    /// Positions the items of the current region on the page.
+    fn finish_region(&mut self, balance: bool) {
+        let first_on_page = self.region_top == 0.0;
+        let count = self.column_count();
+
+        let mut columns = mem::take(&mut self.columns);
+        if balance && count > 1 {
+            let items = columns.into_iter().flatten().collect();
+            columns = balance_columns(items, count, first_on_page);
+        }
+
+        let top = self.document.page_size.y - self.document.margin - self.region_top;
+        let mut region_height = 0.0_f32;
+        for (i, column) in columns.into_iter().enumerate() {
+            let x =
+                self.document.margin + i as f32 * (self.column_width + self.document.columns.gap);
+            region_height = region_height.max(column_height(&column, first_on_page));
+
+            // Lines are positioned relative to the previous line, except at the top of a column
+            // and after a row.
+            let mut y = top;
+            let mut jump = true;
+            for (j, item) in column.into_iter().enumerate() {
+                let first_on_page = j == 0 && first_on_page;
+                let height = item_height(&item, first_on_page);
+
+                match item {
+                    Item::Line(mut line) => {
+                        if jump {
+                            let line_gap = if first_on_page {
+                                0.0
+                            } else {
+                                line.text_metrics.line_gap
+                            };
+                            let target = vec2(x, y - line_gap - line.text_metrics.ascent);
+                            line.delta = target - self.position;
+                        }
+
+                        self.position += line.delta;
+                        self.current_page.lines.push(line);
+                        jump = false;
+                    }
+                    Item::Row(row) => {
+                        let gap = if first_on_page { 0.0 } else { row.gap };
+                        row.place(vec2(x, y - gap), &mut self.position, &mut self.current_page);
+                        jump = true;
+                    }
+                    Item::Anchor => {}
+                }
+
+                y -= height;
+            }
+        }
+
+        self.region_top += region_height;
+        self.columns = vec![Vec::new()];
+        self.column_height = 0.0;
+        self.region_height = 0.0;
+    }
+
+    /// Positions the footnotes and their separator at the bottom of the current page.
+    fn finish_footnotes(&mut self) {
+        if self.footnotes.is_empty() {
+            return;
+        }
+
+        let margin = self.document.margin;
+        let top = margin + self.footnotes_height;
+        let rule_y = top - 0.5 * FOOTNOTE_SEPARATOR;
+        self.current_page.rules.push(Rule {
+            start: vec2(margin, rule_y),
+            end: vec2(margin + FOOTNOTE_RULE_LENGTH, rule_y),
+            width: FOOTNOTE_RULE_WIDTH,
+        });
+
+        let mut y = top - FOOTNOTE_SEPARATOR;
+        for (i, mut line) in mem::take(&mut self.footnotes).into_iter().enumerate() {
+            if i > 0 {
+                y -= line.text_metrics.line_gap;
+            }
+            let target = vec2(margin, y - line.text_metrics.ascent);
+            line.delta = target - self.position;
+            self.position = target;
+            y = target.y + line.text_metrics.descent;
+
+            self.current_page.lines.push(line);
+        }
+        self.footnotes_height = 0.0;
+    }
+
+    fn new_page(&mut self) {
+        self.finish_footnotes();
+        self.pages.push(mem::take(&mut self.current_page));
+        self.position = Vec2::ZERO;
+        self.region_top = 0.0;
+
+        let deferred_footnotes = mem::take(&mut self.deferred_footnotes);
+        self.push_footnotes(deferred_footnotes, true);
+    }
+
+    fn finish(mut self) -> Vec<Page<'a>> {
+        self.current_page.anchors.append(&mut self.pending_anchors);
+        while !self.deferred_footnotes.is_empty() {
+            self.new_page();
+        }
+        self.finish_footnotes();
+        self.pages.push(self.current_page);
+        self.pages
+    }
+}
+
+/// Collects the footnote lines marked in a line, including any nested footnotes.
+fn footnote_lines<'a>(line: &Line<'a>) -> Vec<Line<'a>> {
+    let mut lines = Vec::new();
+    for chunk in &line.chunks {
+        for footnote_line in &chunk.footnote {
+            lines.push(footnote_line.clone());
+            lines.extend(footnote_lines(footnote_line));
+        }
+    }
+    lines
+}
+
+fn line_height(line: &Line, first_on_page: bool) -> f32 {
+    let mut line_height = line.text_metrics.line_height();
+    if first_on_page {
+        line_height -= line.text_metrics.line_gap;
+    }
+    line_height
+}
+
+fn row_height(row: &Row, first_on_page: bool) -> f32 {
+    if first_on_page {
+        row.height
+    } else {
+        row.gap + row.height
+    }
+}
+
+fn item_height(item: &Item, first_on_page: bool) -> f32 {
+    match item {
+        Item::Line(line) => line_height(line, first_on_page),
+        Item::Row(row) => row_height(row, first_on_page),
+        Item::Anchor => 0.0,
+    }
+}
+
+fn column_height(items: &[Item], first_on_page: bool) -> f32 {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| item_height(item, i == 0 && first_on_page))
+        .sum()
+}
+
+/// Redistributes the items of a region so that the columns end at roughly the same height.
+fn balance_columns<'a>(
+    items: Vec<Item<'a>>,
+    count: usize,
+    first_on_page: bool,
+) -> Vec<Vec<Item<'a>>> {
+    let mut height = column_height(&items, first_on_page) / count as f32;
+    let sizes = loop {
+        let (sizes, next_height) = fill_columns(&items, count, height, first_on_page);
+        if sizes.iter().sum::<usize>() == items.len() || !next_height.is_finite() {
+            break sizes;
+        }
+        height = next_height;
+    };
+
+    let mut items = items.into_iter();
+    let mut columns = sizes
+        .into_iter()
+        .map(|size| items.by_ref().take(size).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    if let Some(last_column) = columns.last_mut() {
+        last_column.extend(items);
+    }
+
+    columns
+}
+
+/// Greedily fills up to `count` columns that are at most `height` tall.
+///
+/// Returns the number of items in each column, and the smallest height that would have fit
+/// another item into one of the columns.
+fn fill_columns(
+    items: &[Item],
+    count: usize,
+    height: f32,
+    first_on_page: bool,
+) -> (Vec<usize>, f32) {
+    let mut sizes = vec![0];
+    let mut current_height = 0.0;
+    let mut next_height = f32::INFINITY;
+    for item in items {
+        let column_is_empty = sizes.last() == Some(&0);
+        let item_height = item_height(item, column_is_empty && first_on_page);
+
+        if current_height + item_height > height && !column_is_empty {
+            next_height = next_height.min(current_height + item_height);
+            if sizes.len() >= count {
+                break;
+            }
+            sizes.push(0);
+            current_height = 0.0;
+        }
+
+        if let Some(size) = sizes.last_mut() {
+            *size += 1;
+        }
+        current_height += item_height;
+    }
+
+    (sizes, next_height)
+}
+
+fn layout_block<'a>(
+    context: &Context<'a, '_>,
+    target_width: f32,
+    block: &Block<'a>,
+) -> Result<Vec<Item<'a>>, Error> {
+    match block {
+        Block::Span(blocks) => blocks
+            .iter()
+            .map(|block| layout_block(context, target_width, block))
+            .flatten_ok()
+            .collect(),
+        Block::Text(block) => layout_text(context, target_width, block),
+        Block::Table(table) => {
+            let rows = layout_table(context, target_width, table)?;
+            Ok(rows.into_iter().map(Item::Row).collect())
+        }
+        Block::List(list) => layout_list(context, target_width, list),
+        Block::Heading(heading) => {
+            let mut items = vec![Item::Anchor];
+            items.extend(layout_text(context, target_width, &heading.block)?);
+            Ok(items)
+        }
+        Block::TableOfContents(contents) => layout_contents(context, target_width, contents),
+        Block::Label(_) => Ok(vec![Item::Anchor]),
+    }
+}
+
+fn layout_text<'a>(
+    context: &Context<'a, '_>,
+    target_width: f32,
+    block: &TextBlock<'a>,
+) -> Result<Vec<Item<'a>>, Error> {
+    let chunks = chunk_inlines(context, target_width, &block.inlines, 1.0)?;
+
+    let mut lines = layout_paragraph(context.fonts, target_width, chunks, &block.align)?;
+
+    if let Some(first_line) = lines.first_mut() {
+        first_line.delta.y -= PARAGRAPH_GAP;
+        first_line.text_metrics.line_gap += PARAGRAPH_GAP;
+    }
+
+    Ok(lines.into_iter().map(Item::Line).collect())
+}
+
+/// Breaks a paragraph into lines, which are reordered for display and aligned.
+fn layout_paragraph<'a>(
+    fonts: &BTreeMap<&str, &'a Font>,
+    target_width: f32,
+    mut chunks: Vec<Chunk<'a>>,
+    align: &TextAlign,
+) -> Result<Vec<Line<'a>>, Error> {
+    find_line_breaks(&mut chunks);
+    let paragraph_level = resolve_levels(fonts, &mut chunks)?;
+
+    let mut lines = layout_lines(target_width, chunks);
+
+    reorder_lines(&mut lines, paragraph_level);
+    align_lines(align, paragraph_level.is_rtl(), target_width, &mut lines);
+
+    Ok(lines)
+}
+
+fn layout_lines(target_width: f32, chunks: Vec<Chunk>) -> Vec<Line> {
+    let mut lines = Vec::<Line>::new();
+    let mut line_start = 0;
+    let mut possible_break = 0;
+    let mut width_to_break = 0.0;
+    let mut x = 0.0;
+    let mut current_line_text_metrics = TextMetrics::default();
+    for (i, chunk) in chunks.iter().enumerate() {
+        // Lines can end before whitespace, or between words where a break opportunity was found.
+        let prev_chunk = i.checked_sub(1).map(|j| &chunks[j]);
+        let prev_line_break = prev_chunk.and_then(|prev_chunk| prev_chunk.line_break);
+        let after_word = prev_chunk.is_some_and(|prev_chunk| !prev_chunk.is_whitespace);
+        if chunk.is_whitespace && !chunks[i.saturating_sub(1)].is_whitespace
+            || !chunk.is_whitespace && after_word && prev_line_break.is_some()
+        {
+            width_to_break = x;
+            possible_break = i;
+        }
+
+        let overflows = x - chunk.left_adjust + chunk.width > target_width;
+        let must_break = prev_line_break == Some(BreakOpportunity::Mandatory);
+        if possible_break > line_start && (overflows || must_break) {
+            let mut line_spacing =
+                current_line_text_metrics.line_gap + current_line_text_metrics.ascent;
+            if let Some(prev_line) = lines.last() {
+                line_spacing -= prev_line.text_metrics.descent;
+            }
+
+            let line = Line {
+                chunks: chunks[line_start..possible_break].to_vec(),
+                text_metrics: current_line_text_metrics,
+                text_total_width: width_to_break,
+                delta: vec2(0.0, -line_spacing),
+            };
+            lines.push(line);
+
+            line_start = possible_break;
+            while let Some(next) = chunks.get(line_start) {
+                if next.is_whitespace {
+                    line_start += 1;
+                } else {
+                    break;
+                }
+            }
+
+            // The chunks between the break and this one move on to the new line.
+            let carried = &chunks[line_start.min(i)..=i];
+            x = carried.iter().map(|chunk| chunk.width).sum();
+            current_line_text_metrics = carried[1..]
+                .iter()
+                .fold(carried[0].text_metrics, |metrics, chunk| {
+                    metrics.max(chunk.text_metrics)
+                });
+        } else if i >= line_start {
+            if i == line_start {
+                x = 0.0;
+            }
+            x += chunk.width;
+            current_line_text_metrics = current_line_text_metrics.max(chunk.text_metrics);
+        }
+    }
+
+    // Add the last line if there are any chunks left
+    if line_start < chunks.len() {
+        let mut line_spacing =
+            current_line_text_metrics.line_gap + current_line_text_metrics.ascent;
+        if let Some(prev_line) = lines.last() {
+            line_spacing -= prev_line.text_metrics.descent;
+        }
+
+        let line = Line {
+            chunks: chunks[line_start..].to_vec(),
+            text_metrics: current_line_text_metrics,
+            text_total_width: x,
+            delta: vec2(0.0, -line_spacing),
+        };
+        lines.push(line);
+    }
+
+    #[cfg(debug_assertions)]
+    for line in &lines {
+        debug_assert_eq!(
+            line.chunks.iter().map(|c| c.width).sum::<f32>(),
+            line.text_total_width,
+        );
+    }
+
+    lines
+}
+
+/// Aligns the lines of a paragraph, where `rtl` is whether the paragraph is right-to-left.
+fn align_lines(align: &TextAlign, rtl: bool, target_width: f32, lines: &mut Vec<Line>) {
+    match (align, rtl) {
+        (TextAlign::Left, _) | (TextAlign::Start, false) | (TextAlign::End, true) => {} // Do nothing
+        (TextAlign::Center, _) => {
+            for line in lines {
+                let remaining_width = target_width - line.text_total_width;
+                if let Some(first_chunk) = line.chunks.first_mut() {
+                    first_chunk.left_adjust = -0.5 * remaining_width;
+                }
+            }
+        }
+        (TextAlign::Right, _) | (TextAlign::Start, true) | (TextAlign::End, false) => {
+            for line in lines {
+                align_right(line, target_width);
+            }
+        }
+        (TextAlign::Justify, _) => {
+            let line_count = lines.len();
+            for line in &mut lines[0..line_count.saturating_sub(1)] {
+                let remaining_width = target_width - line.text_total_width;
+
+                let mut num_whitespace_gaps = 0;
+                for i in 1..line.chunks.len() {
+                    if line.chunks[i - 1].is_whitespace || line.chunks[i].is_whitespace {
+                        num_whitespace_gaps += 1;
+                    }
+                }
+
+                let gap_width = remaining_width / num_whitespace_gaps as f32;
+                for i in 1..line.chunks.len() {
+                    if line.chunks[i - 1].is_whitespace || line.chunks[i].is_whitespace {
+                        line.chunks[i].left_adjust = -gap_width;
+                    }
+                }
+            }
+
+            // The last line is aligned to the start of the paragraph.
+            if rtl {
+                if let Some(last_line) = lines.last_mut() {
+                    align_right(last_line, target_width);
+                }
+            }
+        }
+    }
+}
+
+fn align_right(line: &mut Line, target_width: f32) {
+    let remaining_width = target_width - line.text_total_width;
+    if let Some(first_chunk) = line.chunks.first_mut() {
+        first_chunk.left_adjust = -remaining_width;
+    }
+}
+
+/// Splits inlines into chunks, with their font sizes multiplied by `scale`.
+fn chunk_inlines<'a>(
+    context: &Context<'a, '_>,
+    target_width: f32,
+    inlines: &[Inline<'a>],
+    scale: f32,
+) -> Result<Vec<Chunk<'a>>, Error> {
+    let mut chunks = Vec::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text(inline) => {
+                let inline = TextInline {
+                    text: inline.text,
+                    style: Style {
+                        font_size: scale * inline.style.font_size,
+                        ..inline.style
+                    },
+                };
+                chunks.extend(chunk_inline(context.fonts, &inline)?);
+            }
+            Inline::Footnote(footnote) => {
+                let mut marker = chunk_marker(context.fonts, footnote, scale)?;
+
+                let footnote_scale = FOOTNOTE_SCALE * scale;
+                let mut body = chunk_marker(context.fonts, footnote, footnote_scale)?;
+                body.extend(chunk_inlines(
+                    context,
+                    target_width,
+                    &footnote.inlines,
+                    footnote_scale,
+                )?);
+
+                if let Some(last_chunk) = marker.last_mut() {
+                    last_chunk.footnote =
+                        layout_paragraph(context.fonts, target_width, body, &TextAlign::Start)?;
+                }
+                chunks.extend(marker);
+            }
+            Inline::Reference(reference) => {
+                let text = context.reference_text(reference.label, reference.kind);
+                let style = Style {
+                    font_size: scale * reference.style.font_size,
+                    ..reference.style
+                };
+                chunks.extend(chunk_text(context.fonts, Cow::Owned(text), style)?);
+            }
+        }
+    }
+
+    Ok(chunks)
+}
+
+/// Creates the superscript chunks for a footnote marker.
+fn chunk_marker<'a>(
+    fonts: &BTreeMap<&str, &'a Font>,
+    footnote: &Footnote<'a>,
+    scale: f32,
+) -> Result<Vec<Chunk<'a>>, Error> {
+    let font_size = scale * footnote.style.font_size;
+    let inline = TextInline {
+        text: footnote.marker,
+        style: Style {
+            font_size: SUPERSCRIPT_SCALE * font_size,
+            ..footnote.style
+        },
+    };
+    let rise = SUPERSCRIPT_RISE * font_size;
+
+    let mut chunks = chunk_inline(fonts, &inline)?;
+    for chunk in &mut chunks {
+        chunk.rise = rise;
+        chunk.text_metrics.ascent += rise;
+    }
+
+    Ok(chunks)
+}
+
+fn chunk_inline<'a>(
+    fonts: &BTreeMap<&str, &'a Font>,
+    inline: &TextInline<'a>,
+) -> Result<Vec<Chunk<'a>>, Error> {
+    chunk_text(fonts, Cow::Borrowed(inline.text), inline.style)
+}
+
+/// Splits text into chunks, which may borrow from the document or be generated during layout.
+///
+/// Each character is taken from the first font in the style's fallback chain that has a glyph for
+/// it, and runs from different fonts are split into separate chunks. No-break spaces are kept
+/// inside the words around them. Synthetic bold widens every glyph by its stroke width.
+fn chunk_text<'a>(
+    fonts: &BTreeMap<&str, &'a Font>,
+    text: Cow<'a, str>,
+    style: Style<'a>,
+) -> Result<Vec<Chunk<'a>>, Error> {
+    let chain = style
+        .font_chain()
+        .map(|name| {
+            let font = fonts
+                .get(name)
+                .ok_or_else(|| Error::MissingFont(name.to_owned()))?;
+            Ok((name, *font))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let chunk = |range: Range<usize>, font_index: usize, width: f32, is_whitespace: bool| {
+        let (font_name, font) = chain[font_index];
+        let text = substring(&text, range);
+
+        // Words in scripts such as Arabic are measured by their shaped glyphs, since the glyphs
+        // change with the characters around them.
+        let (glyphs, width) = if !is_whitespace && text.chars().any(needs_shaping) {
+            let glyphs = font.shape(&text);
+            let advance = glyphs.iter().map(|glyph| glyph.advance).sum::<i32>();
+            let stroke = glyphs.len() as f32 * style.bold_stroke_width();
+            (glyphs, advance as f32 * style.font_size / 1000.0 + stroke)
+        } else {
+            (Vec::new(), width)
+        };
+
+        Chunk {
+            text,
+            style: Style {
+                font: font_name,
+                ..style
+            },
+            text_metrics: font.metrics() * style.font_size,
+            width,
+            is_whitespace,
+            left_adjust: 0.0,
+            rise: 0.0,
+            footnote: Vec::new(),
+            level: 0,
+            line_break: None,
+            glyphs,
+        }
+    };
+
+    let mut chunks = Vec::new();
+    let mut current_chunk_start = 0;
+    let mut current_chunk_width = 0.0;
+    let mut current_font_index = 0;
+    let mut breaks = linebreaks(&text).map(|(i, _)| i).peekable();
+    for (i, c) in text.char_indices() {
+        let (font_index, glyph_id) = chain
+            .iter()
+            .enumerate()
+            .find_map(|(j, (_, font))| font.face.glyph_index(c).map(|glyph_id| (j, glyph_id)))
+            .ok_or(Error::MissingGlyph(c))?;
+        let font = chain[font_index].1;
+        let font_scale = style.font_size / font.face.units_per_em() as f32;
+        let width = font
+            .glyph_advance(glyph_id)
+            .ok_or(Error::MissingGlyphData(c))? as f32
+            * font_scale
+            + style.bold_stroke_width();
+
+        if is_word_space(c) {
+            let next_i = i + c.len_utf8();
+
+            if current_chunk_start < i {
+                let prev_chunk = chunk(
+                    current_chunk_start..i,
+                    current_font_index,
+                    current_chunk_width,
+                    false,
+                );
+                chunks.push(prev_chunk);
+            }
+
+            chunks.push(chunk(i..next_i, font_index, width, true));
+
+            current_chunk_start = next_i;
+            current_chunk_width = 0.0;
+        } else {
+            // Words are split where the font changes, and where a line could end inside them, such
+            // as between ideographs or after hyphens.
+            while breaks.next_if(|&j| j < i).is_some() {}
+            let break_before = breaks.next_if_eq(&i).is_some();
+            if (font_index != current_font_index || break_before) && current_chunk_start < i {
+                let prev_chunk = chunk(
+                    current_chunk_start..i,
+                    current_font_index,
+                    current_chunk_width,
+                    false,
+                );
+                chunks.push(prev_chunk);
+
+                current_chunk_start = i;
+                current_chunk_width = 0.0;
+            }
+            current_font_index = font_index;
+            current_chunk_width += width;
+        }
+    }
+
+    if current_chunk_start < text.len() {
+        let final_chunk = chunk(
+            current_chunk_start..text.len(),
+            current_font_index,
+            current_chunk_width,
+            false,
+        );
+        chunks.push(final_chunk);
+    }
+
+    Ok(chunks)
+}
+
+fn substring<'a>(text: &Cow<'a, str>, range: Range<usize>) -> Cow<'a, str> {
+    match text {
+        Cow::Borrowed(text) => Cow::Borrowed(&text[range]),
+        Cow::Owned(text) => Cow::Owned(text[range].to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, iter};
+
+    use glam::{vec2, Vec2};
+    use unicode_linebreak::BreakOpportunity;
+
+    use crate::{
+        document::{Columns, Document, Style, TextAlign, TextBlock, TextInline},
+        font::{Font, TextMetrics},
+    };
+
+    use super::{
+        align_lines, chunk_inline, layout_lines, layout_pages, Chunk, Error, Item, Line, Rule,
+        Section,
+    };
+
+    #[test]
+    fn test_layout_pages() {
+        let page_size = vec2(500.0, 500.0);
+        let margin = 100.0;
+        let target_height = 100.0;
+        let text_metrics = TextMetrics {
+            ascent: 20.0,
+            descent: -5.0,
+            line_gap: 10.0,
+            ..Default::default()
+        };
+        let delta = vec2(0.0, -35.0);
+
+        let line = Line {
+            chunks: vec![],
+            text_metrics,
+            text_total_width: 0.0,
+            delta,
+        };
+        let lines = iter::repeat(line).take(5).collect::<Vec<_>>();
+        let document = Document {
+            blocks: Default::default(),
+            fonts: Default::default(),
+            page_size,
+            margin,
+            columns: Default::default(),
+        };
+
+        let sections = vec![Section {
+            items: lines.into_iter().map(Item::Line).collect(),
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 2);
+
+        assert_eq!(pages[0].lines.len(), 3);
+        assert_eq!(pages[0].lines[0].delta, vec2(100.0, 380.0));
+        assert_eq!(pages[0].lines[1].delta, delta);
+        assert_eq!(pages[0].lines[2].delta, delta);
+
+        assert_eq!(pages[1].lines.len(), 2);
+        assert_eq!(pages[1].lines[0].delta, vec2(100.0, 380.0));
+        assert_eq!(pages[1].lines[1].delta, delta);
+    }
+
+    #[test]
+    fn test_anchor_pages() {
+        let line = Line {
+            chunks: vec![],
+            text_metrics: TextMetrics {
+                ascent: 20.0,
+                descent: -5.0,
+                line_gap: 10.0,
+                ..Default::default()
+            },
+            text_total_width: 0.0,
+            delta: vec2(0.0, -35.0),
+        };
+        let document = Document {
+            blocks: Default::default(),
+            fonts: Default::default(),
+            page_size: vec2(500.0, 500.0),
+            margin: 100.0,
+            columns: Default::default(),
+        };
+
+        // The second anchor is followed by a line that moves on to the next page.
+        let mut items = vec![Item::Line(line.clone()); 5];
+        items.insert(3, Item::Anchor);
+        items.insert(0, Item::Anchor);
+        let sections = vec![Section { items, span: false }];
+        let pages = layout_pages(sections, 100.0, &document);
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].anchors, vec![0]);
+        assert_eq!(pages[1].anchors, vec![1]);
+    }
+
+    fn column_test_items(count: usize) -> Vec<Item<'static>> {
+        let line = Line {
+            chunks: vec![],
+            text_metrics: TextMetrics {
+                ascent: 20.0,
+                descent: -5.0,
+                line_gap: 10.0,
+                ..Default::default()
+            },
+            text_total_width: 0.0,
+            delta: vec2(0.0, -35.0),
+        };
+        vec![Item::Line(line); count]
+    }
+
+    pub(super) fn column_test_document(balance: bool) -> Document<'static> {
+        Document {
+            blocks: Default::default(),
+            fonts: Default::default(),
+            page_size: vec2(500.0, 500.0),
+            margin: 100.0,
+            columns: Columns {
+                count: 2,
+                gap: 20.0,
+                balance,
+            },
+        }
+    }
+
+    #[test]
+    fn test_layout_columns() {
+        let target_height = 100.0;
+        let document = column_test_document(false);
+
+        let sections = vec![Section {
+            items: column_test_items(5),
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].lines.len(), 5);
+        assert_eq!(pages[0].lines[0].delta, vec2(100.0, 380.0));
+        assert_eq!(pages[0].lines[1].delta, vec2(0.0, -35.0));
+        assert_eq!(pages[0].lines[2].delta, vec2(0.0, -35.0));
+        // The second column starts 140pt wide column + 20pt gap to the right, back at the top.
+        assert_eq!(pages[0].lines[3].delta, vec2(160.0, 70.0));
+        assert_eq!(pages[0].lines[4].delta, vec2(0.0, -35.0));
+    }
+
+    #[test]
+    fn test_balance_columns() {
+        let target_height = 100.0;
+        let document = column_test_document(true);
+
+        let sections = vec![Section {
+            items: column_test_items(4),
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].lines.len(), 4);
+        assert_eq!(pages[0].lines[0].delta, vec2(100.0, 380.0));
+        assert_eq!(pages[0].lines[1].delta, vec2(0.0, -35.0));
+        assert_eq!(pages[0].lines[2].delta, vec2(160.0, 35.0));
+        assert_eq!(pages[0].lines[3].delta, vec2(0.0, -35.0));
+    }
+
+    #[test]
+    fn test_span_columns() {
+        let target_height = 200.0;
+        let document = column_test_document(false);
+
+        let sections = vec![
+            Section {
+                items: column_test_items(4),
+                span: false,
+            },
+            Section {
+                items: column_test_items(1),
+                span: true,
+            },
+        ];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].lines.len(), 5);
+        // The columns are balanced before the spanning line.
+        assert_eq!(pages[0].lines[2].delta, vec2(160.0, 35.0));
+        assert_eq!(pages[0].lines[3].delta, vec2(0.0, -35.0));
+        // The spanning line is placed below the columns, back at the left margin.
+        assert_eq!(pages[0].lines[4].delta, vec2(-160.0, -35.0));
+    }
+
+    fn footnote_test_line(footnote_lines: usize) -> Line<'static> {
+        let footnote_line = Line {
+            chunks: vec![],
+            text_metrics: TextMetrics {
+                ascent: 8.0,
+                descent: -2.0,
+                line_gap: 0.0,
+                ..Default::default()
+            },
+            text_total_width: 0.0,
+            delta: vec2(0.0, -10.0),
+        };
+        let chunk = Chunk {
+            footnote: vec![footnote_line; footnote_lines],
+            ..Chunk::default()
+        };
+
+        Line {
+            chunks: vec![chunk],
+            text_metrics: TextMetrics {
+                ascent: 20.0,
+                descent: -5.0,
+                line_gap: 10.0,
+                ..Default::default()
+            },
+            text_total_width: 0.0,
+            delta: vec2(0.0, -35.0),
+        }
+    }
+
+    #[test]
+    fn test_layout_footnotes() {
+        let target_height = 100.0;
+        let document = Document {
+            columns: Default::default(),
+            ..column_test_document(false)
+        };
+
+        let sections = vec![Section {
+            items: vec![Item::Line(footnote_test_line(1))],
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].lines.len(), 2);
+        assert_eq!(pages[0].lines[0].delta, vec2(100.0, 380.0));
+        // The footnote is placed at the bottom margin, below the separator.
+        assert_eq!(pages[0].lines[1].delta, vec2(0.0, -278.0));
+        assert_eq!(
+            pages[0].rules,
+            vec![Rule {
+                start: vec2(100.0, 116.0),
+                end: vec2(172.0, 116.0),
+                width: 0.5,
+            }],
+        );
+    }
+
+    #[test]
+    fn test_footnote_continues() {
+        let target_height = 100.0;
+        let document = Document {
+            columns: Default::default(),
+            ..column_test_document(false)
+        };
+
+        let sections = vec![Section {
+            items: vec![Item::Line(footnote_test_line(8))],
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].lines.len(), 7);
+        assert_eq!(pages[1].lines.len(), 2);
+        assert_eq!(pages[1].lines[0].delta, vec2(100.0, 112.0));
+        assert_eq!(pages[1].rules.len(), 1);
+    }
+
+    #[test]
+    fn test_footnote_moves_line() {
+        let target_height = 100.0;
+        let document = Document {
+            columns: Default::default(),
+            ..column_test_document(false)
+        };
+
+        let mut items = column_test_items(2);
+        items.push(Item::Line(footnote_test_line(4)));
+        let sections = vec![Section { items, span: false }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        // The last line would fit, but its footnote would not.
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].lines.len(), 2);
+        assert!(pages[0].rules.is_empty());
+        assert_eq!(pages[1].lines.len(), 5);
+    }
+
+    #[test]
+    fn test_layout_lines() {
+        let target_width = 49.0;
+
+        let word = Chunk {
+            width: 20.0,
+            is_whitespace: false,
+            ..Chunk::default()
+        };
+        let space = Chunk {
+            width: 5.0,
+            is_whitespace: true,
+            ..Chunk::default()
+        };
+        let chunks = vec![
+            word.clone(),
+            space.clone(),
+            word.clone(),
+            space.clone(),
+            word.clone(),
+        ];
+
+        let lines = layout_lines(target_width, chunks);
+
+        assert_eq!(lines.len(), 2);
+
+        assert_eq!(lines[0].chunks.len(), 3);
+        assert_eq!(lines[0].chunks[0], word);
+        assert_eq!(lines[0].chunks[1], space);
+        assert_eq!(lines[0].chunks[2], word);
+
+        assert_eq!(lines[1].chunks.len(), 1);
+        assert_eq!(lines[1].chunks[0], word);
+    }
+
+    #[test]
+    fn test_layout_lines_at_break_opportunities() {
+        let target_width = 49.0;
+
+        // Ideographs can be broken between, but the last one can't be separated from the
+        // punctuation after it.
+        let ideograph = Chunk {
+            width: 10.0,
+            line_break: Some(BreakOpportunity::Allowed),
+            ..Chunk::default()
+        };
+        let last_ideograph = Chunk {
+            line_break: None,
+            ..ideograph.clone()
+        };
+        let punctuation = Chunk {
+            width: 5.0,
+            ..Chunk::default()
+        };
+        let mut chunks = vec![ideograph.clone(); 4];
+        chunks.extend([last_ideograph.clone(), punctuation.clone()]);
+
+        let lines = layout_lines(target_width, chunks);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].chunks.len(), 4);
+        assert_eq!(lines[0].text_total_width, 40.0);
+        assert_eq!(lines[1].chunks, vec![last_ideograph, punctuation]);
+        assert_eq!(lines[1].text_total_width, 15.0);
+    }
+
+    #[test]
+    fn test_layout_lines_mandatory_break() {
+        let word = Chunk {
+            width: 20.0,
+            ..Chunk::default()
+        };
+        let newline = Chunk {
+            width: 5.0,
+            is_whitespace: true,
+            line_break: Some(BreakOpportunity::Mandatory),
+            ..Chunk::default()
+        };
+
+        let lines = layout_lines(100.0, vec![word.clone(), newline, word.clone()]);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].chunks, vec![word.clone()]);
+        assert_eq!(lines[1].chunks, vec![word]);
+    }
+
+    #[test]
+    fn test_align_left() {
+        let block = TextBlock {
+            inlines: Default::default(),
+            align: TextAlign::Left,
+        };
+        let target_width = 49.0;
+
+        let word = Chunk {
+            width: 20.0,
+            is_whitespace: false,
+            ..Chunk::default()
+        };
+        let space = Chunk {
+            width: 5.0,
+            is_whitespace: true,
+            ..Chunk::default()
+        };
+        let mut lines = vec![
+            Line {
+                chunks: vec![word.clone(), space.clone(), word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 45.0,
+                delta: Vec2::ZERO,
+            },
+            Line {
+                chunks: vec![word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 20.0,
+                delta: Vec2::ZERO,
+            },
+        ];
+
+        align_lines(&block.align, false, target_width, &mut lines);
+
+        assert_eq!(lines[0].chunks[0].left_adjust, 0.0);
+        assert_eq!(lines[0].chunks[1].left_adjust, 0.0);
+        assert_eq!(lines[0].chunks[2].left_adjust, 0.0);
+        assert_eq!(lines[1].chunks[0].left_adjust, 0.0);
+    }
+
+    #[test]
+    fn test_align_center() {
+        let block = TextBlock {
+            inlines: Default::default(),
+            align: TextAlign::Center,
+        };
+        let target_width = 49.0;
+
+        let word = Chunk {
+            width: 20.0,
+            is_whitespace: false,
+            ..Chunk::default()
+        };
+        let space = Chunk {
+            width: 5.0,
+            is_whitespace: true,
+            ..Chunk::default()
+        };
+        let mut lines = vec![
+            Line {
+                chunks: vec![word.clone(), space.clone(), word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 45.0,
+                delta: Vec2::ZERO,
+            },
+            Line {
+                chunks: vec![word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 20.0,
+                delta: Vec2::ZERO,
+            },
+        ];
+
+        align_lines(&block.align, false, target_width, &mut lines);
+
+        assert_eq!(lines[0].chunks[0].left_adjust, -2.0);
+        assert_eq!(lines[0].chunks[1].left_adjust, 0.0);
+        assert_eq!(lines[0].chunks[2].left_adjust, 0.0);
+        assert_eq!(lines[1].chunks[0].left_adjust, -14.5);
+    }
+
+    #[test]
+    fn test_align_right() {
+        let block = TextBlock {
+            inlines: Default::default(),
+            align: TextAlign::Right,
+        };
+        let target_width = 49.0;
+
+        let word = Chunk {
+            width: 20.0,
+            is_whitespace: false,
+            ..Chunk::default()
+        };
+        let space = Chunk {
+            width: 5.0,
+            is_whitespace: true,
+            ..Chunk::default()
+        };
+        let mut lines = vec![
+            Line {
+                chunks: vec![word.clone(), space.clone(), word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 45.0,
+                delta: Vec2::ZERO,
+            },
+            Line {
+                chunks: vec![word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 20.0,
+                delta: Vec2::ZERO,
+            },
+        ];
+
+        align_lines(&block.align, false, target_width, &mut lines);
+
+        assert_eq!(lines[0].chunks[0].left_adjust, -4.0);
+        assert_eq!(lines[0].chunks[1].left_adjust, 0.0);
+        assert_eq!(lines[0].chunks[2].left_adjust, 0.0);
+        assert_eq!(lines[1].chunks[0].left_adjust, -29.0);
+    }
+
+    #[test]
+    fn test_align_justify() {
+        let block = TextBlock {
+            inlines: Default::default(),
+            align: TextAlign::Justify,
+        };
+        let target_width = 49.0;
+
+        let word = Chunk {
+            width: 20.0,
+            is_whitespace: false,
+            ..Chunk::default()
+        };
+        let space = Chunk {
+            width: 5.0,
+            is_whitespace: true,
+            ..Chunk::default()
+        };
+        let mut lines = vec![
+            Line {
+                chunks: vec![word.clone(), space.clone(), word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 45.0,
+                delta: Vec2::ZERO,
+            },
+            Line {
+                chunks: vec![word.clone()],
+                text_metrics: Default::default(),
+                text_total_width: 20.0,
+                delta: Vec2::ZERO,
+            },
+        ];
+
+        align_lines(&block.align, false, target_width, &mut lines);
+
+        assert_eq!(lines[0].chunks[0].left_adjust, 0.0);
+        assert_eq!(lines[0].chunks[1].left_adjust, -2.0);
+        assert_eq!(lines[0].chunks[2].left_adjust, -2.0);
+        assert_eq!(lines[1].chunks[0].left_adjust, 0.0);
+    }
+
+    #[test]
+    fn text_chunk_inline() {
+        let mut fonts = BTreeMap::new();
+        let font = Font::default();
+        fonts.insert(&*font.ps_name, &font);
+
+        let inline = TextInline {
+            text: "Lorem ipsum dolor sit amet.",
+            style: Style {
+                font: &font.ps_name,
+                font_size: 12.0,
+                fallbacks: &[],
+                synthetic_bold: false,
+                synthetic_oblique: false,
+            },
+        };
+
+        let chunks = chunk_inline(&fonts, &inline).unwrap();
+
+        assert_eq!(chunks.len(), 9);
+
+        assert_eq!(chunks[0].text, "Lorem");
+        assert_eq!(chunks[0].width, 37.816406);
+        assert!(!chunks[0].is_whitespace);
+
+        assert_eq!(chunks[1].text, " ");
+        assert_eq!(chunks[1].width, 3.1171875);
+        assert!(chunks[1].is_whitespace);
+
+        assert_eq!(chunks[2].text, "ipsum");
+        assert_eq!(chunks[2].width, 35.572266);
+        assert!(!chunks[2].is_whitespace);
+    }
+
+    #[test]
+    fn test_chunk_synthetic_bold() {
+        let mut fonts = BTreeMap::new();
+        let font = Font::default();
+        fonts.insert(&*font.ps_name, &font);
+
+        let style = Style {
+            font: &font.ps_name,
+            font_size: 12.0,
+            ..Default::default()
+        };
+        let bold_style = Style {
+            synthetic_bold: true,
+            ..style
+        };
+        let text = "Lorem ipsum";
+        let chunks = chunk_inline(&fonts, &TextInline { text, style }).unwrap();
+        let bold_chunks = chunk_inline(
+            &fonts,
+            &TextInline {
+                text,
+                style: bold_style,
+            },
+        )
+        .unwrap();
+
+        // Each glyph is widened by the stroke around it.
+        let stroke_width = bold_style.bold_stroke_width();
+        assert_eq!(stroke_width, 0.35999998);
+        for (chunk, bold_chunk) in chunks.iter().zip(&bold_chunks) {
+            let extra_width = chunk.text.chars().count() as f32 * stroke_width;
+            assert!((bold_chunk.width - chunk.width - extra_width).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_chunk_fallback() {
+        let font = Font::default();
+        let math_font = Font::default_math();
+        let mut fonts = BTreeMap::new();
+        fonts.insert(&*font.ps_name, &font);
+        fonts.insert(&*math_font.ps_name, &math_font);
+
+        // Each character is taken from the first font that has it, and the text is split where
+        // the font changes.
+        let fallbacks = [&*math_font.ps_name];
+        let style = Style {
+            font: &font.ps_name,
+            font_size: 12.0,
+            fallbacks: &fallbacks,
+            ..Default::default()
+        };
+        let text = "x \u{1d49c}\u{1d4b3}z";
+        let chunks = chunk_inline(&fonts, &TextInline { text, style }).unwrap();
+        let runs = chunks
+            .iter()
+            .map(|chunk| (&*chunk.text, chunk.style.font))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            runs,
+            [
+                ("x", &*font.ps_name),
+                (" ", &*font.ps_name),
+                ("\u{1d49c}\u{1d4b3}", &*math_font.ps_name),
+                ("z", &*font.ps_name),
+            ]
+        );
+
+        // The fallback's run is measured with the fallback.
+        let advance = |c| {
+            let glyph_id = math_font.face.glyph_index(c).unwrap();
+            let advance = math_font.glyph_advance(glyph_id).unwrap() as f32;
+            advance * style.font_size / math_font.face.units_per_em() as f32
+        };
+        assert_eq!(chunks[2].width, advance('\u{1d49c}') + advance('\u{1d4b3}'));
+        assert_eq!(
+            chunks[2].text_metrics,
+            math_font.metrics() * style.font_size
+        );
+
+        // The main font comes first even if a fallback has the character too.
+        let fallbacks = [&*font.ps_name];
+        let style = Style {
+            font: &math_font.ps_name,
+            fallbacks: &fallbacks,
+            ..style
+        };
+        let chunks = chunk_inline(&fonts, &TextInline { text: "x", style }).unwrap();
+        assert_eq!(chunks[0].style.font, math_font.ps_name);
+
+        assert!(matches!(
+            chunk_inline(
+                &fonts,
+                &TextInline {
+                    text: "\u{5b57}",
+                    style
+                }
+            ),
+            Err(Error::MissingGlyph('\u{5b57}'))
+        ));
+    }
+}
@@ -0,0 +1,472 @@
+use glam::{vec2, Vec2};
+
+use crate::document::{ColumnWidth, Table};
+
+use super::{
+    chunk_inlines, footnote_lines, layout_paragraph, line_height, Chunk, Context, Error, Line,
+    Page, Rule, PARAGRAPH_GAP,
+};
+
+/// A row of table cells, which is placed on the page as a unit.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Row<'a> {
+    pub(super) cells: Vec<Cell<'a>>,
+    /// The height of the row, including the padding.
+    pub(super) height: f32,
+    /// The space above the row, unless it starts a page.
+    pub(super) gap: f32,
+    pub(super) padding: f32,
+    pub(super) border_width: f32,
+    /// The header rows to repeat when this row continues in a new column or page.
+    pub(super) header: Vec<Row<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Cell<'a> {
+    pub(super) lines: Vec<Line<'a>>,
+    /// The offset of the cell from the left edge of the column.
+    pub(super) x: f32,
+    pub(super) width: f32,
+}
+
+pub(super) fn layout_table<'a>(
+    context: &Context<'a, '_>,
+    target_width: f32,
+    table: &Table<'a>,
+) -> Result<Vec<Row<'a>>, Error> {
+    for (i, row) in table.rows.iter().enumerate() {
+        if row.len() > table.columns.len() {
+            return Err(Error::TooManyCells(i + 1, row.len(), table.columns.len()));
+        }
+    }
+
+    let chunks = table
+        .rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| chunk_inlines(context, target_width, &cell.inlines, 1.0))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let widths = column_widths(table, target_width, &chunks);
+
+    let mut rows = Vec::new();
+    let mut header = Vec::new();
+    for (i, (row, row_chunks)) in table.rows.iter().zip(chunks).enumerate() {
+        let mut cells = Vec::new();
+        let mut x = 0.0;
+        for ((block, chunks), &width) in row.iter().zip(row_chunks).zip(&widths) {
+            let inner_width = (width - 2.0 * table.padding).max(0.0);
+            let lines = layout_paragraph(context.fonts, inner_width, chunks, &block.align)?;
+
+            cells.push(Cell { lines, x, width });
+            x += width;
+        }
+
+        let mut row = Row::new(cells, table.padding, table.border_width);
+        if i == 0 {
+            row.gap = PARAGRAPH_GAP;
+        }
+        if i < table.header_rows {
+            header.push(row.clone());
+        } else {
+            row.header = header.clone();
+        }
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn column_widths(table: &Table, target_width: f32, chunks: &[Vec<Vec<Chunk>>]) -> Vec<f32> {
+    let mut widths = table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| match column {
+            ColumnWidth::Fixed(width) => *width,
+            ColumnWidth::Proportional(_) => 0.0,
+            ColumnWidth::Auto => natural_width(chunks, i) + 2.0 * table.padding,
+        })
+        .collect::<Vec<_>>();
+
+    // Shrink the automatic columns if they do not fit next to the fixed columns.
+    let fixed_width = table
+        .columns
+        .iter()
+        .map(|column| match column {
+            ColumnWidth::Fixed(width) => *width,
+            _ => 0.0,
+        })
+        .sum::<f32>();
+    let auto_width = widths
+        .iter()
+        .zip(&table.columns)
+        .filter(|(_, column)| **column == ColumnWidth::Auto)
+        .map(|(width, _)| width)
+        .sum::<f32>();
+    let auto_space = (target_width - fixed_width).max(0.0);
+    if auto_width > auto_space {
+        let scale = auto_space / auto_width;
+        for (width, column) in widths.iter_mut().zip(&table.columns) {
+            if *column == ColumnWidth::Auto {
+                *width *= scale;
+            }
+        }
+    }
+
+    // Share the remaining width between the proportional columns.
+    let remaining_width = (target_width - widths.iter().sum::<f32>()).max(0.0);
+    let total_weight = table
+        .columns
+        .iter()
+        .map(|column| match column {
+            ColumnWidth::Proportional(weight) => *weight,
+            _ => 0.0,
+        })
+        .sum::<f32>();
+    if total_weight > 0.0 {
+        for (width, column) in widths.iter_mut().zip(&table.columns) {
+            if let ColumnWidth::Proportional(weight) = column {
+                *width = remaining_width * weight / total_weight;
+            }
+        }
+    }
+
+    widths
+}
+
+/// The width of the widest cell in a column, if none of its lines were broken.
+fn natural_width(chunks: &[Vec<Vec<Chunk>>], column: usize) -> f32 {
+    chunks
+        .iter()
+        .filter_map(|row| row.get(column))
+        .map(|cell| cell.iter().map(|chunk| chunk.width).sum::<f32>())
+        .fold(0.0, f32::max)
+}
+
+fn cell_height(lines: &[Line]) -> f32 {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| line_height(line, i == 0))
+        .sum()
+}
+
+impl<'a> Row<'a> {
+    fn new(cells: Vec<Cell<'a>>, padding: f32, border_width: f32) -> Self {
+        let content_height = cells
+            .iter()
+            .map(|cell| cell_height(&cell.lines))
+            .fold(0.0, f32::max);
+        Self {
+            cells,
+            height: content_height + 2.0 * padding,
+            gap: 0.0,
+            padding,
+            border_width,
+            header: Vec::new(),
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.cells.iter().all(|cell| cell.lines.is_empty())
+    }
+
+    pub(super) fn header_height(&self) -> f32 {
+        self.header.iter().map(|row| row.height).sum()
+    }
+
+    pub(super) fn footnote_lines(&self) -> Vec<Line<'a>> {
+        self.cells
+            .iter()
+            .flat_map(|cell| &cell.lines)
+            .flat_map(footnote_lines)
+            .collect()
+    }
+
+    /// Splits the row so that the first part is at most `height` tall.
+    ///
+    /// If `force` is set, the first part takes at least one line from each cell, even if it does
+    /// not fit.
+    pub(super) fn split(self, height: f32, force: bool) -> (Self, Self) {
+        let content_height = height - 2.0 * self.padding;
+
+        let mut first_cells = Vec::new();
+        let mut rest_cells = Vec::new();
+        for mut cell in self.cells {
+            let mut used_height = 0.0;
+            let mut count = 0;
+            for (i, line) in cell.lines.iter().enumerate() {
+                let line_height = line_height(line, i == 0);
+                if used_height + line_height > content_height && !(force && i == 0) {
+                    break;
+                }
+                used_height += line_height;
+                count += 1;
+            }
+
+            rest_cells.push(Cell {
+                lines: cell.lines.split_off(count),
+                x: cell.x,
+                width: cell.width,
+            });
+            first_cells.push(cell);
+        }
+
+        let mut first = Self::new(first_cells, self.padding, self.border_width);
+        first.gap = self.gap;
+        first.header = self.header.clone();
+        let mut rest = Self::new(rest_cells, self.padding, self.border_width);
+        rest.header = self.header;
+
+        (first, rest)
+    }
+
+    /// Moves the cells to the right, such as when the table is in a list item.
+    pub(super) fn indent(&mut self, indent: f32) {
+        for cell in &mut self.cells {
+            cell.x += indent;
+        }
+        for row in &mut self.header {
+            row.indent(indent);
+        }
+    }
+
+    /// Positions the cells below `top_left` and draws the borders of the row.
+    pub(super) fn place(self, top_left: Vec2, position: &mut Vec2, page: &mut Page<'a>) {
+        let left = self.cells.first().map_or(0.0, |cell| cell.x);
+        let right = self.cells.last().map_or(0.0, |cell| cell.x + cell.width);
+        let bottom = top_left.y - self.height;
+
+        if self.border_width > 0.0 {
+            for y in [top_left.y, bottom] {
+                page.rules.push(Rule {
+                    start: vec2(top_left.x + left, y),
+                    end: vec2(top_left.x + right, y),
+                    width: self.border_width,
+                });
+            }
+
+            let edges = self.cells.iter().map(|cell| cell.x).chain([right]);
+            for x in edges {
+                page.rules.push(Rule {
+                    start: vec2(top_left.x + x, top_left.y),
+                    end: vec2(top_left.x + x, bottom),
+                    width: self.border_width,
+                });
+            }
+        }
+
+        for cell in self.cells {
+            let x = top_left.x + cell.x + self.padding;
+            for (i, mut line) in cell.lines.into_iter().enumerate() {
+                if i == 0 {
+                    let target = vec2(x, top_left.y - self.padding - line.text_metrics.ascent);
+                    line.delta = target - *position;
+                }
+
+                *position += line.delta;
+                page.lines.push(line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec2;
+
+    use crate::{
+        document::{ColumnWidth, Document, Table, TextAlign, TextBlock},
+        font::TextMetrics,
+        text_layout::{
+            context::Context, layout_pages, tests::column_test_document, Chunk, Item, Line, Section,
+        },
+    };
+
+    use super::{column_widths, layout_table, Cell, Row};
+
+    fn test_table(columns: Vec<ColumnWidth>) -> Table<'static> {
+        Table {
+            columns,
+            rows: Vec::new(),
+            header_rows: 0,
+            padding: 5.0,
+            border_width: 0.0,
+        }
+    }
+
+    fn test_row(line_count: usize, text_total_width: f32) -> Row<'static> {
+        let line = Line {
+            chunks: vec![],
+            text_metrics: TextMetrics {
+                ascent: 8.0,
+                descent: -2.0,
+                line_gap: 0.0,
+                ..Default::default()
+            },
+            text_total_width,
+            delta: vec2(0.0, -10.0),
+        };
+        let cell = Cell {
+            lines: vec![line; line_count],
+            x: 0.0,
+            width: 100.0,
+        };
+        Row::new(vec![cell], 0.0, 0.0)
+    }
+
+    #[test]
+    fn test_column_widths() {
+        let table = test_table(vec![
+            ColumnWidth::Fixed(100.0),
+            ColumnWidth::Auto,
+            ColumnWidth::Proportional(1.0),
+            ColumnWidth::Proportional(3.0),
+        ]);
+        let chunk = |width| Chunk {
+            width,
+            ..Chunk::default()
+        };
+        let chunks = vec![vec![vec![], vec![chunk(20.0), chunk(30.0)], vec![], vec![]]];
+
+        let widths = column_widths(&table, 400.0, &chunks);
+        assert_eq!(widths, vec![100.0, 60.0, 60.0, 180.0]);
+
+        // The automatic column shrinks to fit next to the fixed column.
+        let widths = column_widths(&table, 130.0, &chunks);
+        assert_eq!(widths, vec![100.0, 30.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_too_many_cells() {
+        let document = column_test_document(false);
+        let context = Context::new(&document).unwrap();
+        let cell = || TextBlock {
+            inlines: Vec::new(),
+            align: TextAlign::Left,
+        };
+        let table = Table {
+            rows: vec![vec![cell()], vec![cell(), cell()]],
+            ..test_table(vec![ColumnWidth::Auto])
+        };
+
+        let error = layout_table(&context, 100.0, &table).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "table row 2 has 2 cells, more than the number of columns (1)"
+        );
+    }
+
+    #[test]
+    fn test_split_row() {
+        let row = Row {
+            padding: 5.0,
+            ..test_row(5, 0.0)
+        };
+
+        let (first, rest) = row.split(40.0, false);
+        assert_eq!(first.cells[0].lines.len(), 3);
+        assert_eq!(first.height, 40.0);
+        assert_eq!(rest.cells[0].lines.len(), 2);
+        assert_eq!(rest.height, 30.0);
+
+        // A forced split keeps at least one line.
+        let (first, rest) = test_row(2, 0.0).split(5.0, true);
+        assert_eq!(first.cells[0].lines.len(), 1);
+        assert_eq!(rest.cells[0].lines.len(), 1);
+    }
+
+    #[test]
+    fn test_repeat_header() {
+        let target_height = 100.0;
+        let document = Document {
+            columns: Default::default(),
+            ..column_test_document(false)
+        };
+
+        let header = test_row(2, 1.0);
+        let mut items = vec![Item::Row(header.clone())];
+        for _ in 0..4 {
+            let row = Row {
+                header: vec![header.clone()],
+                ..test_row(3, 0.0)
+            };
+            items.push(Item::Row(row));
+        }
+        let sections = vec![Section { items, span: false }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].lines.len(), 8);
+        assert_eq!(pages[1].lines.len(), 8);
+        assert_eq!(pages[1].lines[0].text_total_width, 1.0);
+        assert_eq!(pages[1].lines[0].delta, vec2(100.0, 392.0));
+    }
+
+    #[test]
+    fn test_tall_header() {
+        let target_height = 100.0;
+        let document = Document {
+            columns: Default::default(),
+            ..column_test_document(false)
+        };
+
+        // A header that takes most of the page isn't repeated.
+        let header = test_row(9, 1.0);
+        let row = Row {
+            header: vec![header.clone()],
+            ..test_row(12, 0.0)
+        };
+        let sections = vec![Section {
+            items: vec![Item::Row(header), Item::Row(row)],
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[0].lines.len(), 10);
+        assert_eq!(pages[1].lines.len(), 10);
+        assert_eq!(pages[1].lines[0].text_total_width, 0.0);
+        assert_eq!(pages[2].lines.len(), 1);
+
+        // A line that doesn't fit below the repeated header is placed anyway.
+        let header = test_row(4, 1.0);
+        let mut cells = test_row(2, 0.0).cells;
+        cells[0].lines[0].text_metrics.ascent = 70.0;
+        let row = Row {
+            header: vec![header.clone()],
+            ..Row::new(cells, 0.0, 0.0)
+        };
+        let sections = vec![Section {
+            items: vec![Item::Row(header), Item::Row(row)],
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[1].lines.len(), 5);
+        assert_eq!(pages[2].lines.len(), 5);
+    }
+
+    #[test]
+    fn test_split_tall_row() {
+        let target_height = 100.0;
+        let document = Document {
+            columns: Default::default(),
+            ..column_test_document(false)
+        };
+
+        let sections = vec![Section {
+            items: vec![Item::Row(test_row(12, 0.0))],
+            span: false,
+        }];
+        let pages = layout_pages(sections, target_height, &document);
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].lines.len(), 10);
+        assert_eq!(pages[1].lines.len(), 2);
+    }
+}
@@ -2,7 +2,7 @@ use std::{collections::BTreeMap, fs};
 
 use glam::vec2;
 use typeset::{
-    document::{Block, Document, Inline, Style, TextAlign, TextBlock},
+    document::{Block, Document, Inline, Style, TextAlign, TextBlock, TextInline},
     font::Font,
     generate_pdf,
     pdf::page::{PAGE_HEIGHT, PAGE_WIDTH},
@@ -26,38 +26,41 @@ fn test_lorem_ipsum() {
     let style = Style {
         font: &font.ps_name,
         font_size: 12.0,
+        fallbacks: &[],
+        synthetic_bold: false,
+        synthetic_oblique: false,
     };
     let mut blocks = vec![
         Block::Text(TextBlock {
-            inlines: vec![Inline {
+            inlines: vec![Inline::Text(TextInline {
                 style: Style {
                     font_size: 2.0 * style.font_size,
                     ..style
                 },
                 text: "Hello, World!",
-            }],
+            })],
             align: TextAlign::Left,
         }),
         Block::Text(TextBlock {
             inlines: vec![
-                Inline {
+                Inline::Text(TextInline {
                     style,
                     text: "Regular, ",
-                },
-                Inline {
+                }),
+                Inline::Text(TextInline {
                     style: Style {
                         font: &bold_font.ps_name,
                         ..style
                     },
                     text: "bold, ",
-                },
-                Inline {
+                }),
+                Inline::Text(TextInline {
                     style: Style {
                         font: &italic_font.ps_name,
                         ..style
                     },
                     text: "or italic?",
-                },
+                }),
             ],
             align: TextAlign::Left,
         }),
@@ -70,7 +73,7 @@ fn test_lorem_ipsum() {
         TextAlign::Justify,
     ]) {
         blocks.push(Block::Text(TextBlock {
-            inlines: vec![Inline { style, text: line }],
+            inlines: vec![Inline::Text(TextInline { style, text: line })],
             align,
         }));
     }
@@ -85,9 +88,10 @@ fn test_lorem_ipsum() {
         fonts,
         page_size: vec2(PAGE_WIDTH, PAGE_HEIGHT),
         margin: 72.0,
+        columns: Default::default(),
     };
 
-    let content = generate_pdf(document);
+    let content = generate_pdf(document).unwrap();
 
     fs::create_dir_all("output").unwrap();
     fs::write("output/lorem_ipsum.pdf", &content).unwrap();
@@ -106,14 +110,17 @@ fn test_greek() {
     let style = Style {
         font: &font.ps_name,
         font_size: 12.0,
+        fallbacks: &[],
+        synthetic_bold: false,
+        synthetic_oblique: false,
     };
     let blocks = vec![
         Block::Text(TextBlock {
-            inlines: vec![Inline { style, text }],
+            inlines: vec![Inline::Text(TextInline { style, text })],
             align: TextAlign::Left,
         }),
         Block::Text(TextBlock {
-            inlines: vec![Inline { style, text: text2 }],
+            inlines: vec![Inline::Text(TextInline { style, text: text2 })],
             align: TextAlign::Left,
         }),
     ];
@@ -126,9 +133,10 @@ fn test_greek() {
         fonts,
         page_size: vec2(PAGE_WIDTH, PAGE_HEIGHT),
         margin: 72.0,
+        columns: Default::default(),
     };
 
-    let content = generate_pdf(document);
+    let content = generate_pdf(document).unwrap();
 
     fs::create_dir_all("output").unwrap();
     fs::write("output/greek.pdf", &content).unwrap();